@@ -0,0 +1,107 @@
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// The URL and file name safe alphabet (RFC 4648 §5).
+const URL_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn sextet(alphabet: &[u8; 64], symbol: u8) -> Option<u32> {
+    alphabet.iter().position(|&candidate| candidate == symbol).map(|index| index as u32)
+}
+
+fn decode_with(alphabet: &[u8; 64], input: &str) -> Option<Vec<u8>> {
+    let symbols = input.trim_end_matches('=').as_bytes();
+    if symbols.len() % 4 == 1 || input.len() - symbols.len() > 2 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(symbols.len() * 3 / 4);
+    for group in symbols.chunks(4) {
+        let mut bits = 0;
+        for (index, &symbol) in group.iter().enumerate() {
+            bits |= sextet(alphabet, symbol)? << (18 - 6 * index);
+        }
+        let bytes = bits.to_be_bytes();
+        decoded.extend_from_slice(&bytes[1..group.len()]);
+    }
+    Some(decoded)
+}
+
+/// Decodes standard base64 (RFC 4648 §4). Padding is optional; `None` for anything malformed.
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    decode_with(ALPHABET, input)
+}
+
+/// Decodes base64url, with or without padding; `None` for anything malformed.
+pub fn decode_url(input: &str) -> Option<Vec<u8>> {
+    decode_with(URL_ALPHABET, input)
+}
+
+/// Encodes as base64url without padding, which fits into cookies and file names as is.
+pub fn encode_url(input: &[u8]) -> String {
+    let mut encoded = String::with_capacity(input.len().div_ceil(3) * 4);
+    for group in input.chunks(3) {
+        let mut bytes = [0u8; 4];
+        bytes[1..=group.len()].copy_from_slice(group);
+        let bits = u32::from_be_bytes(bytes);
+        for index in 0..=group.len() {
+            encoded.push(URL_ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// The test vectors of RFC 4648 section 10.
+    const VECTORS: [(&str, &str); 7] = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+
+    #[test]
+    fn decodes_the_rfc_4648_vectors() {
+        for (decoded, encoded) in VECTORS {
+            assert_eq!(decode(encoded), Some(decoded.as_bytes().to_vec()), "{encoded}");
+            assert_eq!(decode(encoded.trim_end_matches('=')), Some(decoded.as_bytes().to_vec()), "{encoded}");
+            assert_eq!(decode_url(encoded), Some(decoded.as_bytes().to_vec()), "{encoded}");
+        }
+    }
+
+    #[test]
+    fn encodes_the_rfc_4648_vectors_without_padding() {
+        for (decoded, encoded) in VECTORS {
+            assert_eq!(encode_url(decoded.as_bytes()), encoded.trim_end_matches('='));
+        }
+    }
+
+    #[test]
+    fn uses_the_alphabet_of_each_variant() {
+        let bytes = [0xfb, 0xff, 0xbf];
+        assert_eq!(encode_url(&bytes), "-_-_");
+        assert_eq!(decode_url("-_-_"), Some(bytes.to_vec()));
+        assert_eq!(decode("+/+/"), Some(bytes.to_vec()));
+        assert_eq!(decode("-_-_"), None);
+        assert_eq!(decode_url("+/+/"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for input in ["Z", "Zm9vY", "Zg===", "Zm 9v", "Zm9v!", "=Zm9"] {
+            assert_eq!(decode(input), None, "{input}");
+        }
+    }
+
+    #[test]
+    fn round_trips_every_byte() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for length in 0..=bytes.len() {
+            assert_eq!(decode_url(&encode_url(&bytes[..length])), Some(bytes[..length].to_vec()));
+        }
+    }
+}
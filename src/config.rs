@@ -1,15 +1,179 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::http::compression::Level;
+use crate::http::cookie::SameSite;
+
+/// How the files routes treat symbolic links below `files_path`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Any symlink on the way to the requested file is rejected.
+    Deny,
+    /// Symlinks are followed as long as their target stays inside `files_path`.
+    WithinRoot,
+    /// Symlinks are followed wherever they point.
+    Follow,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deny" => Ok(SymlinkPolicy::Deny),
+            "within-root" => Ok(SymlinkPolicy::WithinRoot),
+            "follow" => Ok(SymlinkPolicy::Follow),
+            _ => Err(format!("Unknown symlink policy `{s}`, expected one of: deny, within-root, follow"))
+        }
+    }
+}
+
+/// How `ETag`s of served files are computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ETagMode {
+    /// Weak tag from inode, size and modification time.
+    Weak,
+    /// Strong tag from inode, size and modification time.
+    Strong,
+    /// Strong tag from a SHA-256 of the content. Reads the whole file on every request.
+    ContentHash,
+}
+
+impl FromStr for ETagMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weak" => Ok(ETagMode::Weak),
+            "strong" => Ok(ETagMode::Strong),
+            "hash" => Ok(ETagMode::ContentHash),
+            _ => Err(format!("Unknown ETag mode `{s}`, expected one of: weak, strong, hash"))
+        }
+    }
+}
+
+/// Where session data is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionStore {
+    /// In the signed cookie itself, limiting sessions to what fits into about 4 KiB.
+    Cookie,
+    /// In server memory keyed by the session id, lost on restart.
+    Memory,
+    /// In one file per session in the given directory.
+    File(PathBuf),
+}
+
+impl FromStr for SessionStore {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cookie" => Ok(SessionStore::Cookie),
+            "memory" => Ok(SessionStore::Memory),
+            _ => match s.strip_prefix("file:") {
+                Some(directory) if !directory.is_empty() => Ok(SessionStore::File(PathBuf::from(directory))),
+                _ => Err(format!("Unknown session store `{s}`, expected one of: cookie, memory, file:<directory>"))
+            }
+        }
+    }
+}
+
 pub struct Config {
     pub files_path: Option<String>,
     pub address: String,
     pub port: i32,
+    /// Value of the `Server` header added to every response, `None` leaves it out.
+    pub server_name: Option<String>,
+    pub symlink_policy: SymlinkPolicy,
+    /// Extra extension to media type mappings for served files, e.g. `"log" => "text/plain"`.
+    /// Takes precedence over the built-in table.
+    pub mime_types: HashMap<String, String>,
+    /// Guess the media type of files without a known extension from their first bytes.
+    pub sniff_mime_types: bool,
+    /// Answer requests for directories under `files_path` with a listing of their entries.
+    pub directory_listing: bool,
+    /// Include entries starting with a dot in directory listings.
+    pub list_hidden_files: bool,
+    /// Document root served at `/` in static site mode, `None` disables the mode.
+    pub site_root: Option<String>,
+    /// Page below `site_root` sent with 404 responses of the static site, e.g. `404.html`.
+    pub not_found_page: Option<String>,
+    /// Answer unknown non-asset paths of the static site with its `index.html`.
+    pub spa_fallback: bool,
+    pub etag_mode: ETagMode,
+    /// Answer the WebDAV methods on the files routes, so `files_path` can be mounted as a share.
+    pub webdav: bool,
+    /// How long a resumable upload may go without a `PATCH` before it is thrown away.
+    pub upload_expiry: Duration,
+    /// Where unfinished resumable uploads are kept. It must lie outside `files_path`, where
+    /// clients could tamper with them; on the same filesystem finished uploads are linked
+    /// into place instead of copied.
+    pub uploads_path: PathBuf,
+    /// Largest file a `POST` or `PUT` to the files routes may store, in bytes.
+    pub max_upload_size: u64,
+    /// Largest single part of a `multipart/form-data` upload, in bytes.
+    pub max_form_part_size: u64,
+    /// Largest form body in bytes, summed over all parts.
+    pub max_form_size: u64,
+    /// Largest `application/x-www-form-urlencoded`, JSON or WebDAV XML body in bytes. Unlike
+    /// uploads these are read into memory whole.
+    pub max_buffered_body_size: u64,
+    /// Where sessions are kept, `None` disables them.
+    pub session_store: Option<SessionStore>,
+    /// Secrets session cookies are signed with. The first one signs, all of them verify, so
+    /// a new key can be put in front of the old one without ending running sessions.
+    pub session_keys: Vec<Vec<u8>>,
+    /// Sessions end this long after they were last changed.
+    pub session_max_age: Duration,
+    pub session_cookie_name: String,
+    /// `Domain` of the session cookie, `None` limits it to the host that set it.
+    pub session_cookie_domain: Option<String>,
+    pub session_same_site: SameSite,
+    /// Mark the session cookie `Secure`, for servers behind a TLS terminating proxy.
+    pub session_secure_cookie: bool,
+    /// Level of gzip and deflate content codings.
+    pub compression_level: Level,
+    /// Serve the server's counters at `/metrics`.
+    pub metrics: bool,
 }
 
-impl<'a> Config {
+impl Config {
+    pub const DEFAULT_SERVER_NAME: &'static str = "codecrafters-http-server";
+
     pub fn new(address: &str, port: i32, files_path: Option<String>) -> Self {
         Self {
             address: address.to_string(),
             port,
             files_path,
+            server_name: Some(Config::DEFAULT_SERVER_NAME.to_string()),
+            symlink_policy: SymlinkPolicy::WithinRoot,
+            mime_types: HashMap::new(),
+            sniff_mime_types: true,
+            directory_listing: false,
+            list_hidden_files: false,
+            site_root: None,
+            not_found_page: None,
+            spa_fallback: false,
+            etag_mode: ETagMode::Strong,
+            webdav: false,
+            upload_expiry: Duration::from_secs(24 * 60 * 60),
+            uploads_path: env::temp_dir().join("http-server-uploads"),
+            max_upload_size: 1024 * 1024 * 1024,
+            max_form_part_size: 32 * 1024 * 1024,
+            max_form_size: 64 * 1024 * 1024,
+            max_buffered_body_size: 1024 * 1024,
+            session_store: None,
+            session_keys: Vec::new(),
+            session_max_age: Duration::from_secs(24 * 60 * 60),
+            session_cookie_name: "session".to_string(),
+            session_cookie_domain: None,
+            session_same_site: SameSite::Lax,
+            session_secure_cookie: false,
+            compression_level: Level::DEFAULT,
+            metrics: false,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,97 @@
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::files;
+use crate::files::upload::{self, WriteMode};
+use crate::http::multipart::Multipart;
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+use crate::http::url::percent_encode_segment;
+use crate::webdav;
+
+/// The last component of a client supplied file name; some browsers send whole paths.
+/// Hidden and special names are refused.
+fn sanitize_filename(filename: &str) -> Option<&str> {
+    let name = filename.rsplit(['/', '\\']).next()?.trim();
+    if name.is_empty() || name.starts_with('.') || name.chars().any(char::is_control) {
+        return None;
+    }
+    Some(name)
+}
+
+/// Stores the file parts of a `multipart/form-data` request in `directory`, each under its
+/// sanitized file name, replacing existing files. Parts are streamed to disk one at a time,
+/// limited by `Config.max_form_part_size` and `Config.max_form_size`. Other fields are
+/// read and ignored. Files stored before a failing part are kept.
+pub fn store_parts(request: &Request, directory: &Path, mut form: Multipart<impl Read>, config: &Config) -> Response {
+    if !directory.is_dir() {
+        return Response::new(HTTPStatus::Conflict).text("Form uploads go to a directory");
+    }
+    let limit_response = || {
+        Response::new(HTTPStatus::ContentTooLarge).text(format!(
+            "Form uploads are limited to {part} bytes per part and {total} bytes in total",
+            part = config.max_form_part_size,
+            total = config.max_form_size
+        ))
+    };
+    // Checked before anything is read from the connection
+    if request.content_length() > config.max_form_size {
+        return limit_response();
+    }
+    let malformed = |error: io::Error| Response::new(HTTPStatus::BadRequest).text(format!("Malformed form data: {error}"));
+
+    let mut base_path = request.path().to_string();
+    if !base_path.ends_with('/') {
+        base_path.push('/');
+    }
+    let mut total = 0;
+    let mut stored = Vec::new();
+    loop {
+        let part = match form.next_part() {
+            Ok(Some(part)) => part,
+            Ok(None) => break,
+            Err(error) => return malformed(error)
+        };
+        let limit = config.max_form_part_size.min(config.max_form_size - total);
+        let Some(filename) = part.filename.filter(|filename| !filename.is_empty()) else {
+            // A plain field, or a file input left empty
+            match io::copy(&mut (&mut form).take(limit + 1), &mut io::sink()) {
+                Ok(length) if length > limit => return limit_response(),
+                Ok(length) => total += length,
+                Err(error) => return malformed(error)
+            }
+            continue;
+        };
+        let Some(name) = sanitize_filename(&filename) else {
+            return Response::new(HTTPStatus::BadRequest).text(format!("Unusable file name `{filename}`"));
+        };
+        let destination = directory.join(name);
+        if let Err(response) = webdav::check_locks(request, &destination, false) {
+            return response;
+        }
+
+        let mut length = 0;
+        let result = upload::write_atomically(&destination, WriteMode::Replace, false, |file| {
+            length = io::copy(&mut (&mut form).take(limit + 1), file)?;
+            match length > limit {
+                true => Err(io::Error::other("Form part exceeds the size limit")),
+                false => Ok(())
+            }
+        });
+        match result {
+            Ok(_) => {}
+            Err(_) if length > limit => return limit_response(),
+            Err(error) if error.kind() == ErrorKind::InvalidData || error.kind() == ErrorKind::UnexpectedEof => return malformed(error),
+            Err(error) => return files::write_error_response(&error, &destination)
+        }
+        total += length;
+        stored.push(format!("{base_path}{} ({length} bytes)", percent_encode_segment(name)));
+    }
+
+    match stored.is_empty() {
+        true => Response::new(HTTPStatus::BadRequest).text("The form contains no files"),
+        false => Response::new(HTTPStatus::Created).text(format!("Stored {count} file(s):\n{list}\n", count = stored.len(), list = stored.join("\n")))
+    }
+}
@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::http::date::format_http_date;
+use crate::http::url::percent_encode_segment;
+use crate::json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+impl EntryType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EntryType::File => "file",
+            EntryType::Directory => "directory",
+            EntryType::Symlink => "symlink",
+            EntryType::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub name: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub entry_type: EntryType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    /// Parses the `sort` query parameter.
+    pub fn from_query(value: Option<&str>) -> Self {
+        match value {
+            Some("size") => SortKey::Size,
+            Some("mtime") => SortKey::Modified,
+            _ => SortKey::Name
+        }
+    }
+}
+
+/// Entries of `directory`; names that aren't valid UTF-8 are skipped.
+pub fn read_entries(directory: &Path, include_hidden: bool) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for dir_entry in fs::read_dir(directory)? {
+        let dir_entry = dir_entry?;
+        let name = match dir_entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue
+        };
+        if !include_hidden && name.starts_with('.') {
+            continue;
+        }
+        let metadata = dir_entry.metadata()?;
+        let file_type = metadata.file_type();
+        let entry_type = if file_type.is_symlink() {
+            EntryType::Symlink
+        } else if file_type.is_dir() {
+            EntryType::Directory
+        } else if file_type.is_file() {
+            EntryType::File
+        } else {
+            EntryType::Other
+        };
+        entries.push(Entry { name, size: metadata.len(), modified: metadata.modified().ok(), entry_type });
+    }
+    Ok(entries)
+}
+
+pub fn sort_entries(entries: &mut [Entry], key: SortKey, descending: bool) {
+    entries.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+        }.then_with(|| a.name.cmp(&b.name));
+        if descending { ordering.reverse() } else { ordering }
+    });
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// `base_path` is the request path of the directory and ends with a `/`.
+pub fn render_html(base_path: &str, entries: &[Entry]) -> String {
+    let title = escape_html(base_path);
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n<body>\n<h1>Index of {title}</h1>\n<table>\n");
+    html.push_str("<tr><th><a href=\"?sort=name\">Name</a></th><th><a href=\"?sort=size\">Size</a></th><th><a href=\"?sort=mtime\">Modified</a></th></tr>\n");
+    if base_path.trim_end_matches('/').matches('/').count() > 1 {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let suffix = if entry.entry_type == EntryType::Directory { "/" } else { "" };
+        html.push_str(&format!(
+            "<tr><td><a href=\"{href}{suffix}\">{name}{suffix}</a></td><td>{size}</td><td>{modified}</td></tr>\n",
+            href = escape_html(&format!("{base_path}{}", percent_encode_segment(&entry.name))),
+            name = escape_html(&entry.name),
+            size = if entry.entry_type == EntryType::Directory { "-".to_string() } else { entry.size.to_string() },
+            modified = entry.modified.map(format_http_date).unwrap_or_default(),
+        ));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+/// A JSON array of `{"name", "size", "mtime", "type"}` objects, `mtime` in Unix seconds.
+pub fn render_json(entries: &[Entry]) -> Value {
+    let items = entries.iter().map(|entry| {
+        let mtime = entry.modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+        Value::object([
+            ("name", Value::from(entry.name.as_str())),
+            ("size", Value::from(entry.size)),
+            ("mtime", Value::from(mtime)),
+            ("type", Value::from(entry.entry_type.as_str())),
+        ])
+    }).collect::<Vec<_>>();
+    Value::Array(items)
+}
@@ -0,0 +1,109 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::files;
+use crate::files::upload;
+use crate::http::conditional::{self, Precondition};
+use crate::http::headers::HTTPHeader;
+use crate::http::range;
+use crate::http::request::{BodyError, Request};
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+
+/// `PUT`: creates the file (`201` with `Location`) or replaces it (`204`).
+pub fn put(request: &Request, path: &Path, config: &Config) -> Response {
+    match files::store(request, path, config) {
+        Ok(true) => Response::new(HTTPStatus::Created).header(HTTPHeader::Location, request.path()),
+        Ok(false) => files::with_validators(Response::new(HTTPStatus::NoContent), &files::current_validators(path, config)),
+        Err(response) => response
+    }
+}
+
+/// `DELETE`: removes the file or an empty directory, `409` for a non-empty one. With WebDAV
+/// enabled directories are removed with their contents, as clients expect of collections.
+pub fn delete(request: &Request, path: &Path, config: &Config) -> Response {
+    let is_root = config.files_path.as_ref()
+        .and_then(|root| fs::canonicalize(root).ok())
+        .is_some_and(|root| root == path);
+    if is_root {
+        return Response::new(HTTPStatus::Forbidden);
+    }
+    let validators = files::current_validators(path, config);
+    if !validators.exists {
+        return Response::not_found();
+    }
+    if conditional::evaluate(request, &validators, false) == Precondition::Failed {
+        return Response::new(HTTPStatus::PreconditionFailed);
+    }
+
+    let result = match (path.is_dir(), config.webdav) {
+        (true, true) => fs::remove_dir_all(path),
+        (true, false) => fs::remove_dir(path),
+        (false, _) => fs::remove_file(path)
+    };
+    match result {
+        Ok(_) => Response::new(HTTPStatus::NoContent),
+        Err(_) if path.is_dir() && fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_some()) => {
+            Response::new(HTTPStatus::Conflict)
+        }
+        Err(error) => files::io_error_response(&error, path)
+    }
+}
+
+/// `PATCH` with `Content-Range: bytes first-last/*`: overwrites that range of an existing
+/// file, or appends when `first` is the current size. Writes happen in place, streamed from
+/// the connection, and may not grow the file past `Config.max_upload_size`.
+pub fn patch(request: &Request, path: &Path, config: &Config) -> Response {
+    let Some(content_range) = request.get_known_header_line(HTTPHeader::ContentRange) else {
+        return Response::new(HTTPStatus::BadRequest).text("PATCH requires a Content-Range header");
+    };
+    let Some((byte_range, _)) = range::parse_content_range(&content_range) else {
+        return Response::new(HTTPStatus::BadRequest).text("Malformed Content-Range header");
+    };
+    if request.content_length() != byte_range.length() {
+        return Response::new(HTTPStatus::BadRequest).text("Content-Range does not match the body length");
+    }
+    if byte_range.last >= config.max_upload_size {
+        return Response::new(HTTPStatus::ContentTooLarge)
+            .text(format!("Uploads are limited to {limit} bytes", limit = config.max_upload_size));
+    }
+
+    let validators = files::current_validators(path, config);
+    if !validators.exists {
+        return Response::not_found();
+    }
+    if conditional::evaluate(request, &validators, false) == Precondition::Failed {
+        return Response::new(HTTPStatus::PreconditionFailed);
+    }
+
+    let mut file = match OpenOptions::new().write(true).open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Response::not_found(),
+        Err(error) => return files::write_error_response(&error, path)
+    };
+    let size = match file.metadata() {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        Ok(_) => return Response::new(HTTPStatus::Conflict),
+        Err(error) => return files::io_error_response(&error, path)
+    };
+    if byte_range.first > size {
+        // Writing there would leave a hole in the file
+        return Response::new(HTTPStatus::RangeNotSatisfiable)
+            .header(HTTPHeader::ContentRange, format!("bytes */{size}"));
+    }
+
+    let mut body = match request.body_stream() {
+        Ok(body) => body,
+        Err(error) => return error.into()
+    };
+    let result = file.seek(SeekFrom::Start(byte_range.first))
+        .and_then(|_| upload::copy_body(&mut body, &mut file, byte_range.length()))
+        .and_then(|_| file.sync_data());
+    match result {
+        Ok(_) => files::with_validators(Response::new(HTTPStatus::NoContent), &files::current_validators(path, config)),
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => BodyError::Incomplete.into(),
+        Err(error) => files::write_error_response(&error, path)
+    }
+}
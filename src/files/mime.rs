@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Extension to media type table for the files routes, extended by `Config.mime_types`.
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("xml", "application/xml"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("7z", "application/x-7z-compressed"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("proto", "text/plain"),
+];
+
+/// Leading bytes identifying common formats, used for files without an extension.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+    (b"OggS", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+];
+
+pub fn from_extension(path: &Path, overrides: &HashMap<String, String>) -> Option<String> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    if let Some(mime_type) = overrides.get(&extension) {
+        return Some(mime_type.clone());
+    }
+    MIME_TYPES.iter()
+        .find(|(known_extension, _)| *known_extension == extension)
+        .map(|(_, mime_type)| mime_type.to_string())
+}
+
+/// Guesses the media type from the first bytes of a file.
+pub fn sniff(prefix: &[u8]) -> Option<&'static str> {
+    if let Some((_, mime_type)) = SIGNATURES.iter().find(|(signature, _)| prefix.starts_with(signature)) {
+        return Some(mime_type);
+    }
+    if prefix.len() >= 12 && &prefix[..4] == b"RIFF" && &prefix[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if prefix.len() >= 12 && &prefix[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+
+    if !is_utf8(prefix) {
+        return None;
+    }
+    let text = match std::str::from_utf8(prefix) {
+        Ok(text) => text,
+        Err(error) => std::str::from_utf8(&prefix[..error.valid_up_to()]).ok()?
+    };
+    let lowercase = text.trim_start().to_ascii_lowercase();
+    if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+        Some("text/html")
+    } else if lowercase.starts_with("<?xml") {
+        Some("application/xml")
+    } else if text.chars().all(|c| !c.is_control() || c.is_ascii_whitespace()) {
+        Some("text/plain")
+    } else {
+        None
+    }
+}
+
+/// Whether `prefix`, the first bytes of some content, is UTF-8 as far as it goes.
+pub fn is_utf8(prefix: &[u8]) -> bool {
+    match std::str::from_utf8(prefix) {
+        Ok(_) => true,
+        // The prefix may end in the middle of a multi-byte character
+        Err(error) => error.error_len().is_none()
+    }
+}
+
+/// Adds `charset=utf-8` to text types that don't carry a charset already, as long as `prefix`,
+/// the start of the content, is UTF-8. Other encodings are left for the client to detect.
+pub fn with_charset(mime_type: &str, prefix: &[u8]) -> String {
+    if mime_type.starts_with("text/") && !mime_type.contains("charset=") && is_utf8(prefix) {
+        format!("{mime_type}; charset=utf-8")
+    } else {
+        mime_type.to_string()
+    }
+}
+
+/// Parses comma separated `extension=media/type` pairs, e.g. `log=text/plain,conf=text/plain`,
+/// into overrides for `from_extension`.
+pub fn parse_overrides(value: &str) -> Result<HashMap<String, String>, String> {
+    value.split(',').filter(|pair| !pair.trim().is_empty()).map(|pair| {
+        let (extension, mime_type) = pair.split_once('=')
+            .ok_or_else(|| format!("Invalid media type mapping `{pair}`, expected `extension=type/subtype`"))?;
+        let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+        let mime_type = mime_type.trim();
+        let valid_type = mime_type.split_once('/')
+            .is_some_and(|(main, sub)| !main.is_empty() && !sub.is_empty() && !mime_type.contains(|c: char| c.is_ascii_control()));
+        if extension.is_empty() || !valid_type {
+            return Err(format!("Invalid media type mapping `{pair}`, expected `extension=type/subtype`"));
+        }
+        Ok((extension, mime_type.to_string()))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn extension_lookup_prefers_overrides() {
+        let overrides = parse_overrides("log=text/plain, .TXT=text/x-custom").unwrap();
+        assert_eq!(from_extension(Path::new("app.log"), &overrides).as_deref(), Some("text/plain"));
+        assert_eq!(from_extension(Path::new("notes.txt"), &overrides).as_deref(), Some("text/x-custom"));
+        assert_eq!(from_extension(Path::new("image.PNG"), &overrides).as_deref(), Some("image/png"));
+        assert_eq!(from_extension(Path::new("unknown.xyz"), &overrides), None);
+        assert_eq!(from_extension(Path::new("no-extension"), &overrides), None);
+    }
+
+    #[test]
+    fn parses_overrides() {
+        assert_eq!(parse_overrides("").unwrap(), HashMap::new());
+        assert_eq!(parse_overrides("conf=text/plain; charset=iso-8859-1,").unwrap(),
+                   HashMap::from([("conf".to_string(), "text/plain; charset=iso-8859-1".to_string())]));
+        assert!(parse_overrides("log").is_err());
+        assert!(parse_overrides("=text/plain").is_err());
+        assert!(parse_overrides("log=plain").is_err());
+        assert!(parse_overrides("log=text/").is_err());
+    }
+
+    #[test]
+    fn sniffs_signatures_and_text() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), Some("image/png"));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(b"  <!DOCTYPE html><html>"), Some("text/html"));
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>"), Some("application/xml"));
+        assert_eq!(sniff("gr\u{fc}\u{df}e\n".as_bytes()), Some("text/plain"));
+        // Cut inside the two bytes of `ü`
+        assert_eq!(sniff(&"gr\u{fc}".as_bytes()[..3]), Some("text/plain"));
+        assert_eq!(sniff(b"\0\x01\x02binary"), None);
+        assert_eq!(sniff(b"caf\xe9 au lait"), None);
+    }
+
+    #[test]
+    fn adds_charset_only_to_utf8_text() {
+        assert_eq!(with_charset("text/plain", "gr\u{fc}\u{df}e".as_bytes()), "text/plain; charset=utf-8");
+        assert_eq!(with_charset("text/html", b""), "text/html; charset=utf-8");
+        assert_eq!(with_charset("text/plain", b"caf\xe9 au lait"), "text/plain");
+        assert_eq!(with_charset("text/plain", b"\xff\xfeh\0i\0"), "text/plain");
+        assert_eq!(with_charset("text/plain; charset=iso-8859-1", b"plain"), "text/plain; charset=iso-8859-1");
+        assert_eq!(with_charset("image/png", b"plain"), "image/png");
+    }
+}
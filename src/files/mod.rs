@@ -0,0 +1,387 @@
+use std::cell::OnceCell;
+use std::fs::{File, Metadata};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::config::{Config, ETagMode};
+use crate::files::path::PathRejection;
+use crate::files::upload::WriteMode;
+use crate::hash::{Sha256, to_hex};
+use crate::http::Body;
+use crate::http::conditional::{self, EntityTag, Precondition, Validators};
+use crate::http::date::format_http_date;
+use crate::http::headers::HTTPHeader;
+use crate::http::negotiation::Dimension;
+use crate::http::range::{self, RangeRequest};
+use crate::http::request::{BodyError, HTTPMethod, Request};
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+
+pub mod path;
+pub mod mime;
+pub mod listing;
+pub mod site;
+pub mod upload;
+pub mod manage;
+pub mod resumable;
+pub mod form;
+#[cfg(test)]
+pub mod scratch;
+
+pub const ROUTE_PREFIX: &str = "/files";
+
+/// Maps the request onto a path under `Config.files_path`, or the response to send instead.
+/// Attempts to escape the files directory are logged.
+pub fn resolve_request_path(request: &Request, config: &Config) -> Result<PathBuf, Response> {
+    resolve_files_url_path(request, request.path(), config)
+}
+
+/// Like `resolve_request_path`, for a URL path other than the request's own, e.g. a WebDAV
+/// `Destination`.
+pub fn resolve_files_url_path(request: &Request, url_path: &str, config: &Config) -> Result<PathBuf, Response> {
+    let root = match &config.files_path {
+        None => return Err(Response::not_found()),
+        Some(root) => Path::new(root)
+    };
+    let request_path = match url_path.strip_prefix(ROUTE_PREFIX).and_then(|path| path.strip_prefix('/')) {
+        None => return Err(Response::not_found()),
+        Some(request_path) => request_path
+    };
+
+    resolve_under(request, root, request_path, config)
+}
+
+/// Resolves `request_path` below `root`, logging rejected attempts and turning them into responses.
+pub fn resolve_under(request: &Request, root: &Path, request_path: &str, config: &Config) -> Result<PathBuf, Response> {
+    path::resolve(root, request_path, config.symlink_policy).map_err(|rejection| {
+        eprintln!("Rejected files path in `{method} {resource}`: {rejection}", method = request.method, resource = request.resource);
+        match rejection {
+            PathRejection::InvalidEncoding => Response::new(HTTPStatus::BadRequest),
+            PathRejection::Io(error) => io_error_response(&error, root),
+            _ => Response::new(HTTPStatus::Forbidden)
+        }
+    })
+}
+
+/// Maps a filesystem error to the response the client gets for it.
+pub fn io_error_response(error: &io::Error, path: &Path) -> Response {
+    match error.kind() {
+        ErrorKind::NotFound => Response::not_found(),
+        ErrorKind::PermissionDenied => Response::new(HTTPStatus::Forbidden),
+        _ => {
+            eprintln!("Failed to access `{path}`: {error}", path = path.display());
+            Response::new(HTTPStatus::InternalServerError)
+        }
+    }
+}
+
+/// Up to the first 512 bytes of `file`, enough to sniff its media type and encoding.
+fn read_prefix(mut file: &File) -> Vec<u8> {
+    let mut prefix = vec![0u8; 512];
+    let read = file.seek(SeekFrom::Start(0)).and_then(|_| file.read(&mut prefix)).unwrap_or(0);
+    prefix.truncate(read);
+    prefix
+}
+
+/// Media type for a served file: by extension first, then by sniffing its first bytes.
+pub fn content_type(path: &Path, file: &File, config: &Config) -> String {
+    let prefix = OnceCell::new();
+    let prefix = || prefix.get_or_init(|| read_prefix(file)).as_slice();
+    let mime_type = mime::from_extension(path, &config.mime_types)
+        .or_else(|| config.sniff_mime_types.then(|| mime::sniff(prefix())).flatten().map(|mime_type| mime_type.to_string()))
+        .unwrap_or_else(|| mime::DEFAULT_MIME_TYPE.to_string());
+    // Only text types get a charset, so others don't need the prefix read
+    if mime_type.starts_with("text/") {
+        mime::with_charset(&mime_type, prefix())
+    } else {
+        mime_type
+    }
+}
+
+/// Tag derived from the inode, size and modification time, so it changes whenever the file does
+/// without reading it.
+fn metadata_tag(metadata: &Metadata) -> String {
+    let modified = metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        format!("{inode:x}-{size:x}-{modified:x}", inode = metadata.ino(), size = metadata.len())
+    }
+    #[cfg(not(unix))]
+    format!("{size:x}-{modified:x}", size = metadata.len())
+}
+
+fn content_hash_tag(mut file: &File) -> io::Result<String> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 0x10000];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(to_hex(&hasher.finish()))
+}
+
+pub fn validators(file: &File, metadata: &Metadata, config: &Config) -> Validators {
+    let etag = match config.etag_mode {
+        ETagMode::Weak => Some(EntityTag::weak(&metadata_tag(metadata))),
+        ETagMode::Strong => Some(EntityTag::strong(&metadata_tag(metadata))),
+        ETagMode::ContentHash => content_hash_tag(file).ok().map(|tag| EntityTag::strong(&tag)),
+    };
+    Validators { etag, last_modified: metadata.modified().ok(), exists: true }
+}
+
+/// Adds `ETag` and `Last-Modified` for the validators that are known.
+pub fn with_validators(mut response: Response, validators: &Validators) -> Response {
+    if let Some(etag) = &validators.etag {
+        response = response.header(HTTPHeader::ETag, etag);
+    }
+    if let Some(last_modified) = validators.last_modified {
+        response = response.header(HTTPHeader::LastModified, format_http_date(last_modified));
+    }
+    response
+}
+
+struct OpenedFile {
+    file: File,
+    metadata: Metadata,
+    validators: Validators,
+}
+
+fn open_regular_file(path: &Path, config: &Config) -> Result<OpenedFile, Response> {
+    let file = File::open(path).map_err(|error| io_error_response(&error, path))?;
+    let metadata = file.metadata().map_err(|error| io_error_response(&error, path))?;
+    if !metadata.is_file() {
+        let error = io::Error::other("not a regular file (is it a directory?)");
+        return Err(io_error_response(&error, path));
+    }
+    let validators = validators(&file, &metadata, config);
+    Ok(OpenedFile { file, metadata, validators })
+}
+
+fn file_response(path: &Path, opened: OpenedFile, config: &Config) -> Response {
+    let content_type = content_type(path, &opened.file, config);
+    let response = Response::ok()
+        .content(&content_type, Body::from_file_region(opened.file, 0, opened.metadata.len()))
+        .header(HTTPHeader::XContentTypeOptions, "nosniff")
+        .header(HTTPHeader::AcceptRanges, "bytes");
+    with_validators(response, &opened.validators)
+}
+
+/// `206 Partial Content` with the requested ranges of the file; several ranges are sent
+/// as `multipart/byteranges`. Falls back to the full file when the `Range` header doesn't apply.
+fn ranged_file_response(request: &Request, path: &Path, opened: OpenedFile, config: &Config) -> Response {
+    let Some(range_header) = request.get_known_header_line(HTTPHeader::Range) else {
+        return file_response(path, opened, config);
+    };
+    if !range::if_range_matches(request, &opened.validators) {
+        return file_response(path, opened, config);
+    }
+
+    let length = opened.metadata.len();
+    let ranges = match range::parse(&range_header, length) {
+        RangeRequest::Full => return file_response(path, opened, config),
+        RangeRequest::Unsatisfiable => {
+            return Response::new(HTTPStatus::RangeNotSatisfiable)
+                .header(HTTPHeader::ContentRange, format!("bytes */{length}"))
+                .header(HTTPHeader::AcceptRanges, "bytes");
+        }
+        RangeRequest::Ranges(ranges) => ranges
+    };
+
+    let content_type = content_type(path, &opened.file, config);
+    let file = Arc::new(opened.file);
+    let response = match ranges.as_slice() {
+        [range] => Response::ok()
+            .content(&content_type, Body::from_file_region(file, range.first, range.length()))
+            .header(HTTPHeader::ContentRange, range.content_range(length)),
+        ranges => {
+            let boundary = range::multipart_boundary();
+            let mut parts = Vec::with_capacity(ranges.len() * 2 + 1);
+            for (index, range) in ranges.iter().enumerate() {
+                let separator = if index == 0 { "" } else { "\r\n" };
+                parts.push(Body::from(format!(
+                    "{separator}--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {content_range}\r\n\r\n",
+                    content_range = range.content_range(length)
+                )));
+                parts.push(Body::from_file_region(file.clone(), range.first, range.length()));
+            }
+            parts.push(Body::from(format!("\r\n--{boundary}--\r\n")));
+            Response::ok().content(&format!("multipart/byteranges; boundary={boundary}"), Body::from_parts(parts))
+        }
+    };
+    let response = response
+        .status(HTTPStatus::PartialContent)
+        .header(HTTPHeader::XContentTypeOptions, "nosniff")
+        .header(HTTPHeader::AcceptRanges, "bytes");
+    with_validators(response, &opened.validators)
+}
+
+/// Responds with the file's bytes, streamed from disk rather than loaded up front,
+/// or with `304`/`412` if the request's preconditions say so.
+pub fn serve_file(request: &Request, path: &Path, config: &Config) -> Response {
+    let opened = match open_regular_file(path, config) {
+        Ok(opened) => opened,
+        Err(response) => return response
+    };
+    // `HEAD` has to answer exactly like `GET`, minus the body
+    let safe = matches!(request.method, HTTPMethod::GET | HTTPMethod::HEAD);
+    match conditional::evaluate(request, &opened.validators, safe) {
+        Precondition::NotModified => with_validators(Response::new(HTTPStatus::NotModified), &opened.validators),
+        Precondition::Failed => Response::new(HTTPStatus::PreconditionFailed),
+        Precondition::Proceed if safe => ranged_file_response(request, path, opened, config),
+        Precondition::Proceed => file_response(path, opened, config)
+    }
+}
+
+/// Sends the file as the content of an error response, ignoring preconditions.
+pub fn serve_error_page(path: &Path, status: HTTPStatus, config: &Config) -> Response {
+    match open_regular_file(path, config) {
+        Ok(opened) => file_response(path, opened, config).status(status),
+        Err(_) => Response::new(status)
+    }
+}
+
+/// Lists the directory as JSON for clients preferring it and as HTML otherwise.
+/// `?sort=name|size|mtime` and `?order=asc|desc` pick the order of the entries.
+pub fn serve_directory(request: &Request, path: &Path, config: &Config) -> Response {
+    let mut entries = match listing::read_entries(path, config.list_hidden_files) {
+        Ok(entries) => entries,
+        Err(error) => return io_error_response(&error, path)
+    };
+    let query = request.query();
+    let sort_key = listing::SortKey::from_query(query.get("sort"));
+    listing::sort_entries(&mut entries, sort_key, query.get("order") == Some("desc"));
+
+    let media_type = match request.negotiate(Dimension::MediaType, &["text/html", "application/json"]) {
+        Ok(media_type) => media_type,
+        Err(response) => return response
+    };
+    if media_type == "application/json" {
+        return Response::ok().json(listing::render_json(&entries));
+    }
+    // File names can be anything, so the page is only sent as UTF-8
+    if let Err(response) = request.negotiate(Dimension::Charset, &["utf-8"]) {
+        return response;
+    }
+    let base_path = match request.path().ends_with('/') {
+        true => request.path().to_string(),
+        false => format!("{}/", request.path())
+    };
+    Response::ok().html(listing::render_html(&base_path, &entries))
+}
+
+/// Serves the file at `path`, or a listing if it is a directory and listings are enabled.
+pub fn serve(request: &Request, path: &Path, config: &Config) -> Response {
+    if config.directory_listing && path.is_dir() {
+        serve_directory(request, path, config)
+    } else {
+        serve_file(request, path, config)
+    }
+}
+
+/// Validators of whatever currently exists at `path`, for evaluating preconditions of writes.
+pub fn current_validators(path: &Path, config: &Config) -> Validators {
+    match File::open(path).and_then(|file| file.metadata().map(|metadata| (file, metadata))) {
+        Ok((file, metadata)) if metadata.is_file() => validators(&file, &metadata, config),
+        Ok(_) => Validators { exists: true, ..Validators::default() },
+        Err(_) => Validators::default()
+    }
+}
+
+/// Maps an error from writing a file to a response.
+pub fn write_error_response(error: &io::Error, path: &Path) -> Response {
+    match error.kind() {
+        ErrorKind::AlreadyExists => Response::new(HTTPStatus::PreconditionFailed),
+        // A missing parent directory
+        ErrorKind::NotFound => Response::new(HTTPStatus::Conflict),
+        _ if upload::is_out_of_space(error) => Response::new(HTTPStatus::InsufficientStorage),
+        _ => io_error_response(error, path)
+    }
+}
+
+/// Atomically stores the request body at `path`, streaming it from the connection; bodies over
+/// `Config.max_upload_size` get `413`. `If-Match`/`If-None-Match` are honoured, so
+/// `If-None-Match: *` refuses to overwrite, and `?create_parents=true` creates missing
+/// parent directories. Returns whether the file was newly created.
+pub fn store(request: &Request, path: &Path, config: &Config) -> Result<bool, Response> {
+    // Checked before anything is read from the connection
+    if request.content_length() > config.max_upload_size {
+        return Err(Response::new(HTTPStatus::ContentTooLarge)
+            .text(format!("Uploads are limited to {limit} bytes", limit = config.max_upload_size)));
+    }
+    if path.is_dir() {
+        return Err(Response::new(HTTPStatus::Conflict));
+    }
+    let validators = current_validators(path, config);
+    if conditional::evaluate(request, &validators, false) == Precondition::Failed {
+        return Err(Response::new(HTTPStatus::PreconditionFailed));
+    }
+    let mode = match request.get_known_header_line(HTTPHeader::IfNoneMatch) {
+        Some(value) if value.trim() == "*" => WriteMode::CreateOnly,
+        _ => WriteMode::Replace
+    };
+    let create_parents = request.query().get("create_parents") == Some("true");
+    let mut body = request.body_stream()?;
+
+    upload::write_atomically(path, mode, create_parents, |file| upload::copy_body(&mut body, file, request.content_length()))
+        .map_err(|error| match error.kind() {
+            ErrorKind::UnexpectedEof => BodyError::Incomplete.into(),
+            _ => write_error_response(&error, path)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::files::scratch::ScratchDir;
+
+    use super::*;
+
+    fn upload(path: &Path, content: &str, announced_length: usize, config: &Config) -> Result<bool, Response> {
+        let raw = format!("PUT /files/file.txt HTTP/1.1\r\nContent-Length: {announced_length}\r\n\r\n{content}");
+        let request = Request::new(io::Cursor::new(raw.into_bytes())).unwrap();
+        store(&request, path, config)
+    }
+
+    #[test]
+    fn stores_the_streamed_body() {
+        let directory = ScratchDir::new();
+        let path = directory.join("file.txt");
+        let config = Config::new("127.0.0.1", 4221, directory.to_str().map(str::to_string));
+        assert!(upload(&path, "hello", 5, &config).unwrap());
+        assert!(!upload(&path, "hello, world", 12, &config).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello, world");
+    }
+
+    #[test]
+    fn rejects_uploads_over_the_limit() {
+        let directory = ScratchDir::new();
+        let path = directory.join("file.txt");
+        let mut config = Config::new("127.0.0.1", 4221, directory.to_str().map(str::to_string));
+        config.max_upload_size = 4;
+        assert_eq!(upload(&path, "hello", 5, &config).unwrap_err().status, HTTPStatus::ContentTooLarge);
+        assert!(upload(&path, "hell", 4, &config).unwrap());
+        assert!(!upload(&path, "", 0, &config).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn keeps_the_old_content_when_the_body_ends_early() {
+        let directory = ScratchDir::new();
+        let path = directory.file("file.txt", b"old");
+        let config = Config::new("127.0.0.1", 4221, directory.to_str().map(str::to_string));
+        assert_eq!(upload(&path, "new", 10, &config).unwrap_err().status, HTTPStatus::BadRequest);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+    }
+}
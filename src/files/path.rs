@@ -0,0 +1,304 @@
+use std::fmt::{Display, Formatter};
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::SymlinkPolicy;
+use crate::http::url::percent_decode;
+
+/// Symlinks followed in a row before a path counts as a loop, as on Linux.
+const MAX_SYMLINK_HOPS: usize = 40;
+
+#[derive(Debug)]
+pub enum PathRejection {
+    InvalidEncoding,
+    EncodedSeparator,
+    Absolute,
+    Traversal,
+    Symlink,
+    OutsideRoot,
+    Io(io::Error),
+}
+
+impl Display for PathRejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathRejection::InvalidEncoding => write!(f, "malformed percent-encoding"),
+            PathRejection::EncodedSeparator => write!(f, "encoded path separator"),
+            PathRejection::Absolute => write!(f, "absolute path"),
+            PathRejection::Traversal => write!(f, "`..` segment"),
+            PathRejection::Symlink => write!(f, "symlink denied by policy"),
+            PathRejection::OutsideRoot => write!(f, "resolves outside of the files directory"),
+            PathRejection::Io(error) => write!(f, "{error}")
+        }
+    }
+}
+
+/// Turns the part of a request path below the files route into a filesystem path that is
+/// guaranteed to stay under `root`, applying `policy` to any symlinks on the way.
+/// The target itself does not need to exist, so the same check serves reads and writes.
+pub fn resolve(root: &Path, request_path: &str, policy: SymlinkPolicy) -> Result<PathBuf, PathRejection> {
+    let lowercase = request_path.to_ascii_lowercase();
+    if lowercase.contains("%2f") || lowercase.contains("%5c") {
+        return Err(PathRejection::EncodedSeparator);
+    }
+    let decoded = percent_decode(request_path).ok_or(PathRejection::InvalidEncoding)?;
+    if decoded.contains('\0') || decoded.contains('\\') {
+        return Err(PathRejection::InvalidEncoding);
+    }
+    if decoded.starts_with('/') || Path::new(&decoded).is_absolute() {
+        return Err(PathRejection::Absolute);
+    }
+
+    let root = fs::canonicalize(root).map_err(PathRejection::Io)?;
+    let mut resolved = root.clone();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return Err(PathRejection::Traversal),
+            segment => resolved.push(segment)
+        }
+    }
+
+    match policy {
+        SymlinkPolicy::Follow => {}
+        SymlinkPolicy::Deny => {
+            let mut current = root.clone();
+            for component in resolved.strip_prefix(&root).unwrap_or(Path::new("")).components() {
+                current.push(component);
+                match fs::symlink_metadata(&current) {
+                    Ok(metadata) if metadata.file_type().is_symlink() => return Err(PathRejection::Symlink),
+                    Ok(_) => {}
+                    Err(_) => break
+                }
+            }
+        }
+        SymlinkPolicy::WithinRoot => {
+            if !stays_within(&root, &resolved) {
+                return Err(PathRejection::OutsideRoot);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Whether `path` stays under `root` once every symlink on it is followed, including a
+/// dangling one, whose target a write through it could create.
+fn stays_within(root: &Path, path: &Path) -> bool {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_HOPS {
+        // Only the existing part of the path and a dangling symlink right below it can point anywhere
+        let mut existing = path.as_path();
+        let canonical = loop {
+            match fs::canonicalize(existing) {
+                Ok(canonical) => break canonical,
+                Err(_) => match existing.parent() {
+                    Some(parent) => existing = parent,
+                    None => return false
+                }
+            }
+        };
+        if !canonical.starts_with(root) {
+            return false;
+        }
+        let mut missing = path.strip_prefix(existing).unwrap_or(Path::new("")).components();
+        let Some(next) = missing.next() else {
+            return true;
+        };
+        match fs::read_link(canonical.join(next)) {
+            // Relative targets start from the directory holding the link, absolute ones replace it
+            Ok(target) => path = canonical.join(target).join(missing.as_path()),
+            Err(_) => return true
+        }
+    }
+    false
+}
+
+/// Applies `policy` to `path`, an entry met while walking a directory that was resolved
+/// below `root` already. Returns the entry's metadata, that of its target for a symlink the
+/// policy lets through.
+pub fn entry_metadata(root: &Path, path: &Path, policy: SymlinkPolicy) -> Result<Metadata, PathRejection> {
+    let metadata = fs::symlink_metadata(path).map_err(PathRejection::Io)?;
+    if !metadata.file_type().is_symlink() {
+        return Ok(metadata);
+    }
+    match policy {
+        SymlinkPolicy::Follow => {}
+        SymlinkPolicy::Deny => return Err(PathRejection::Symlink),
+        SymlinkPolicy::WithinRoot => {
+            let root = fs::canonicalize(root).map_err(PathRejection::Io)?;
+            let target = fs::canonicalize(path).map_err(PathRejection::Io)?;
+            if !target.starts_with(&root) {
+                return Err(PathRejection::OutsideRoot);
+            }
+        }
+    }
+    fs::metadata(path).map_err(PathRejection::Io)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::files::scratch::ScratchDir;
+
+    use super::*;
+
+    const POLICIES: [SymlinkPolicy; 3] = [SymlinkPolicy::Deny, SymlinkPolicy::WithinRoot, SymlinkPolicy::Follow];
+
+    fn rejection(root: &Path, request_path: &str, policy: SymlinkPolicy) -> String {
+        match resolve(root, request_path, policy) {
+            Ok(path) => panic!("`{request_path}` resolved to {path:?}"),
+            Err(rejection) => format!("{rejection:?}")
+        }
+    }
+
+    #[test]
+    fn resolves_below_root() {
+        let root = ScratchDir::new();
+        root.file("dir/file.txt", b"content");
+        let canonical = fs::canonicalize(&*root).unwrap();
+        for policy in POLICIES {
+            assert_eq!(resolve(&root, "dir/file.txt", policy).unwrap(), canonical.join("dir/file.txt"));
+            assert_eq!(resolve(&root, "./dir//new%20file.txt", policy).unwrap(), canonical.join("dir/new file.txt"));
+            assert_eq!(resolve(&root, "", policy).unwrap(), canonical);
+        }
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        let root = ScratchDir::new();
+        root.file("dir/file.txt", b"content");
+        for policy in POLICIES {
+            assert_eq!(rejection(&root, "..", policy), "Traversal");
+            assert_eq!(rejection(&root, "dir/../../etc/passwd", policy), "Traversal");
+            assert_eq!(rejection(&root, "dir/..", policy), "Traversal");
+            assert_eq!(rejection(&root, "%2e%2e/etc/passwd", policy), "Traversal");
+        }
+    }
+
+    #[test]
+    fn rejects_encoded_separators_and_nul() {
+        let root = ScratchDir::new();
+        for policy in POLICIES {
+            assert_eq!(rejection(&root, "..%2f..%2fetc", policy), "EncodedSeparator");
+            assert_eq!(rejection(&root, "dir%2Ffile.txt", policy), "EncodedSeparator");
+            assert_eq!(rejection(&root, "..%5c..%5Cwindows", policy), "EncodedSeparator");
+            assert_eq!(rejection(&root, "file.txt%00.png", policy), "InvalidEncoding");
+            assert_eq!(rejection(&root, "dir\\file.txt", policy), "InvalidEncoding");
+            assert_eq!(rejection(&root, "file%zz", policy), "InvalidEncoding");
+            assert_eq!(rejection(&root, "file%e9", policy), "InvalidEncoding");
+        }
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let root = ScratchDir::new();
+        for policy in POLICIES {
+            assert_eq!(rejection(&root, "/etc/passwd", policy), "Absolute");
+            assert_eq!(rejection(&root, "//etc/passwd", policy), "Absolute");
+        }
+    }
+
+    #[test]
+    fn missing_root_is_an_io_error() {
+        let root = ScratchDir::new();
+        assert!(matches!(resolve(&root.join("missing"), "file.txt", SymlinkPolicy::Deny), Err(PathRejection::Io(_))));
+    }
+
+    #[cfg(unix)]
+    mod symlinks {
+        use std::os::unix::fs::symlink;
+
+        use pretty_assertions::assert_eq;
+
+        use super::*;
+
+        /// A root holding `inside` and `escape`, symlinks to a directory inside and outside of it,
+        /// and `dangling` and `dangling-escape`, symlinks to missing files inside and outside.
+        fn linked_root(outside: &ScratchDir) -> ScratchDir {
+            let root = ScratchDir::new();
+            root.file("real/file.txt", b"inside");
+            outside.file("secret.txt", b"outside");
+            symlink(root.join("real"), root.join("inside")).unwrap();
+            symlink(&**outside, root.join("escape")).unwrap();
+            symlink("real/missing.txt", root.join("dangling")).unwrap();
+            symlink(outside.join("created.txt"), root.join("dangling-escape")).unwrap();
+            root
+        }
+
+        #[test]
+        fn deny_rejects_every_symlink() {
+            let outside = ScratchDir::new();
+            let root = linked_root(&outside);
+            for path in ["inside", "inside/file.txt", "escape/secret.txt", "dangling", "dangling-escape", "inside/new/file.txt"] {
+                assert_eq!(rejection(&root, path, SymlinkPolicy::Deny), "Symlink");
+            }
+            assert!(resolve(&root, "real/file.txt", SymlinkPolicy::Deny).is_ok());
+        }
+
+        #[test]
+        fn within_root_follows_symlinks_that_stay_inside() {
+            let outside = ScratchDir::new();
+            let root = linked_root(&outside);
+            let canonical = fs::canonicalize(&*root).unwrap();
+            assert_eq!(resolve(&root, "inside/file.txt", SymlinkPolicy::WithinRoot).unwrap(), canonical.join("inside/file.txt"));
+            assert_eq!(resolve(&root, "inside/new/file.txt", SymlinkPolicy::WithinRoot).unwrap(), canonical.join("inside/new/file.txt"));
+            assert_eq!(resolve(&root, "dangling", SymlinkPolicy::WithinRoot).unwrap(), canonical.join("dangling"));
+        }
+
+        #[test]
+        fn within_root_rejects_symlinks_that_escape() {
+            let outside = ScratchDir::new();
+            let root = linked_root(&outside);
+            for path in ["escape", "escape/secret.txt", "escape/new.txt", "dangling-escape"] {
+                assert_eq!(rejection(&root, path, SymlinkPolicy::WithinRoot), "OutsideRoot");
+            }
+        }
+
+        #[test]
+        fn within_root_rejects_symlinks_through_parent_directories() {
+            let outside = ScratchDir::new();
+            let root = linked_root(&outside);
+            symlink("../..", root.join("real/up")).unwrap();
+            symlink("loop", root.join("loop")).unwrap();
+            assert_eq!(rejection(&root, "real/up", SymlinkPolicy::WithinRoot), "OutsideRoot");
+            assert_eq!(rejection(&root, "real/up/file.txt", SymlinkPolicy::WithinRoot), "OutsideRoot");
+            assert_eq!(rejection(&root, "loop", SymlinkPolicy::WithinRoot), "OutsideRoot");
+        }
+
+        #[test]
+        fn follow_allows_any_symlink() {
+            let outside = ScratchDir::new();
+            let root = linked_root(&outside);
+            let canonical = fs::canonicalize(&*root).unwrap();
+            for path in ["inside/file.txt", "escape/secret.txt", "dangling", "dangling-escape"] {
+                assert_eq!(resolve(&root, path, SymlinkPolicy::Follow).unwrap(), canonical.join(path));
+            }
+        }
+
+        #[test]
+        fn entry_metadata_applies_policy() {
+            let outside = ScratchDir::new();
+            let root = linked_root(&outside);
+            let entry = |name: &str, policy| entry_metadata(&root, &root.join(name), policy);
+
+            for policy in POLICIES {
+                assert!(entry("real", policy).unwrap().is_dir());
+            }
+
+            assert!(matches!(entry("inside", SymlinkPolicy::Deny), Err(PathRejection::Symlink)));
+            assert!(matches!(entry("escape", SymlinkPolicy::Deny), Err(PathRejection::Symlink)));
+            assert!(matches!(entry("dangling", SymlinkPolicy::Deny), Err(PathRejection::Symlink)));
+
+            assert!(entry("inside", SymlinkPolicy::WithinRoot).unwrap().is_dir());
+            assert!(matches!(entry("escape", SymlinkPolicy::WithinRoot), Err(PathRejection::OutsideRoot)));
+            assert!(matches!(entry("dangling", SymlinkPolicy::WithinRoot), Err(PathRejection::Io(_))));
+
+            assert!(entry("inside", SymlinkPolicy::Follow).unwrap().is_dir());
+            assert!(entry("escape", SymlinkPolicy::Follow).unwrap().is_dir());
+            assert!(matches!(entry("dangling", SymlinkPolicy::Follow), Err(PathRejection::Io(_))));
+        }
+    }
+}
@@ -0,0 +1,719 @@
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use itertools::Itertools;
+
+use crate::base64;
+use crate::config::Config;
+use crate::files;
+use crate::files::upload::{self, WriteMode};
+use crate::hash::{Sha256, to_hex};
+use crate::http::date::format_http_date;
+use crate::http::headers::HTTPHeader;
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+use crate::http::url::percent_encode_segment;
+use crate::random::random_bytes;
+
+pub const ROUTE_PREFIX: &str = "/uploads";
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,expiration,checksum,termination";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// An unfinished upload, as stored in its `.info` file.
+struct Upload {
+    length: u64,
+    /// Percent-encoded path below `files_path` the file is moved to once complete.
+    destination: String,
+    /// `Upload-Metadata` as sent on creation, echoed back by `HEAD`.
+    metadata: Option<String>,
+    /// SHA-256 of the complete file, if the client sent one on creation.
+    checksum: Option<[u8; 32]>,
+    expires: SystemTime,
+}
+
+impl Upload {
+    fn serialize(&self) -> String {
+        let mut info = format!(
+            "length {length}\ndestination {destination}\nexpires {expires}\n",
+            length = self.length,
+            destination = self.destination,
+            expires = self.expires.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+        );
+        if let Some(metadata) = &self.metadata {
+            info.push_str(&format!("metadata {metadata}\n"));
+        }
+        if let Some(checksum) = &self.checksum {
+            info.push_str(&format!("checksum {}\n", to_hex(checksum)));
+        }
+        info
+    }
+
+    fn parse(info: &str) -> Option<Self> {
+        let mut length = None;
+        let mut destination = None;
+        let mut expires = None;
+        let mut metadata = None;
+        let mut checksum = None;
+        for line in info.lines() {
+            let (key, value) = line.split_once(' ')?;
+            match key {
+                "length" => length = value.parse().ok(),
+                "destination" => destination = Some(value.to_string()),
+                "expires" => expires = value.parse().ok().map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)),
+                "metadata" => metadata = Some(value.to_string()),
+                "checksum" => checksum = Some(parse_hex_digest(value)?),
+                _ => {}
+            }
+        }
+        Some(Self { length: length?, destination: destination?, metadata, checksum, expires: expires? })
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires <= SystemTime::now()
+    }
+}
+
+fn parse_hex_digest(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (index, byte) in digest.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(digest)
+}
+
+/// Ids of uploads a request is currently appending to or terminating.
+static BUSY_UPLOADS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Marks an upload as busy until dropped, so concurrent `PATCH`es can't interleave.
+struct BusyUpload(String);
+
+impl BusyUpload {
+    fn claim(id: &str) -> Option<Self> {
+        let mut busy = BUSY_UPLOADS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if busy.iter().any(|other| other == id) {
+            return None;
+        }
+        busy.push(id.to_string());
+        Some(Self(id.to_string()))
+    }
+}
+
+impl Drop for BusyUpload {
+    fn drop(&mut self) {
+        let mut busy = BUSY_UPLOADS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        busy.retain(|id| *id != self.0);
+    }
+}
+
+fn tus_response(status: HTTPStatus) -> Response {
+    Response::new(status).header(HTTPHeader::TusResumable, TUS_VERSION)
+}
+
+fn checksum_mismatch() -> Response {
+    tus_response(HTTPStatus::custom(460, "Checksum Mismatch").expect("460 is in the valid range"))
+}
+
+/// Every request but `OPTIONS` has to state the protocol version it speaks.
+fn check_version(request: &Request) -> Result<(), Response> {
+    match request.get_known_header_line(HTTPHeader::TusResumable) {
+        Some(version) if version.trim() == TUS_VERSION => Ok(()),
+        _ => Err(tus_response(HTTPStatus::PreconditionFailed).header(HTTPHeader::TusVersion, TUS_VERSION))
+    }
+}
+
+fn files_root(config: &Config) -> Result<&Path, Response> {
+    config.files_path.as_deref().map(Path::new).ok_or_else(Response::not_found)
+}
+
+/// `Config.uploads_path`, holding the data received so far of each unfinished upload in
+/// `<id>` and its parameters in `<id>.info`. Created on first use, accessible to the server only.
+fn uploads_directory(config: &Config) -> Result<PathBuf, Response> {
+    let directory = config.uploads_path.as_path();
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        // Unfinished uploads are nobody else's business
+        builder.mode(0o700);
+    }
+    builder.create(directory)
+        .map_err(|error| files::write_error_response(&error, directory))?;
+    // Anything below `files_path` can be read and written over the files routes
+    let canonical = |path: &Path| fs::canonicalize(path).map_err(|error| files::io_error_response(&error, path));
+    if canonical(directory)?.starts_with(canonical(files_root(config)?)?) {
+        eprintln!("Refusing to keep uploads in `{}`, which is inside the files directory", directory.display());
+        return Err(tus_response(HTTPStatus::InternalServerError));
+    }
+    Ok(directory.to_path_buf())
+}
+
+/// The upload id in the request path; ids are 32 lowercase hex digits.
+fn upload_id(request: &Request) -> Result<&str, Response> {
+    let id = request.path().strip_prefix(ROUTE_PREFIX).and_then(|path| path.strip_prefix('/'));
+    match id {
+        Some(id) if id.len() == 32 && id.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f')) => Ok(id),
+        _ => Err(tus_response(HTTPStatus::NotFound))
+    }
+}
+
+fn new_upload_id() -> io::Result<String> {
+    random_bytes(16).map(|bytes| to_hex(&bytes))
+}
+
+fn info_path(directory: &Path, id: &str) -> PathBuf {
+    directory.join(format!("{id}.info"))
+}
+
+fn save(directory: &Path, id: &str, upload: &Upload) -> io::Result<()> {
+    let info = upload.serialize();
+    upload::write_atomically(&info_path(directory, id), WriteMode::Replace, false, |file| file.write_all(info.as_bytes()))
+        .map(|_| ())
+}
+
+fn load(directory: &Path, id: &str) -> Result<Upload, Response> {
+    let info = fs::read_to_string(info_path(directory, id)).map_err(|error| match error.kind() {
+        ErrorKind::NotFound => tus_response(HTTPStatus::NotFound),
+        _ => files::io_error_response(&error, directory)
+    })?;
+    let upload = Upload::parse(&info).ok_or_else(|| {
+        eprintln!("Corrupt upload info for `{id}` in `{}`", directory.display());
+        tus_response(HTTPStatus::InternalServerError)
+    })?;
+    if upload.is_expired() {
+        remove(directory, id);
+        return Err(tus_response(HTTPStatus::Gone));
+    }
+    Ok(upload)
+}
+
+fn remove(directory: &Path, id: &str) {
+    let _ = fs::remove_file(info_path(directory, id));
+    let _ = fs::remove_file(directory.join(id));
+}
+
+/// Removes uploads that have not seen a `PATCH` within `Config.upload_expiry`.
+fn remove_expired(directory: &Path) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let Some(id) = file_name.to_str().and_then(|name| name.strip_suffix(".info")) else {
+            continue;
+        };
+        let upload = fs::read_to_string(entry.path()).ok().and_then(|info| Upload::parse(&info));
+        let expired = match upload {
+            Some(upload) => upload.is_expired(),
+            None => true
+        };
+        if expired {
+            remove(directory, id);
+        }
+    }
+}
+
+/// Parses `Upload-Metadata`: comma separated keys, each optionally followed by a space and
+/// its base64 encoded value.
+fn parse_metadata(value: &str) -> Option<Vec<(String, Vec<u8>)>> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once(' ') {
+            None => Some((pair.to_string(), Vec::new())),
+            Some((key, value)) => Some((key.to_string(), base64::decode(value.trim())?))
+        })
+        .collect()
+}
+
+/// Parses an `Upload-Checksum` of the form `sha256 <base64 digest>`.
+fn parse_checksum(value: &str) -> Result<[u8; 32], Response> {
+    let bad_request = |message: &str| tus_response(HTTPStatus::BadRequest).text(message);
+    let (algorithm, digest) = value.trim().split_once(' ').ok_or_else(|| bad_request("Malformed Upload-Checksum header"))?;
+    if algorithm != "sha256" {
+        return Err(bad_request("Unsupported checksum algorithm, expected sha256"));
+    }
+    base64::decode(digest.trim())
+        .and_then(|digest| <[u8; 32]>::try_from(digest).ok())
+        .ok_or_else(|| bad_request("Malformed Upload-Checksum digest"))
+}
+
+fn file_sha256(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(hasher.finish()),
+            read => hasher.update(&buffer[..read])
+        }
+    }
+}
+
+/// Moves a complete upload into place, after verifying its checksum if one was given.
+fn finish(request: &Request, directory: &Path, id: &str, upload: &Upload, config: &Config) -> Result<(), Response> {
+    let data = directory.join(id);
+    if let Some(expected) = upload.checksum {
+        let actual = file_sha256(&data).map_err(|error| files::io_error_response(&error, &data))?;
+        if actual != expected {
+            remove(directory, id);
+            return Err(checksum_mismatch());
+        }
+    }
+    let destination = files::resolve_under(request, files_root(config)?, &upload.destination, config)?;
+    move_into_place(&data, &destination).map_err(|error| match error.kind() {
+        ErrorKind::AlreadyExists => destination_exists(),
+        _ => files::write_error_response(&error, &destination)
+    })?;
+    let _ = fs::remove_file(info_path(directory, id));
+    Ok(())
+}
+
+/// Moves the data of a complete upload to `destination`, which must not exist yet. It is
+/// linked there if both are on the same filesystem and copied otherwise.
+fn move_into_place(data: &Path, destination: &Path) -> io::Result<()> {
+    match fs::hard_link(data, destination) {
+        Ok(_) => {}
+        // Linking across filesystems fails with EXDEV
+        Err(error) if error.raw_os_error() == Some(18) => {
+            upload::write_atomically(destination, WriteMode::CreateOnly, false, |file| {
+                io::copy(&mut File::open(data)?, file).map(|_| ())
+            })?;
+        }
+        Err(error) => return Err(error)
+    }
+    fs::remove_file(data)
+}
+
+/// Uploads never replace existing files.
+fn destination_exists() -> Response {
+    tus_response(HTTPStatus::Conflict).text("A file already exists at the upload's destination")
+}
+
+/// `OPTIONS`: what this server supports of the protocol.
+pub fn options(_request: &Request, _config: &Config) -> Response {
+    tus_response(HTTPStatus::NoContent)
+        .header(HTTPHeader::TusVersion, TUS_VERSION)
+        .header(HTTPHeader::TusExtension, TUS_EXTENSIONS)
+        .header(HTTPHeader::TusChecksumAlgorithm, "sha256")
+}
+
+/// `POST`: creates an upload of `Upload-Length` bytes. The `filename` metadata names the
+/// destination below `files_path`, and an `Upload-Checksum` is verified on completion.
+pub fn create(request: &Request, config: &Config) -> Response {
+    if let Err(response) = check_version(request) {
+        return response;
+    }
+    if !request.path().strip_prefix(ROUTE_PREFIX).is_some_and(|rest| rest.is_empty() || rest == "/") {
+        return tus_response(HTTPStatus::NotFound);
+    }
+    if request.content_length() > 0 {
+        return tus_response(HTTPStatus::BadRequest).text("Uploading data on creation is not supported");
+    }
+    let Some(length) = request.get_known_header_line(HTTPHeader::UploadLength).and_then(|length| length.trim().parse::<u64>().ok()) else {
+        return tus_response(HTTPStatus::BadRequest).text("Missing or malformed Upload-Length header");
+    };
+    let metadata_header = request.get_known_header_line(HTTPHeader::UploadMetadata);
+    let metadata = match metadata_header.as_deref().map(parse_metadata) {
+        None => Vec::new(),
+        Some(Some(metadata)) => metadata,
+        Some(None) => return tus_response(HTTPStatus::BadRequest).text("Malformed Upload-Metadata header")
+    };
+    let checksum = match request.get_known_header_line(HTTPHeader::UploadChecksum) {
+        None => None,
+        Some(value) => match parse_checksum(&value) {
+            Ok(checksum) => Some(checksum),
+            Err(response) => return response
+        }
+    };
+    let (root, directory) = match files_root(config).and_then(|root| Ok((root, uploads_directory(config)?))) {
+        Ok(found) => found,
+        Err(response) => return response
+    };
+    remove_expired(&directory);
+    let id = match new_upload_id() {
+        Ok(id) => id,
+        Err(error) => {
+            eprintln!("Failed to create an upload id: {error}");
+            return tus_response(HTTPStatus::InternalServerError);
+        }
+    };
+
+    let filename = match metadata.iter().find(|(key, _)| key == "filename") {
+        None => id.clone(),
+        Some((_, value)) => match String::from_utf8(value.clone()) {
+            Ok(filename) if !filename.chars().any(char::is_control) => filename,
+            _ => return tus_response(HTTPStatus::BadRequest).text("Malformed filename metadata")
+        }
+    };
+    let destination = filename.split('/').map(percent_encode_segment).join("/");
+    let destination_path = match files::resolve_under(request, root, &destination, config) {
+        Ok(destination_path) => destination_path,
+        Err(response) => return response
+    };
+    // Caught before any data is sent rather than when the last chunk arrives
+    if fs::symlink_metadata(&destination_path).is_ok() {
+        return destination_exists();
+    }
+    if !destination_path.parent().is_some_and(Path::is_dir) {
+        return tus_response(HTTPStatus::Conflict).text("The upload's destination directory does not exist");
+    }
+
+    let upload = Upload {
+        length,
+        destination,
+        metadata: metadata_header,
+        checksum,
+        expires: SystemTime::now() + config.upload_expiry,
+    };
+    let data = directory.join(&id);
+    let created = OpenOptions::new().write(true).create_new(true).open(&data)
+        .and_then(|_| save(&directory, &id, &upload));
+    if let Err(error) = created {
+        remove(&directory, &id);
+        return files::write_error_response(&error, &data);
+    }
+    if length == 0 {
+        if let Err(response) = finish(request, &directory, &id, &upload, config) {
+            return response;
+        }
+    }
+
+    tus_response(HTTPStatus::Created)
+        .header(HTTPHeader::Location, format!("{ROUTE_PREFIX}/{id}"))
+        .header(HTTPHeader::UploadExpires, format_http_date(upload.expires))
+}
+
+/// `HEAD`: how many bytes of the upload the server has.
+pub fn head(request: &Request, config: &Config) -> Response {
+    if let Err(response) = check_version(request) {
+        return response;
+    }
+    let (id, directory) = match upload_id(request).and_then(|id| Ok((id, uploads_directory(config)?))) {
+        Ok(found) => found,
+        Err(response) => return response
+    };
+    let upload = match load(&directory, id) {
+        Ok(upload) => upload,
+        Err(response) => return response
+    };
+    let offset = match fs::metadata(directory.join(id)) {
+        Ok(metadata) => metadata.len(),
+        Err(error) => return files::io_error_response(&error, &directory)
+    };
+
+    let mut response = tus_response(HTTPStatus::Ok)
+        .header(HTTPHeader::UploadOffset, offset)
+        .header(HTTPHeader::UploadLength, upload.length)
+        .header(HTTPHeader::UploadExpires, format_http_date(upload.expires))
+        .header(HTTPHeader::CacheControl, "no-store");
+    if let Some(metadata) = &upload.metadata {
+        response = response.header(HTTPHeader::UploadMetadata, metadata);
+    }
+    response
+}
+
+/// Passes the chunk of a `PATCH` on to the upload's data file, hashing it along the way if
+/// the client sent an `Upload-Checksum`.
+struct ChunkWriter<'a> {
+    file: &'a mut File,
+    hasher: Option<Sha256>,
+}
+
+impl Write for ChunkWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// `PATCH`: appends the body at `Upload-Offset`, which has to match the current offset.
+/// The body is streamed to disk and has to fit into what is left of `Upload-Length`.
+/// An `Upload-Checksum` covers just this chunk. The final chunk moves the file into place.
+pub fn patch(request: &Request, config: &Config) -> Response {
+    if let Err(response) = check_version(request) {
+        return response;
+    }
+    let content_type = request.get_known_header_line(HTTPHeader::ContentType);
+    if content_type.as_deref().map(str::trim) != Some(OFFSET_CONTENT_TYPE) {
+        return tus_response(HTTPStatus::UnsupportedMediaType);
+    }
+    let Some(offset) = request.get_known_header_line(HTTPHeader::UploadOffset).and_then(|offset| offset.trim().parse::<u64>().ok()) else {
+        return tus_response(HTTPStatus::BadRequest).text("Missing or malformed Upload-Offset header");
+    };
+    let checksum = match request.get_known_header_line(HTTPHeader::UploadChecksum).as_deref().map(parse_checksum) {
+        None => None,
+        Some(Ok(checksum)) => Some(checksum),
+        Some(Err(response)) => return response
+    };
+    let (id, directory) = match upload_id(request).and_then(|id| Ok((id, uploads_directory(config)?))) {
+        Ok(found) => found,
+        Err(response) => return response
+    };
+    let Some(_busy) = BusyUpload::claim(id) else {
+        return tus_response(HTTPStatus::Locked).text("Another request is writing to this upload");
+    };
+    let mut upload = match load(&directory, id) {
+        Ok(upload) => upload,
+        Err(response) => return response
+    };
+
+    let data = directory.join(id);
+    let mut file = match OpenOptions::new().append(true).open(&data) {
+        Ok(file) => file,
+        Err(error) => return files::io_error_response(&error, &data)
+    };
+    let current = match file.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(error) => return files::io_error_response(&error, &data)
+    };
+    if offset != current {
+        return tus_response(HTTPStatus::Conflict).header(HTTPHeader::UploadOffset, current);
+    }
+    // Checked before anything is read from the connection
+    let new_offset = offset.saturating_add(request.content_length());
+    if new_offset > upload.length {
+        return tus_response(HTTPStatus::ContentTooLarge).text("Chunk exceeds the Upload-Length");
+    }
+    let mut body = match request.body_stream() {
+        Ok(body) => body,
+        Err(error) => return tus_response(Response::from(error).status)
+    };
+
+    let mut writer = ChunkWriter { file: &mut file, hasher: checksum.map(|_| Sha256::new()) };
+    let written = upload::copy_body(&mut body, &mut writer, request.content_length());
+    let digest = writer.hasher.map(Sha256::finish);
+    let failure = match written.and_then(|_| file.sync_data()) {
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => Some(tus_response(HTTPStatus::BadRequest).text("Request body ended early")),
+        Err(error) => Some(files::write_error_response(&error, &data)),
+        Ok(_) if digest != checksum => Some(checksum_mismatch()),
+        Ok(_) => None
+    };
+    if let Some(response) = failure {
+        // Cut off a partial or rejected append so the offset stays trustworthy
+        let _ = file.set_len(current);
+        return response;
+    }
+    drop(file);
+
+    if new_offset == upload.length {
+        if let Err(response) = finish(request, &directory, id, &upload, config) {
+            return response;
+        }
+        return tus_response(HTTPStatus::NoContent).header(HTTPHeader::UploadOffset, new_offset);
+    }
+    upload.expires = SystemTime::now() + config.upload_expiry;
+    if let Err(error) = save(&directory, id, &upload) {
+        return files::write_error_response(&error, &directory);
+    }
+    tus_response(HTTPStatus::NoContent)
+        .header(HTTPHeader::UploadOffset, new_offset)
+        .header(HTTPHeader::UploadExpires, format_http_date(upload.expires))
+}
+
+/// `DELETE`: abandons an upload and frees its space.
+pub fn terminate(request: &Request, config: &Config) -> Response {
+    if let Err(response) = check_version(request) {
+        return response;
+    }
+    let (id, directory) = match upload_id(request).and_then(|id| Ok((id, uploads_directory(config)?))) {
+        Ok(found) => found,
+        Err(response) => return response
+    };
+    let Some(_busy) = BusyUpload::claim(id) else {
+        return tus_response(HTTPStatus::Locked).text("Another request is writing to this upload");
+    };
+    if let Err(response) = load(&directory, id) {
+        return response;
+    }
+    remove(&directory, id);
+    tus_response(HTTPStatus::NoContent)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::files::scratch::ScratchDir;
+
+    use super::*;
+
+    struct Server {
+        files: ScratchDir,
+        _uploads: ScratchDir,
+        config: Config,
+    }
+
+    impl Server {
+        fn new() -> Server {
+            let files = ScratchDir::new();
+            let uploads = ScratchDir::new();
+            let mut config = Config::new("127.0.0.1", 4221, files.to_str().map(str::to_string));
+            config.uploads_path = uploads.to_path_buf();
+            Server { files, _uploads: uploads, config }
+        }
+
+        fn request(&self, method: &str, target: &str, headers: &[(&str, &str)], body: &str,
+                   handler: fn(&Request, &Config) -> Response) -> Response {
+            let headers: String = headers.iter().map(|(name, value)| format!("{name}: {value}\r\n")).collect();
+            let raw = format!("{method} {target} HTTP/1.1\r\nTus-Resumable: 1.0.0\r\n{headers}Content-Length: {length}\r\n\r\n{body}", length = body.len());
+            handler(&Request::new(io::Cursor::new(raw.into_bytes())).unwrap(), &self.config)
+        }
+
+        /// Creates an upload of `length` bytes for `filename` and returns its URL.
+        fn create(&self, length: u64, filename: &str, extra: &[(&str, &str)]) -> String {
+            let metadata = format!("filename {}", standard_base64(filename.as_bytes()));
+            let length = length.to_string();
+            let mut headers = vec![("Upload-Length", length.as_str()), ("Upload-Metadata", metadata.as_str())];
+            headers.extend_from_slice(extra);
+            let response = self.request("POST", ROUTE_PREFIX, &headers, "", create);
+            assert_eq!(response.status, HTTPStatus::Created);
+            header(&response, "Location").unwrap()
+        }
+
+        fn append(&self, location: &str, offset: u64, chunk: &str, extra: &[(&str, &str)]) -> Response {
+            let offset = offset.to_string();
+            let mut headers = vec![("Content-Type", OFFSET_CONTENT_TYPE), ("Upload-Offset", offset.as_str())];
+            headers.extend_from_slice(extra);
+            self.request("PATCH", location, &headers, chunk, patch)
+        }
+
+        fn offset(&self, location: &str) -> Response {
+            self.request("HEAD", location, &[], "", head)
+        }
+    }
+
+    fn header(response: &Response, name: &str) -> Option<String> {
+        response.headers.get(name).map(|values| values.join(", "))
+    }
+
+    fn status_and_offset(response: Response) -> (HTTPStatus, Option<String>) {
+        let offset = header(&response, "Upload-Offset");
+        (response.status, offset)
+    }
+
+    /// The `base64` module only encodes the URL safe alphabet.
+    fn standard_base64(bytes: &[u8]) -> String {
+        base64::encode_url(bytes).replace('-', "+").replace('_', "/")
+    }
+
+    fn sha256_checksum(content: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(content);
+        format!("sha256 {}", standard_base64(&hasher.finish()))
+    }
+
+    #[test]
+    fn uploads_in_chunks_and_moves_into_place() {
+        let server = Server::new();
+        let location = server.create(11, "hello.txt", &[]);
+        assert!(location.starts_with("/uploads/"));
+        let response = server.offset(&location);
+        assert_eq!(header(&response, "Upload-Length").as_deref(), Some("11"));
+        assert_eq!(status_and_offset(response), (HTTPStatus::Ok, Some("0".to_string())));
+
+        let response = server.append(&location, 0, "hello", &[]);
+        assert_eq!(status_and_offset(response), (HTTPStatus::NoContent, Some("5".to_string())));
+        assert_eq!(header(&server.offset(&location), "Upload-Offset").as_deref(), Some("5"));
+        assert!(!server.files.join("hello.txt").exists());
+
+        let response = server.append(&location, 5, " world", &[]);
+        assert_eq!(status_and_offset(response), (HTTPStatus::NoContent, Some("11".to_string())));
+        assert_eq!(fs::read_to_string(server.files.join("hello.txt")).unwrap(), "hello world");
+        assert_eq!(server.offset(&location).status, HTTPStatus::NotFound);
+    }
+
+    #[test]
+    fn refuses_bad_creations() {
+        let server = Server::new();
+        server.files.file("taken.txt", b"");
+        let create_with = |headers: &[(&str, &str)]| server.request("POST", ROUTE_PREFIX, headers, "", create).status;
+        assert_eq!(create_with(&[]), HTTPStatus::BadRequest);
+        assert_eq!(create_with(&[("Upload-Length", "-1")]), HTTPStatus::BadRequest);
+        assert_eq!(create_with(&[("Upload-Length", "1"), ("Upload-Metadata", "filename !!")]), HTTPStatus::BadRequest);
+        assert_eq!(create_with(&[("Upload-Length", "1"), ("Upload-Metadata", "filename dGFrZW4udHh0")]), HTTPStatus::Conflict);
+        assert_eq!(create_with(&[("Upload-Length", "1"), ("Upload-Metadata", "filename Li4vZXNjYXBl")]), HTTPStatus::Forbidden);
+        let raw = "POST /uploads HTTP/1.1\r\nUpload-Length: 1\r\n\r\n";
+        assert_eq!(create(&Request::new(raw.as_bytes()).unwrap(), &server.config).status, HTTPStatus::PreconditionFailed);
+    }
+
+    #[test]
+    fn rejects_patches_at_the_wrong_offset() {
+        let server = Server::new();
+        let location = server.create(10, "offset.txt", &[]);
+        let response = server.append(&location, 3, "abc", &[]);
+        assert_eq!(status_and_offset(response), (HTTPStatus::Conflict, Some("0".to_string())));
+        server.append(&location, 0, "abc", &[]);
+        let response = server.append(&location, 0, "abc", &[]);
+        assert_eq!(status_and_offset(response), (HTTPStatus::Conflict, Some("3".to_string())));
+        let plain = server.request("PATCH", &location, &[("Content-Type", "text/plain"), ("Upload-Offset", "3")], "def", patch);
+        assert_eq!(plain.status, HTTPStatus::UnsupportedMediaType);
+    }
+
+    #[test]
+    fn rejects_chunks_past_the_upload_length() {
+        let server = Server::new();
+        let location = server.create(4, "short.txt", &[]);
+        assert_eq!(server.append(&location, 0, "abc", &[]).status, HTTPStatus::NoContent);
+        assert_eq!(server.append(&location, 3, "de", &[]).status, HTTPStatus::ContentTooLarge);
+        assert_eq!(header(&server.offset(&location), "Upload-Offset").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn verifies_chunk_checksums() {
+        let server = Server::new();
+        let location = server.create(6, "checked.txt", &[]);
+        let wrong = sha256_checksum(b"other");
+        assert_eq!(server.append(&location, 0, "abc", &[("Upload-Checksum", &wrong)]).status.to_string(), "460 Checksum Mismatch");
+        assert_eq!(header(&server.offset(&location), "Upload-Offset").as_deref(), Some("0"));
+        let right = sha256_checksum(b"abc");
+        assert_eq!(server.append(&location, 0, "abc", &[("Upload-Checksum", &right)]).status, HTTPStatus::NoContent);
+        assert_eq!(server.append(&location, 3, "def", &[("Upload-Checksum", "md5 abc")]).status, HTTPStatus::BadRequest);
+    }
+
+    #[test]
+    fn verifies_the_whole_file_checksum() {
+        let server = Server::new();
+        let location = server.create(3, "whole.txt", &[("Upload-Checksum", &sha256_checksum(b"abc"))]);
+        assert_eq!(server.append(&location, 0, "abd", &[]).status.to_string(), "460 Checksum Mismatch");
+        assert!(!server.files.join("whole.txt").exists());
+        assert_eq!(server.offset(&location).status, HTTPStatus::NotFound);
+    }
+
+    #[test]
+    fn expires_idle_uploads() {
+        let mut server = Server::new();
+        server.config.upload_expiry = Duration::ZERO;
+        let location = server.create(3, "expired.txt", &[]);
+        assert_eq!(server.offset(&location).status, HTTPStatus::Gone);
+        assert_eq!(server.offset(&location).status, HTTPStatus::NotFound);
+        assert_eq!(fs::read_dir(&server.config.uploads_path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn terminates_uploads() {
+        let server = Server::new();
+        let location = server.create(3, "dropped.txt", &[]);
+        server.append(&location, 0, "a", &[]);
+        assert_eq!(server.request("DELETE", &location, &[], "", terminate).status, HTTPStatus::NoContent);
+        assert_eq!(server.offset(&location).status, HTTPStatus::NotFound);
+        assert_eq!(server.request("DELETE", &location, &[], "", terminate).status, HTTPStatus::NotFound);
+        assert_eq!(fs::read_dir(&server.config.uploads_path).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use crate::config::Config;
+use crate::files;
+use crate::http::request::{HTTPMethod, Request};
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+
+pub const INDEX_FILE: &str = "index.html";
+
+/// Paths whose last segment has an extension are treated as assets and never get the
+/// single-page-app fallback, so a missing `app.js` is still a 404.
+fn looks_like_asset(request_path: &str) -> bool {
+    request_path.rsplit('/').next().is_some_and(|segment| segment.contains('.'))
+}
+
+/// The configured custom 404 page, or an empty 404.
+fn not_found(root: &Path, config: &Config) -> Response {
+    match &config.not_found_page {
+        Some(page) if root.join(page).is_file() => files::serve_error_page(&root.join(page), HTTPStatus::NotFound, config),
+        _ => Response::not_found()
+    }
+}
+
+/// Serves `Config.site_root` as a static site: directories resolve to their `index.html`,
+/// `dir` redirects to `dir/`, and unknown pages get the custom 404 or the SPA entry point.
+pub fn serve(request: &Request, config: &Config) -> Response {
+    let root = match &config.site_root {
+        None => return Response::not_found(),
+        Some(root) => Path::new(root)
+    };
+    if !matches!(request.method, HTTPMethod::GET | HTTPMethod::HEAD) {
+        return Response::not_found();
+    }
+
+    let request_path = request.path();
+    let path = match files::resolve_under(request, root, request_path.trim_start_matches('/'), config) {
+        Ok(path) => path,
+        Err(response) => return response
+    };
+
+    if path.is_dir() {
+        if !request_path.ends_with('/') {
+            let location = match request.query_string() {
+                None => format!("{request_path}/"),
+                Some(query) => format!("{request_path}/?{query}")
+            };
+            return Response::redirect(&location).status(HTTPStatus::MovedPermanently);
+        }
+        let index = path.join(INDEX_FILE);
+        return if index.is_file() {
+            files::serve_file(request, &index, config)
+        } else if config.directory_listing {
+            files::serve_directory(request, &path, config)
+        } else {
+            not_found(root, config)
+        };
+    }
+    if path.is_file() {
+        return files::serve_file(request, &path, config);
+    }
+
+    let entry_point = root.join(INDEX_FILE);
+    if config.spa_fallback && !looks_like_asset(request_path) && entry_point.is_file() {
+        return files::serve_file(request, &entry_point, config);
+    }
+    not_found(root, config)
+}
@@ -0,0 +1,124 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// `ENOSPC`/`EDQUOT`, which `io::ErrorKind` only names on newer toolchains.
+const NO_SPACE_ERRORS: [i32; 2] = [28, 122];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Replace the file if it exists.
+    Replace,
+    /// Fail with `ErrorKind::AlreadyExists` if the file exists.
+    CreateOnly,
+}
+
+pub fn is_out_of_space(error: &io::Error) -> bool {
+    error.raw_os_error().is_some_and(|code| NO_SPACE_ERRORS.contains(&code))
+}
+
+/// Size of the chunks request bodies are copied to disk in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Hidden sibling of `path`, unique per process and call, so concurrent uploads never share one.
+fn temporary_path(path: &Path) -> io::Result<PathBuf> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let file_name = path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Upload path has no file name"))?;
+    let unique = COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok(path.with_file_name(format!(".{file_name}.upload-{pid}-{unique}", pid = process::id())))
+}
+
+/// Writes a file so that readers only ever see the old or the complete new content: `fill`
+/// writes into a temporary file next to `path`, which is synced and then renamed into place.
+/// Returns whether the file was newly created.
+pub fn write_atomically(path: &Path, mode: WriteMode, create_parents: bool, fill: impl FnOnce(&mut File) -> io::Result<()>) -> io::Result<bool> {
+    if let Some(parent) = path.parent() {
+        if create_parents {
+            fs::create_dir_all(parent)?;
+        } else if !parent.is_dir() {
+            return Err(io::Error::new(ErrorKind::NotFound, "Parent directory does not exist"));
+        }
+    }
+
+    let temporary = temporary_path(path)?;
+    let mut file = OpenOptions::new().write(true).create_new(true).open(&temporary)?;
+    let result = fill(&mut file)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| {
+            let existed = path.exists();
+            match mode {
+                WriteMode::Replace => fs::rename(&temporary, path).map(|_| !existed),
+                // Linking fails if the target exists, so two racing creators can't both win
+                WriteMode::CreateOnly => fs::hard_link(&temporary, path).map(|_| true)
+            }
+        });
+    if result.is_err() || mode == WriteMode::CreateOnly {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Copies `length` bytes of a request body from `body` to `file` a chunk at a time. A body that
+/// ends early or fails to read is reported as `ErrorKind::UnexpectedEof`, which tells a broken
+/// upload apart from a failing disk.
+pub fn copy_body(body: &mut impl Read, file: &mut impl Write, length: u64) -> io::Result<()> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut remaining = length;
+    while remaining > 0 {
+        let chunk = &mut buffer[..remaining.min(CHUNK_SIZE as u64) as usize];
+        let read = match body.read(chunk) {
+            Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "Request body ended early")),
+            Ok(read) => read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(io::Error::new(ErrorKind::UnexpectedEof, error))
+        };
+        file.write_all(&chunk[..read])?;
+        remaining -= read as u64;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::files::scratch::ScratchDir;
+
+    use super::*;
+
+    #[test]
+    fn copies_the_announced_length_in_chunks() {
+        let content = (0..3 * CHUNK_SIZE + 5).map(|index| index as u8).collect::<Vec<_>>();
+        let mut copied = Vec::new();
+        copy_body(&mut content.as_slice(), &mut copied, content.len() as u64 - 1).unwrap();
+        assert_eq!(copied, &content[..content.len() - 1]);
+    }
+
+    #[test]
+    fn reports_short_bodies_as_unexpected_eof() {
+        let error = copy_body(&mut &b"short"[..], &mut Vec::new(), 6).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn writes_atomically() {
+        let directory = ScratchDir::new();
+        let path = directory.join("nested/file.txt");
+        let fill = |content: &'static [u8]| move |file: &mut File| file.write_all(content);
+
+        assert_eq!(write_atomically(&path, WriteMode::Replace, false, fill(b"one")).unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(write_atomically(&path, WriteMode::Replace, true, fill(b"one")).unwrap());
+        assert!(!write_atomically(&path, WriteMode::Replace, false, fill(b"two")).unwrap());
+        assert_eq!(write_atomically(&path, WriteMode::CreateOnly, false, fill(b"three")).unwrap_err().kind(), ErrorKind::AlreadyExists);
+        let failing = write_atomically(&path, WriteMode::Replace, false, |_| Err(io::Error::new(ErrorKind::UnexpectedEof, "broken")));
+        assert_eq!(failing.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        assert_eq!(fs::read(&path).unwrap(), b"two");
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,41 @@
+/// Code points of windows-1252 bytes `0x80..=0x9F`; the rest of the range matches Latin-1.
+/// Undefined bytes map to the C1 control of the same value, as browsers do.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Character encodings text in request bodies can be decoded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Utf8,
+    /// Also what `iso-8859-1` and `us-ascii` labels mean in practice, per the WHATWG Encoding standard.
+    Windows1252,
+}
+
+impl Charset {
+    /// Resolves a `charset` parameter, `None` for unsupported encodings.
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "unicode-1-1-utf-8" => Some(Charset::Utf8),
+            "windows-1252" | "cp1252" | "x-cp1252" | "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1"
+            | "l1" | "us-ascii" | "ascii" => Some(Charset::Windows1252),
+            _ => None
+        }
+    }
+
+    /// Decodes `bytes`, replacing malformed sequences with U+FFFD.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Charset::Windows1252 => bytes.iter()
+                .map(|&byte| match byte {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[byte as usize - 0x80],
+                    byte => byte as char
+                })
+                .collect()
+        }
+    }
+}
@@ -0,0 +1,749 @@
+use crate::http::compression::huffman;
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+const HASH_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Shortest matches this far back take more bits than the literals they stand for.
+const TOO_FAR: usize = 4096;
+/// Marks empty hash chain entries.
+const NONE: u32 = u32::MAX;
+/// Blocks end after this many symbols or `WINDOW_SIZE` input bytes, whichever comes first.
+const BLOCK_SYMBOLS: usize = 16 * 1024;
+const MAX_STORED: usize = 65535;
+const END_OF_BLOCK: usize = 256;
+const MAX_CODE_LENGTH: usize = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: usize = 7;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order code length code lengths are sent in.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Match finder settings of a level, the same trade-offs zlib makes.
+#[derive(Debug, Clone, Copy)]
+struct Parameters {
+    /// Search less hard for a match after a match at least this long.
+    good_length: usize,
+    /// Don't look for a better match after one at least this long; 0 disables lazy matching.
+    max_lazy: usize,
+    /// Stop searching on a match at least this long.
+    nice_length: usize,
+    /// Candidates to try at most.
+    max_chain: usize,
+}
+
+impl Parameters {
+    fn for_level(level: u8) -> Self {
+        let (good_length, max_lazy, nice_length, max_chain) = match level {
+            1 => (4, 0, 8, 4),
+            2 => (4, 0, 16, 8),
+            3 => (4, 0, 32, 32),
+            4 => (4, 4, 16, 16),
+            5 => (8, 16, 32, 32),
+            6 => (8, 16, 128, 128),
+            7 => (8, 32, 128, 256),
+            8 => (32, 128, 258, 1024),
+            _ => (32, 258, 258, 4096)
+        };
+        Self { good_length, max_lazy, nice_length, max_chain }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Symbol {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+fn length_code(length: usize) -> usize {
+    LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1
+}
+
+fn distance_code(distance: usize) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+/// Packs bits into bytes starting with the least significant bit, as DEFLATE wants.
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u64,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Pads to a byte boundary with zero bits.
+    fn align(&mut self) {
+        if self.bit_count > 0 {
+            self.write(0, 8 - self.bit_count);
+        }
+    }
+}
+
+/// A Huffman code for one alphabet of a block.
+struct Code {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+}
+
+impl Code {
+    fn new(lengths: Vec<u8>) -> Self {
+        let codes = huffman::codes(&lengths);
+        Self { lengths, codes }
+    }
+
+    /// Built from `frequencies`. At least two symbols get codes: decoders reject codes
+    /// with a single symbol of length 1 in some places, complete codes everywhere.
+    fn from_frequencies(mut frequencies: Vec<u32>, max_length: usize) -> Self {
+        for symbol in 0..2 {
+            if frequencies.iter().filter(|&&frequency| frequency > 0).count() < 2 && frequencies[symbol] == 0 {
+                frequencies[symbol] = 1;
+            }
+        }
+        Code::new(huffman::code_lengths(&frequencies, max_length))
+    }
+
+    /// The code of RFC 1951 section 3.2.6 for literals and lengths.
+    fn fixed_literals() -> Self {
+        Code::new((0..288).map(|symbol| match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8
+        }).collect())
+    }
+
+    fn fixed_distances() -> Self {
+        Code::new(vec![5; 30])
+    }
+
+    fn write(&self, output: &mut BitWriter, symbol: usize) {
+        output.write(self.codes[symbol] as u32, self.lengths[symbol] as u32);
+    }
+
+    /// Bits taken by symbols with these frequencies, without extra bits.
+    fn cost(&self, frequencies: &[u32]) -> u64 {
+        frequencies.iter().zip(&self.lengths).map(|(&frequency, &length)| frequency as u64 * length as u64).sum()
+    }
+}
+
+/// Code lengths of both alphabets of a dynamic block, run-length encoded with symbols 16 to
+/// 18 (RFC 1951 section 3.2.7) as pairs of symbol and extra bits value.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut encoded = Vec::new();
+    let mut index = 0;
+    while index < lengths.len() {
+        let length = lengths[index];
+        let run = lengths[index..].iter().take_while(|&&other| other == length).count();
+        let mut remaining = run;
+        if length == 0 {
+            while remaining >= 11 {
+                let repeat = remaining.min(138);
+                encoded.push((18, (repeat - 11) as u8));
+                remaining -= repeat;
+            }
+            if remaining >= 3 {
+                encoded.push((17, (remaining - 3) as u8));
+                remaining = 0;
+            }
+        } else {
+            encoded.push((length, 0));
+            remaining -= 1;
+            while remaining >= 3 {
+                let repeat = remaining.min(6);
+                encoded.push((16, (repeat - 3) as u8));
+                remaining -= repeat;
+            }
+        }
+        for _ in 0..remaining {
+            encoded.push((length, 0));
+        }
+        index += run;
+    }
+    encoded
+}
+
+fn code_length_extra_bits(symbol: u8) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0
+    }
+}
+
+/// The tables of a dynamic block and what sending them costs.
+struct DynamicHeader {
+    literals: Code,
+    distances: Code,
+    literal_count: usize,
+    distance_count: usize,
+    code_lengths: Code,
+    code_length_count: usize,
+    encoded_lengths: Vec<(u8, u8)>,
+}
+
+impl DynamicHeader {
+    fn new(literal_frequencies: &[u32], distance_frequencies: &[u32]) -> Self {
+        let literals = Code::from_frequencies(literal_frequencies.to_vec(), MAX_CODE_LENGTH);
+        let distances = Code::from_frequencies(distance_frequencies.to_vec(), MAX_CODE_LENGTH);
+        let used = |code: &Code, minimum: usize| code.lengths.iter().rposition(|&length| length > 0).map_or(0, |last| last + 1).max(minimum);
+        let literal_count = used(&literals, 257);
+        let distance_count = used(&distances, 1);
+
+        let all_lengths = [&literals.lengths[..literal_count], &distances.lengths[..distance_count]].concat();
+        let encoded_lengths = run_length_encode(&all_lengths);
+        let mut frequencies = vec![0u32; 19];
+        for &(symbol, _) in &encoded_lengths {
+            frequencies[symbol as usize] += 1;
+        }
+        let code_lengths = Code::from_frequencies(frequencies, MAX_CODE_LENGTH_CODE_LENGTH);
+        let code_length_count = CODE_LENGTH_ORDER.iter()
+            .rposition(|&symbol| code_lengths.lengths[symbol] > 0)
+            .map_or(0, |last| last + 1)
+            .max(4);
+        Self { literals, distances, literal_count, distance_count, code_lengths, code_length_count, encoded_lengths }
+    }
+
+    fn cost(&self) -> u64 {
+        let lengths = self.encoded_lengths.iter()
+            .map(|&(symbol, _)| self.code_lengths.lengths[symbol as usize] as u64 + code_length_extra_bits(symbol) as u64)
+            .sum::<u64>();
+        5 + 5 + 4 + 3 * self.code_length_count as u64 + lengths
+    }
+
+    fn write(&self, output: &mut BitWriter) {
+        output.write((self.literal_count - 257) as u32, 5);
+        output.write((self.distance_count - 1) as u32, 5);
+        output.write((self.code_length_count - 4) as u32, 4);
+        for &symbol in &CODE_LENGTH_ORDER[..self.code_length_count] {
+            output.write(self.code_lengths.lengths[symbol] as u32, 3);
+        }
+        for &(symbol, extra) in &self.encoded_lengths {
+            self.code_lengths.write(output, symbol as usize);
+            output.write(extra as u32, code_length_extra_bits(symbol));
+        }
+    }
+}
+
+/// DEFLATE (RFC 1951) compressor. Input is pushed in pieces, compressed output collects in
+/// `output` until it is taken. Matches are found with hash chains over a 32 KiB window, lazily
+/// on levels 4 and up; each block is sent stored, with the fixed or with its own Huffman code,
+/// whatever is smallest.
+pub struct Deflater {
+    /// Level 0: no matching, only stored blocks.
+    store_only: bool,
+    parameters: Parameters,
+    /// Up to a window of history followed by input that isn't compressed yet.
+    buffer: Vec<u8>,
+    /// Next position in `buffer` to find a match at.
+    position: usize,
+    /// Start of the input not yet sent in a block.
+    block_start: usize,
+    /// Input bytes covered by `symbols`.
+    block_length: usize,
+    symbols: Vec<Symbol>,
+    /// Most recent position of each 3 byte hash.
+    head: Vec<u32>,
+    /// Previous position with the same hash, indexed by position modulo the window.
+    previous: Vec<u32>,
+    /// Lazy matching: the symbol for `position - 1` is still undecided.
+    pending: bool,
+    /// The match at `position - 1`, if there is one.
+    pending_match: Option<(usize, usize)>,
+    output: BitWriter,
+}
+
+impl Deflater {
+    pub fn new(level: u8) -> Self {
+        Self {
+            store_only: level == 0,
+            parameters: Parameters::for_level(level),
+            buffer: Vec::new(),
+            position: 0,
+            block_start: 0,
+            block_length: 0,
+            symbols: Vec::with_capacity(BLOCK_SYMBOLS),
+            head: vec![NONE; HASH_SIZE],
+            previous: vec![NONE; WINDOW_SIZE],
+            pending: false,
+            pending_match: None,
+            output: BitWriter::default(),
+        }
+    }
+
+    /// Appends bytes to the output as they are, e.g. a container header.
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.output.align();
+        self.output.bytes.extend_from_slice(bytes);
+    }
+
+    /// Complete bytes of compressed output so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output.bytes)
+    }
+
+    pub fn push(&mut self, input: &[u8]) {
+        self.buffer.extend_from_slice(input);
+        self.compress(false);
+    }
+
+    /// Compresses all input so far and ends the output on a byte boundary, so a reader
+    /// can decompress everything up to here (a zlib sync flush).
+    pub fn flush(&mut self) {
+        self.compress(true);
+        if self.block_length > 0 {
+            self.write_block(false);
+        }
+        // An empty stored block
+        self.output.write(0, 3);
+        self.output.align();
+        self.output.bytes.extend_from_slice(&[0, 0, 0xff, 0xff]);
+    }
+
+    /// Compresses all remaining input into the final block.
+    pub fn finish(&mut self) {
+        self.compress(true);
+        self.write_block(true);
+        self.output.align();
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let bytes = &self.buffer[position..position + MIN_MATCH];
+        (((bytes[0] as usize) << 10) ^ ((bytes[1] as usize) << 5) ^ bytes[2] as usize) & (HASH_SIZE - 1)
+    }
+
+    fn insert(&mut self, position: usize) {
+        if position + MIN_MATCH > self.buffer.len() {
+            return;
+        }
+        let hash = self.hash(position);
+        self.previous[position & WINDOW_MASK] = self.head[hash];
+        self.head[hash] = position as u32;
+    }
+
+    /// The longest match for `position` that is longer than `shorter_than`, as length and
+    /// distance. `position` has to be inserted already.
+    fn longest_match(&self, position: usize, max_chain: usize, shorter_than: usize) -> Option<(usize, usize)> {
+        let max_length = MAX_MATCH.min(self.buffer.len() - position);
+        let mut best_length = shorter_than.max(MIN_MATCH - 1);
+        if best_length >= max_length {
+            return None;
+        }
+        let mut best_distance = 0;
+        let mut candidate = self.previous[position & WINDOW_MASK];
+        let mut chain = max_chain;
+        while candidate != NONE && chain > 0 {
+            let candidate_position = candidate as usize;
+            let distance = position - candidate_position;
+            if distance >= WINDOW_SIZE {
+                break;
+            }
+            if self.buffer[candidate_position + best_length] == self.buffer[position + best_length] {
+                let length = self.buffer[candidate_position..candidate_position + max_length].iter()
+                    .zip(&self.buffer[position..position + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = distance;
+                    if length >= self.parameters.nice_length || length == max_length {
+                        break;
+                    }
+                }
+            }
+            // Entries older than the window may have been overwritten by newer positions
+            let next = self.previous[candidate_position & WINDOW_MASK];
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+        match best_distance {
+            0 => None,
+            distance if best_length == MIN_MATCH && distance > TOO_FAR => None,
+            distance => Some((best_length, distance))
+        }
+    }
+
+    fn emit(&mut self, symbol: Symbol) {
+        self.block_length += match symbol {
+            Symbol::Literal(_) => 1,
+            Symbol::Match { length, .. } => length as usize
+        };
+        self.symbols.push(symbol);
+        if self.symbols.len() >= BLOCK_SYMBOLS || self.block_length >= WINDOW_SIZE {
+            self.write_block(false);
+        }
+    }
+
+    fn emit_match(&mut self, length: usize, distance: usize) {
+        self.emit(Symbol::Match { length: length as u16, distance: distance as u16 });
+    }
+
+    /// Drops the oldest window of input once it is out of reach of matches and blocks.
+    fn slide(&mut self) {
+        if self.position < 2 * WINDOW_SIZE || self.block_start < WINDOW_SIZE {
+            return;
+        }
+        self.buffer.drain(..WINDOW_SIZE);
+        self.position -= WINDOW_SIZE;
+        self.block_start -= WINDOW_SIZE;
+        for entry in self.head.iter_mut().chain(self.previous.iter_mut()) {
+            *entry = match *entry {
+                NONE => NONE,
+                entry if (entry as usize) < WINDOW_SIZE => NONE,
+                entry => entry - WINDOW_SIZE as u32
+            };
+        }
+    }
+
+    /// Turns buffered input into symbols. Unless `drain` is set, the last `MAX_MATCH` bytes
+    /// wait for more input, so matches aren't cut short at the end of a piece of input.
+    fn compress(&mut self, drain: bool) {
+        if self.store_only {
+            while self.buffer.len() - self.block_start >= MAX_STORED {
+                self.block_length = MAX_STORED;
+                self.write_block(false);
+            }
+            self.buffer.drain(..self.block_start);
+            self.block_start = 0;
+            if drain {
+                self.block_length = self.buffer.len();
+            }
+            return;
+        }
+
+        let lookahead = match drain {
+            true => 1,
+            false => MAX_MATCH
+        };
+        while self.position + lookahead <= self.buffer.len() {
+            self.slide();
+            let position = self.position;
+            self.insert(position);
+            if self.parameters.max_lazy == 0 {
+                match self.longest_match(position, self.parameters.max_chain, 0) {
+                    Some((length, distance)) => {
+                        self.emit_match(length, distance);
+                        for inner in position + 1..position + length {
+                            self.insert(inner);
+                        }
+                        self.position += length;
+                    }
+                    None => {
+                        self.emit(Symbol::Literal(self.buffer[position]));
+                        self.position += 1;
+                    }
+                }
+                continue;
+            }
+
+            let previous_length = self.pending_match.map_or(0, |(length, _)| length);
+            let current_match = match previous_length < self.parameters.max_lazy {
+                true => {
+                    let max_chain = match previous_length >= self.parameters.good_length {
+                        true => self.parameters.max_chain / 4,
+                        false => self.parameters.max_chain
+                    };
+                    self.longest_match(position, max_chain, previous_length)
+                }
+                false => None
+            };
+            match (self.pending_match, current_match) {
+                // The match before is at least as good, take it
+                (Some((length, distance)), None) => {
+                    self.emit_match(length, distance);
+                    for inner in position + 1..position - 1 + length {
+                        self.insert(inner);
+                    }
+                    self.position = position - 1 + length;
+                    self.pending = false;
+                    self.pending_match = None;
+                }
+                (_, current_match) => {
+                    if self.pending {
+                        self.emit(Symbol::Literal(self.buffer[position - 1]));
+                    }
+                    self.pending = true;
+                    self.pending_match = current_match;
+                    self.position = position + 1;
+                }
+            }
+        }
+
+        if drain && self.pending {
+            match self.pending_match {
+                Some((length, distance)) => {
+                    self.emit_match(length, distance);
+                    self.position += length - 1;
+                }
+                None => self.emit(Symbol::Literal(self.buffer[self.position - 1]))
+            }
+            self.pending = false;
+            self.pending_match = None;
+        }
+    }
+
+    fn write_stored(&mut self, last: bool) {
+        let data = &self.buffer[self.block_start..self.block_start + self.block_length];
+        let chunk_count = data.len().div_ceil(MAX_STORED).max(1);
+        for index in 0..chunk_count {
+            let chunk = &data[(index * MAX_STORED).min(data.len())..((index + 1) * MAX_STORED).min(data.len())];
+            self.output.write((last && index == chunk_count - 1) as u32, 1);
+            self.output.write(0, 2);
+            self.output.align();
+            self.output.bytes.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+            self.output.bytes.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+            self.output.bytes.extend_from_slice(chunk);
+        }
+    }
+
+    fn write_symbols(&mut self, literals: &Code, distances: &Code) {
+        for &symbol in &self.symbols {
+            match symbol {
+                Symbol::Literal(byte) => literals.write(&mut self.output, byte as usize),
+                Symbol::Match { length, distance } => {
+                    let length_code = length_code(length as usize);
+                    literals.write(&mut self.output, 257 + length_code);
+                    self.output.write((length - LENGTH_BASE[length_code]) as u32, LENGTH_EXTRA[length_code] as u32);
+                    let distance_code = distance_code(distance as usize);
+                    distances.write(&mut self.output, distance_code);
+                    self.output.write((distance - DISTANCE_BASE[distance_code]) as u32, DISTANCE_EXTRA[distance_code] as u32);
+                }
+            }
+        }
+        literals.write(&mut self.output, END_OF_BLOCK);
+    }
+
+    /// Sends the current symbols as a block in whichever form is smallest.
+    fn write_block(&mut self, last: bool) {
+        if self.store_only {
+            self.write_stored(last);
+        } else {
+            let mut literal_frequencies = vec![0u32; 286];
+            let mut distance_frequencies = vec![0u32; 30];
+            let mut extra_bits = 0u64;
+            for &symbol in &self.symbols {
+                match symbol {
+                    Symbol::Literal(byte) => literal_frequencies[byte as usize] += 1,
+                    Symbol::Match { length, distance } => {
+                        let length_code = length_code(length as usize);
+                        let distance_code = distance_code(distance as usize);
+                        literal_frequencies[257 + length_code] += 1;
+                        distance_frequencies[distance_code] += 1;
+                        extra_bits += LENGTH_EXTRA[length_code] as u64 + DISTANCE_EXTRA[distance_code] as u64;
+                    }
+                }
+            }
+            literal_frequencies[END_OF_BLOCK] += 1;
+
+            let dynamic = DynamicHeader::new(&literal_frequencies, &distance_frequencies);
+            let dynamic_cost = 3 + dynamic.cost() + dynamic.literals.cost(&literal_frequencies) + dynamic.distances.cost(&distance_frequencies) + extra_bits;
+            let fixed_literals = Code::fixed_literals();
+            let fixed_distances = Code::fixed_distances();
+            let fixed_cost = 3 + fixed_literals.cost(&literal_frequencies) + fixed_distances.cost(&distance_frequencies) + extra_bits;
+            // Header and padding of each stored block, at most
+            let stored_cost = (self.block_length.div_ceil(MAX_STORED).max(1) * (3 + 7 + 32) + 8 * self.block_length) as u64;
+
+            if stored_cost < dynamic_cost.min(fixed_cost) {
+                self.write_stored(last);
+            } else if dynamic_cost < fixed_cost {
+                self.output.write(last as u32, 1);
+                self.output.write(2, 2);
+                dynamic.write(&mut self.output);
+                self.write_symbols(&dynamic.literals, &dynamic.distances);
+            } else {
+                self.output.write(last as u32, 1);
+                self.output.write(1, 2);
+                self.write_symbols(&fixed_literals, &fixed_distances);
+            }
+        }
+        self.symbols.clear();
+        self.block_start += self.block_length;
+        self.block_length = 0;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Reads bits starting with the least significant one of each byte.
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bits(&mut self, count: u8) -> usize {
+            (0..count).fold(0, |value, index| {
+                let bit = (self.data[self.position / 8] >> (self.position % 8)) & 1;
+                self.position += 1;
+                value | (bit as usize) << index
+            })
+        }
+
+        /// Decodes a symbol of the canonical code with `lengths`, one bit at a time.
+        fn symbol(&mut self, lengths: &[u8]) -> usize {
+            let (mut code, mut first) = (0, 0);
+            for length in 1..=MAX_CODE_LENGTH as u8 {
+                code |= self.bits(1);
+                let mut symbols = (0..lengths.len()).filter(|&symbol| lengths[symbol] == length);
+                let count = symbols.clone().count();
+                if code < first + count {
+                    return symbols.nth(code - first).unwrap();
+                }
+                first = (first + count) << 1;
+                code <<= 1;
+            }
+            panic!("Invalid Huffman code");
+        }
+    }
+
+    /// Straightforward DEFLATE decoder, to check the encoder against.
+    pub fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut reader = BitReader { data, position: 0 };
+        let mut output = Vec::new();
+        loop {
+            let last = reader.bits(1) == 1;
+            let (literals, distances) = match reader.bits(2) {
+                0 => {
+                    let start = reader.position.div_ceil(8);
+                    let length = u16::from_le_bytes([data[start], data[start + 1]]) as usize;
+                    assert_eq!(!u16::from_le_bytes([data[start + 2], data[start + 3]]) as usize, length);
+                    output.extend_from_slice(&data[start + 4..start + 4 + length]);
+                    reader.position = (start + 4 + length) * 8;
+                    match last {
+                        true => return output,
+                        false => continue
+                    }
+                }
+                1 => (Code::fixed_literals().lengths, Code::fixed_distances().lengths),
+                2 => {
+                    let literal_count = reader.bits(5) + 257;
+                    let distance_count = reader.bits(5) + 1;
+                    let mut code_length_lengths = [0; 19];
+                    for &symbol in &CODE_LENGTH_ORDER[..reader.bits(4) + 4] {
+                        code_length_lengths[symbol] = reader.bits(3) as u8;
+                    }
+                    let mut lengths = Vec::new();
+                    while lengths.len() < literal_count + distance_count {
+                        match reader.symbol(&code_length_lengths) {
+                            16 => {
+                                let previous = *lengths.last().unwrap();
+                                lengths.resize(lengths.len() + 3 + reader.bits(2), previous);
+                            }
+                            17 => lengths.resize(lengths.len() + 3 + reader.bits(3), 0),
+                            18 => lengths.resize(lengths.len() + 11 + reader.bits(7), 0),
+                            length => lengths.push(length as u8)
+                        }
+                    }
+                    let distances = lengths.split_off(literal_count);
+                    (lengths, distances)
+                }
+                _ => panic!("Reserved block type")
+            };
+            loop {
+                let symbol = reader.symbol(&literals);
+                match symbol {
+                    0..=255 => output.push(symbol as u8),
+                    END_OF_BLOCK => break,
+                    _ => {
+                        let code = symbol - 257;
+                        let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code]);
+                        let code = reader.symbol(&distances);
+                        let distance = DISTANCE_BASE[code] as usize + reader.bits(DISTANCE_EXTRA[code]);
+                        for _ in 0..length {
+                            output.push(output[output.len() - distance]);
+                        }
+                    }
+                }
+            }
+            if last {
+                return output;
+            }
+        }
+    }
+
+    fn deflate(input: &[u8], level: u8) -> Vec<u8> {
+        let mut deflater = Deflater::new(level);
+        for chunk in input.chunks(10_000) {
+            deflater.push(chunk);
+        }
+        deflater.finish();
+        deflater.take_output()
+    }
+
+    /// Deterministic bytes that don't compress.
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..length).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect()
+    }
+
+    pub fn samples() -> Vec<Vec<u8>> {
+        let text = "It was the best of times, it was the worst of times, it was the age of wisdom. ".repeat(2000);
+        vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"abcabcabcabcabcabcabcabc".to_vec(),
+            vec![0; 300_000],
+            text.into_bytes(),
+            noise(150_000),
+        ]
+    }
+
+    #[test]
+    fn round_trips_at_every_level() {
+        for level in 0..=9 {
+            for sample in samples() {
+                assert_eq!(inflate(&deflate(&sample, level)), sample, "level {level}, {} bytes", sample.len());
+            }
+        }
+    }
+
+    #[test]
+    fn compresses_repetitive_input() {
+        let sample = vec![b'x'; 100_000];
+        assert!(deflate(&sample, 1).len() < 1000);
+        assert!(deflate(&sample, 0).len() > sample.len());
+    }
+
+    #[test]
+    fn flushed_output_decodes_without_the_rest() {
+        let mut deflater = Deflater::new(6);
+        deflater.push(b"hello hello hello");
+        deflater.flush();
+        let mut output = deflater.take_output();
+        // A final empty stored block ends the stream at the flush point
+        output.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        assert_eq!(inflate(&output), b"hello hello hello");
+    }
+}
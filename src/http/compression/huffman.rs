@@ -0,0 +1,124 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Code lengths of a Huffman code for `frequencies` that is at most `max_length` bits long,
+/// 0 for symbols that don't occur.
+pub fn code_lengths(frequencies: &[u32], max_length: usize) -> Vec<u8> {
+    let mut lengths = vec![0u8; frequencies.len()];
+    let mut symbols = (0..frequencies.len()).filter(|&symbol| frequencies[symbol] > 0).collect::<Vec<_>>();
+    if symbols.len() == 1 {
+        lengths[symbols[0]] = 1;
+    }
+    if symbols.len() < 2 {
+        return lengths;
+    }
+
+    // Plain Huffman tree: leaves are `0..symbols.len()`, inner nodes follow in creation order
+    let mut parents = vec![0usize; symbols.len()];
+    let mut heap = symbols.iter().enumerate()
+        .map(|(leaf, &symbol)| Reverse((frequencies[symbol] as u64, leaf)))
+        .collect::<BinaryHeap<_>>();
+    while let (Some(Reverse((weight_a, a))), Some(Reverse((weight_b, b)))) = (heap.pop(), heap.pop()) {
+        let node = parents.len();
+        parents.push(node);
+        parents[a] = node;
+        parents[b] = node;
+        heap.push(Reverse((weight_a + weight_b, node)));
+    }
+    let mut depths = vec![0usize; parents.len()];
+    for node in (0..parents.len() - 1).rev() {
+        depths[node] = depths[parents[node]] + 1;
+    }
+
+    // Limit the length by moving leaves up, keeping the code complete (Kraft sum of exactly 1)
+    let mut counts = vec![0u32; max_length + 1];
+    for &depth in &depths[..symbols.len()] {
+        counts[depth.min(max_length)] += 1;
+    }
+    let mut kraft_sum = (1..=max_length).map(|length| (counts[length] as u64) << (max_length - length)).sum::<u64>();
+    while kraft_sum > 1 << max_length {
+        counts[max_length] -= 1;
+        if let Some(length) = (1..max_length).rev().find(|&length| counts[length] > 0) {
+            counts[length] -= 1;
+            counts[length + 1] += 2;
+        }
+        kraft_sum -= 1;
+    }
+
+    // The most frequent symbols get the shortest codes
+    symbols.sort_by_key(|&symbol| Reverse(frequencies[symbol]));
+    let mut symbols = symbols.into_iter();
+    for (length, &count) in counts.iter().enumerate().skip(1) {
+        for symbol in symbols.by_ref().take(count as usize) {
+            lengths[symbol] = length as u8;
+        }
+    }
+    lengths
+}
+
+/// The canonical codes for `lengths` (RFC 1951 section 3.2.2), bit-reversed since DEFLATE
+/// writes them starting with the most significant bit into a stream filled from the least.
+pub fn codes(lengths: &[u8]) -> Vec<u16> {
+    let max_length = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut counts = vec![0u16; max_length + 1];
+    for &length in lengths.iter().filter(|&&length| length > 0) {
+        counts[length as usize] += 1;
+    }
+    let mut next_code = vec![0u16; max_length + 1];
+    let mut code = 0u16;
+    for length in 1..=max_length {
+        code = (code + counts[length - 1]) << 1;
+        next_code[length] = code;
+    }
+    lengths.iter()
+        .map(|&length| match length {
+            0 => 0,
+            length => {
+                let code = next_code[length as usize];
+                next_code[length as usize] += 1;
+                code.reverse_bits() >> (16 - length)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn kraft_sum(lengths: &[u8], max_length: usize) -> u64 {
+        lengths.iter().filter(|&&length| length > 0).map(|&length| 1u64 << (max_length - length as usize)).sum()
+    }
+
+    #[test]
+    fn builds_optimal_lengths() {
+        assert_eq!(code_lengths(&[5, 0, 1, 1, 2], 15), [1, 0, 3, 3, 2]);
+        assert_eq!(code_lengths(&[0, 7, 0], 15), [0, 1, 0]);
+        assert_eq!(code_lengths(&[0, 0], 15), [0, 0]);
+    }
+
+    #[test]
+    fn limits_lengths_and_keeps_the_code_complete() {
+        // Fibonacci frequencies make the deepest possible tree
+        let mut frequencies = vec![1u32, 1];
+        while frequencies.len() < 30 {
+            frequencies.push(frequencies[frequencies.len() - 1] + frequencies[frequencies.len() - 2]);
+        }
+        let lengths = code_lengths(&frequencies, 7);
+        assert!(lengths.iter().all(|&length| (1..=7).contains(&length)));
+        assert_eq!(kraft_sum(&lengths, 7), 1 << 7);
+    }
+
+    #[test]
+    fn assigns_canonical_codes() {
+        // The example of RFC 1951 section 3.2.2, before bit reversal
+        let lengths = [3, 3, 3, 3, 3, 2, 4, 4];
+        let expected = [0b010, 0b011, 0b100, 0b101, 0b110, 0b00, 0b1110, 0b1111];
+        let reversed = codes(&lengths).iter().zip(lengths)
+            .map(|(&code, length)| code.reverse_bits() >> (16 - length))
+            .collect::<Vec<_>>();
+        assert_eq!(reversed, expected);
+    }
+}
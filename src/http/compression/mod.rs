@@ -0,0 +1,303 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::hash::{adler32, crc32};
+use crate::http::compression::deflate::Deflater;
+
+mod deflate;
+mod huffman;
+
+/// Input is compressed in pieces of at most this size, bounding the memory an encoder holds.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How DEFLATE data (RFC 1951) is wrapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Bare DEFLATE data, for callers that frame it themselves.
+    // Neither HTTP content coding uses it
+    #[allow(dead_code)]
+    Deflate,
+    /// zlib (RFC 1950), which is what the `deflate` content coding means in HTTP.
+    Zlib,
+    /// gzip (RFC 1952), the `gzip` content coding.
+    Gzip,
+}
+
+/// Compression level from 0, storing the data as is, to 9, the smallest output at the
+/// highest cost. Levels mean the same as with zlib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level(u8);
+
+impl Level {
+    pub const DEFAULT: Level = Level(6);
+
+    pub fn new(level: u8) -> Option<Self> {
+        (level <= 9).then_some(Level(level))
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().ok()
+            .and_then(Level::new)
+            .ok_or_else(|| format!("Invalid compression level `{s}`, expected 0 to 9"))
+    }
+}
+
+/// Compresses everything written to it into `inner`. Call `finish` at the end, which writes
+/// the rest of the data and the trailer and hands `inner` back; `flush` makes everything
+/// written so far decompressible without ending the stream.
+pub struct Encoder<W: Write> {
+    inner: W,
+    format: Format,
+    deflater: Deflater,
+    /// CRC-32 for gzip, Adler-32 for zlib.
+    checksum: u32,
+    /// Input size modulo 2^32, for the gzip trailer.
+    length: u32,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W, format: Format, level: Level) -> Self {
+        let mut deflater = Deflater::new(level.0);
+        let checksum = match format {
+            Format::Deflate => 0,
+            Format::Zlib => {
+                // 32 KiB window, and the level in the two bits reserved for it
+                let compression_method = 0x78u16;
+                let level_bits = match level.0 {
+                    0..=1 => 0,
+                    2..=5 => 1,
+                    6 => 2,
+                    _ => 3
+                };
+                let header = (compression_method << 8) | (level_bits << 6);
+                let header = header + (31 - header % 31) % 31;
+                deflater.write_raw(&header.to_be_bytes());
+                1
+            }
+            Format::Gzip => {
+                let extra_flags = match level.0 {
+                    9 => 2,
+                    1 => 4,
+                    _ => 0
+                };
+                // Magic, DEFLATE, no flags, no modification time, unknown operating system
+                deflater.write_raw(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, extra_flags, 255]);
+                0
+            }
+        };
+        Self { inner, format, deflater, checksum, length: 0 }
+    }
+
+    fn write_output(&mut self) -> io::Result<()> {
+        let output = self.deflater.take_output();
+        self.inner.write_all(&output)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.deflater.finish();
+        match self.format {
+            Format::Deflate => {}
+            Format::Zlib => self.deflater.write_raw(&self.checksum.to_be_bytes()),
+            Format::Gzip => self.deflater.write_raw(&[self.checksum.to_le_bytes(), self.length.to_le_bytes()].concat())
+        }
+        self.write_output()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Output of earlier input goes first, so an error means nothing of `buf` was taken
+        self.write_output()?;
+        let input = &buf[..buf.len().min(CHUNK_SIZE)];
+        match self.format {
+            Format::Deflate => {}
+            Format::Zlib => self.checksum = adler32(self.checksum, input),
+            Format::Gzip => self.checksum = crc32(self.checksum, input)
+        }
+        self.length = self.length.wrapping_add(input.len() as u32);
+        self.deflater.push(input);
+        Ok(input.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.deflater.flush();
+        self.write_output()?;
+        self.inner.flush()
+    }
+}
+
+/// Compresses `data` in one go.
+pub fn compress(data: &[u8], format: Format, level: Level) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::with_capacity(data.len() / 2), format, level);
+    // Writing into a `Vec` cannot fail
+    let _ = encoder.write_all(data);
+    encoder.finish().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use std::io::ErrorKind;
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    use super::*;
+    use crate::hash::to_hex;
+    use crate::http::compression::deflate::tests::{inflate, samples};
+
+    const TEXT: &[u8] = b"Hello, hello, hello! Compression works best on repetitive text, repetitive text.";
+
+    #[test]
+    fn wraps_gzip() {
+        let output = compress(TEXT, Format::Gzip, Level::new(9).unwrap());
+        assert_eq!(output[..10], [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 2, 255]);
+        let (data, trailer) = output[10..].split_at(output.len() - 18);
+        assert_eq!(inflate(data), TEXT);
+        assert_eq!(trailer[..4], crc32(0, TEXT).to_le_bytes());
+        assert_eq!(trailer[4..], (TEXT.len() as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn wraps_zlib() {
+        for level in 0..=9 {
+            let output = compress(TEXT, Format::Zlib, Level::new(level).unwrap());
+            assert_eq!(output[0], 0x78);
+            assert_eq!(u16::from_be_bytes([output[0], output[1]]) % 31, 0);
+            let (data, trailer) = output[2..].split_at(output.len() - 6);
+            assert_eq!(inflate(data), TEXT);
+            assert_eq!(trailer, adler32(1, TEXT).to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn streams_in_pieces() {
+        let input = TEXT.repeat(5000);
+        let mut encoder = Encoder::new(Vec::new(), Format::Gzip, Level::DEFAULT);
+        for piece in input.chunks(777) {
+            encoder.write_all(piece).unwrap();
+        }
+        encoder.flush().unwrap();
+        let output = encoder.finish().unwrap();
+        assert_eq!(inflate(&output[10..output.len() - 8]), input);
+        assert_eq!(output[output.len() - 4..], (input.len() as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn leaves_deflate_unwrapped() {
+        for level in 0..=9 {
+            let level = Level::new(level).unwrap();
+            let output = compress(TEXT, Format::Deflate, level);
+            assert_eq!(inflate(&output), TEXT);
+            let zlib = compress(TEXT, Format::Zlib, level);
+            assert_eq!(output, zlib[2..zlib.len() - 4]);
+        }
+    }
+
+    /// Output of Python's `zlib.compress(input, level)`, and of `gzip.compress(input, level)`
+    /// after its header. Where DEFLATE leaves no choice, stored blocks and lone literals, the
+    /// output has to be the same.
+    #[test]
+    fn matches_zlib_where_the_output_is_canonical() {
+        let vectors: [(&[u8], u8, &str, &str); 5] = [
+            (b"", 0, "7801010000ffff00000001", "010000ffff0000000000000000"),
+            (b"", 6, "789c030000000001", "03000000000000000000"),
+            (b"a", 0, "7801010100feff6100620062", "010100feff6143beb7e801000000"),
+            (b"a", 6, "789c4b040000620062", "4b040043beb7e801000000"),
+            (b"hello", 6, "789ccb48cdc9c90700062c0215", "cb48cdc9c9070086a6103605000000"),
+        ];
+        for (input, level, zlib, gzip) in vectors {
+            let level = Level::new(level).unwrap();
+            assert_eq!(to_hex(&compress(input, Format::Zlib, level)), zlib);
+            assert_eq!(to_hex(&compress(input, Format::Gzip, level)[10..]), gzip);
+            assert_eq!(to_hex(&compress(input, Format::Deflate, level)), zlib[4..zlib.len() - 8]);
+        }
+    }
+
+    /// Dynamic Huffman block from Python's `zlib.compressobj(9, zlib.DEFLATED, -15)`, so the
+    /// decoder the other tests rely on is checked against a real encoder too.
+    #[test]
+    fn test_decoder_reads_zlib_output() {
+        const BOTTLES: &str = "95cc4b0a84301045d179afe22d40c4bf661fbd8188258a212526e2f6ed790f8a37be97e31c66cd394882ae98452e6844de048f0fa180fbeb25befe90df2558f489054e9f12f60c7fe91d97f2e326839c787234c8912707831c78b237c89e273b83ec78b235c896271b836c78b236c89a272b83ac68f205";
+        let compressed = (0..BOTTLES.len()).step_by(2)
+            .map(|index| u8::from_str_radix(&BOTTLES[index..index + 2], 16).unwrap())
+            .collect::<Vec<_>>();
+        let expected = (90..=99).rev()
+            .map(|n| format!("{n} bottles of beer on the wall, {n} bottles of beer. Take one down, pass it around.\n"))
+            .collect::<String>();
+        assert_eq!(inflate(&compressed), expected.as_bytes());
+    }
+
+    /// Runs an external decoder on `input`, `None` if it isn't installed.
+    fn decode_externally(program: &str, arguments: &[&str], input: Vec<u8>) -> Option<Vec<u8>> {
+        let mut child = match Command::new(program).args(arguments)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn() {
+            Ok(child) => child,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                eprintln!("{program} is not installed, skipping");
+                return None;
+            }
+            Err(error) => panic!("Failed to run {program}: {error}")
+        };
+        // Written from another thread, the decoder's output would fill its pipe otherwise
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output().unwrap();
+        writer.join().unwrap().unwrap();
+        assert!(output.status.success(), "{program}: {}", String::from_utf8_lossy(&output.stderr));
+        Some(output.stdout)
+    }
+
+    fn python_zlib(window_bits: &str, input: Vec<u8>) -> Option<Vec<u8>> {
+        let script = "import sys, zlib; sys.stdout.buffer.write(zlib.decompress(sys.stdin.buffer.read(), int(sys.argv[1])))";
+        decode_externally("python3", &["-c", script, window_bits], input)
+    }
+
+    #[test]
+    fn python_zlib_decodes_every_format() {
+        // One stream of all samples, starting a decoder per sample takes long
+        let input = samples().concat();
+        for level in [0, 1, 6, 9] {
+            let level = Level::new(level).unwrap();
+            for (format, window_bits) in [(Format::Deflate, "-15"), (Format::Zlib, "15"), (Format::Gzip, "31")] {
+                let Some(decoded) = python_zlib(window_bits, compress(&input, format, level)) else {
+                    return;
+                };
+                assert!(decoded == input, "{format:?} at level {level:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn gzip_decodes_streamed_output() {
+        let input = samples().concat();
+        let mut encoder = Encoder::new(Vec::new(), Format::Gzip, Level::DEFAULT);
+        for (index, piece) in input.chunks(100_000).enumerate() {
+            encoder.write_all(piece).unwrap();
+            if index % 2 == 0 {
+                encoder.flush().unwrap();
+            }
+        }
+        let output = encoder.finish().unwrap();
+        if let Some(decoded) = decode_externally("gzip", &["-d", "-c"], output) {
+            assert!(decoded == input);
+        }
+    }
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!("0".parse(), Ok(Level(0)));
+        assert_eq!("9".parse(), Ok(Level(9)));
+        assert!("10".parse::<Level>().is_err());
+        assert!("fast".parse::<Level>().is_err());
+    }
+}
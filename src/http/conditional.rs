@@ -0,0 +1,233 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::http::date::parse_http_date;
+use crate::http::headers::HTTPHeader;
+use crate::http::request::Request;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityTag {
+    pub weak: bool,
+    pub tag: String,
+}
+
+impl EntityTag {
+    pub fn strong(tag: &str) -> Self {
+        Self { weak: false, tag: tag.to_string() }
+    }
+
+    pub fn weak(tag: &str) -> Self {
+        Self { weak: true, tag: tag.to_string() }
+    }
+
+    /// Both tags are strong and identical.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Tags are identical, ignoring weakness.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Parses a comma separated list of entity tags. `None` stands for `*`.
+    fn parse_list(value: &str) -> Option<Vec<EntityTag>> {
+        if value.trim() == "*" {
+            return None;
+        }
+        let mut tags = Vec::new();
+        let mut rest = value;
+        loop {
+            rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+            if rest.is_empty() {
+                break;
+            }
+            let (weak, quoted) = match rest.strip_prefix("W/") {
+                Some(quoted) => (true, quoted),
+                None => (false, rest)
+            };
+            let Some(quoted) = quoted.strip_prefix('"') else {
+                // Not a valid entity tag, skip to the next list member
+                rest = rest.split_once(',').map_or("", |(_, rest)| rest);
+                continue;
+            };
+            let Some((tag, after)) = quoted.split_once('"') else {
+                break;
+            };
+            tags.push(EntityTag { weak, tag: tag.to_string() });
+            rest = after;
+        }
+        Some(tags)
+    }
+}
+
+impl Display for EntityTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.weak {
+            true => write!(f, "W/\"{}\"", self.tag),
+            false => write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+/// Validators of the current representation of a resource.
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<EntityTag>,
+    pub last_modified: Option<SystemTime>,
+    /// Whether the resource currently exists, which is what `If-Match: *` asks about.
+    pub exists: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Precondition {
+    /// Carry on with the request.
+    Proceed,
+    /// Answer with `304 Not Modified`.
+    NotModified,
+    /// Answer with `412 Precondition Failed`.
+    Failed,
+}
+
+/// HTTP dates have a resolution of one second.
+pub fn truncate_to_seconds(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => UNIX_EPOCH + Duration::from_secs(duration.as_secs()),
+        Err(_) => time
+    }
+}
+
+fn matches_any(condition: Option<Vec<EntityTag>>, validators: &Validators, strong: bool) -> bool {
+    match condition {
+        None => validators.exists,
+        Some(tags) => match &validators.etag {
+            None => false,
+            Some(etag) => tags.iter().any(|tag| if strong { tag.strong_eq(etag) } else { tag.weak_eq(etag) })
+        }
+    }
+}
+
+/// Evaluates the conditional request headers in the order RFC 9110, section 13.2.2 prescribes.
+/// `safe` is true for GET and HEAD, which get `304` where other methods get `412`.
+pub fn evaluate(request: &Request, validators: &Validators, safe: bool) -> Precondition {
+    let last_modified = validators.last_modified.map(truncate_to_seconds);
+
+    if let Some(if_match) = request.get_known_header_line(HTTPHeader::IfMatch) {
+        if !matches_any(EntityTag::parse_list(&if_match), validators, true) {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = request.get_known_header_line(HTTPHeader::IfUnmodifiedSince).and_then(|value| parse_http_date(&value)) {
+        // Without a modification date there is nothing to compare, so the header is ignored
+        if last_modified.is_some_and(|modified| modified > since) {
+            return Precondition::Failed;
+        }
+    }
+
+    if let Some(if_none_match) = request.get_known_header_line(HTTPHeader::IfNoneMatch) {
+        if matches_any(EntityTag::parse_list(&if_none_match), validators, false) {
+            return if safe { Precondition::NotModified } else { Precondition::Failed };
+        }
+    } else if safe {
+        if let Some(since) = request.get_known_header_line(HTTPHeader::IfModifiedSince).and_then(|value| parse_http_date(&value)) {
+            if last_modified.is_some_and(|modified| modified <= since) {
+                return Precondition::NotModified;
+            }
+        }
+    }
+
+    Precondition::Proceed
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const OLDER: &str = "Sun, 06 Nov 1994 08:49:36 GMT";
+    const NEWER: &str = "Sun, 06 Nov 1994 08:49:38 GMT";
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        let headers: String = headers.iter().map(|(name, value)| format!("{name}: {value}\r\n")).collect();
+        Request::new(std::io::Cursor::new(format!("GET /file HTTP/1.1\r\n{headers}\r\n").into_bytes())).unwrap()
+    }
+
+    fn validators(etag: Option<EntityTag>) -> Validators {
+        // Between OLDER and NEWER, with a fraction of a second that must not count
+        let last_modified = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap() + Duration::from_millis(500);
+        Validators { etag, last_modified: Some(last_modified), exists: true }
+    }
+
+    fn evaluate_headers(headers: &[(&str, &str)], validators: &Validators, safe: bool) -> Precondition {
+        evaluate(&request(headers), validators, safe)
+    }
+
+    #[test]
+    fn parses_entity_tag_lists() {
+        assert_eq!(EntityTag::parse_list(" * "), None);
+        assert_eq!(EntityTag::parse_list(r#""a", W/"b",bogus, "c""#),
+                   Some(vec![EntityTag::strong("a"), EntityTag::weak("b"), EntityTag::strong("c")]));
+        assert_eq!(EntityTag::parse_list(r#""a", "unterminated"#), Some(vec![EntityTag::strong("a")]));
+    }
+
+    #[test]
+    fn if_match_uses_strong_comparison() {
+        let strong = validators(Some(EntityTag::strong("v1")));
+        let weak = validators(Some(EntityTag::weak("v1")));
+        assert_eq!(evaluate_headers(&[("If-Match", r#""v1""#)], &strong, false), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-Match", r#"W/"v1""#)], &strong, false), Precondition::Failed);
+        assert_eq!(evaluate_headers(&[("If-Match", r#""v1""#)], &weak, false), Precondition::Failed);
+        assert_eq!(evaluate_headers(&[("If-Match", r#""v0", "v1""#)], &strong, false), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-Match", "*")], &strong, false), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-Match", "*")], &Validators::default(), false), Precondition::Failed);
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let strong = validators(Some(EntityTag::strong("v1")));
+        let weak = validators(Some(EntityTag::weak("v1")));
+        assert_eq!(evaluate_headers(&[("If-None-Match", r#"W/"v1""#)], &strong, true), Precondition::NotModified);
+        assert_eq!(evaluate_headers(&[("If-None-Match", r#""v1""#)], &weak, true), Precondition::NotModified);
+        assert_eq!(evaluate_headers(&[("If-None-Match", r#""v1""#)], &weak, false), Precondition::Failed);
+        assert_eq!(evaluate_headers(&[("If-None-Match", r#""v2""#)], &strong, true), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-None-Match", "*")], &Validators::default(), false), Precondition::Proceed);
+    }
+
+    #[test]
+    fn if_match_takes_precedence_over_if_unmodified_since() {
+        let validators = validators(Some(EntityTag::strong("v1")));
+        assert_eq!(evaluate_headers(&[("If-Match", r#""v1""#), ("If-Unmodified-Since", OLDER)], &validators, false), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-Match", r#""v2""#), ("If-Unmodified-Since", NEWER)], &validators, false), Precondition::Failed);
+        assert_eq!(evaluate_headers(&[("If-Unmodified-Since", OLDER)], &validators, false), Precondition::Failed);
+        assert_eq!(evaluate_headers(&[("If-Unmodified-Since", NEWER)], &validators, false), Precondition::Proceed);
+    }
+
+    #[test]
+    fn if_unmodified_since_is_ignored_without_last_modified() {
+        let validators = Validators { etag: Some(EntityTag::strong("v1")), last_modified: None, exists: true };
+        assert_eq!(evaluate_headers(&[("If-Unmodified-Since", OLDER)], &validators, false), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-Unmodified-Since", "not a date")], &validators, false), Precondition::Proceed);
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence_over_if_modified_since() {
+        let validators = validators(Some(EntityTag::strong("v1")));
+        assert_eq!(evaluate_headers(&[("If-None-Match", r#""v2""#), ("If-Modified-Since", NEWER)], &validators, true), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-None-Match", r#""v1""#), ("If-Modified-Since", OLDER)], &validators, true), Precondition::NotModified);
+        assert_eq!(evaluate_headers(&[("If-Modified-Since", NEWER)], &validators, true), Precondition::NotModified);
+        assert_eq!(evaluate_headers(&[("If-Modified-Since", OLDER)], &validators, true), Precondition::Proceed);
+    }
+
+    #[test]
+    fn if_modified_since_only_applies_to_safe_methods() {
+        let validators = validators(None);
+        assert_eq!(evaluate_headers(&[("If-Modified-Since", NEWER)], &validators, false), Precondition::Proceed);
+        assert_eq!(evaluate_headers(&[("If-Modified-Since", NEWER)], &Validators::default(), true), Precondition::Proceed);
+    }
+
+    #[test]
+    fn failed_if_match_wins_over_if_none_match() {
+        let validators = validators(Some(EntityTag::strong("v1")));
+        assert_eq!(evaluate_headers(&[("If-Match", r#""v2""#), ("If-None-Match", r#""v1""#)], &validators, true), Precondition::Failed);
+    }
+}
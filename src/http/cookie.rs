@@ -0,0 +1,290 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use crate::http::date::format_http_date;
+
+/// Cookies sent by the client in `Cookie` headers, in the order they appear.
+/// Names may repeat, e.g. when cookies with different paths match the request.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Cookies {
+    pairs: Vec<(String, String)>,
+}
+
+impl Cookies {
+    /// Parses `name=value` pairs separated by `;` (RFC 6265 section 5.4), one header line at a
+    /// time. Pairs without `=` or with an empty name are ignored, quotes around values removed.
+    pub fn parse<'a>(lines: impl IntoIterator<Item=&'a str>) -> Self {
+        let pairs = lines.into_iter()
+            .flat_map(|line| line.split(';'))
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let name = name.trim();
+                let value = value.trim();
+                let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')).unwrap_or(value);
+                (!name.is_empty()).then(|| (name.to_string(), value.to_string()))
+            })
+            .collect();
+        Self { pairs }
+    }
+
+    /// Values of the cookie `name`, which is matched case-sensitively.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a str> {
+        self.pairs.iter().filter(move |(pair_name, _)| pair_name == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let same_site = match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None"
+        };
+        write!(f, "{}", same_site)
+    }
+}
+
+impl FromStr for SameSite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err(format!("Unknown SameSite mode `{s}`, expected one of: strict, lax, none"))
+        }
+    }
+}
+
+/// Why a `SetCookie` couldn't be built. Anything it rejects would change how clients split
+/// the header into cookies and attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieError {
+    /// Names have to be non-empty tokens.
+    Name(String),
+    /// Values have to be cookie-octets, optionally in double quotes. Carries the cookie name.
+    Value(String),
+    /// Attribute values may contain neither `;` nor controls.
+    Attribute { attribute: &'static str, value: String },
+}
+
+impl Display for CookieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CookieError::Name(name) => write!(f, "Invalid cookie name `{name}`"),
+            CookieError::Value(name) => write!(f, "Invalid value for cookie `{name}`"),
+            CookieError::Attribute { attribute, value } => write!(f, "Invalid cookie {attribute} `{value}`")
+        }
+    }
+}
+
+/// Builder for the value of a `Set-Cookie` header (RFC 6265 section 4.1). Names, values
+/// and the `Path` and `Domain` attributes are checked as they are set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+fn is_token_character(c: char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c)
+}
+
+fn is_cookie_octet(c: char) -> bool {
+    c.is_ascii_graphic() && !"\",;\\".contains(c)
+}
+
+fn is_valid_value(value: &str) -> bool {
+    let unquoted = match value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        Some(unquoted) => unquoted,
+        None => value
+    };
+    unquoted.chars().all(is_cookie_octet)
+}
+
+/// Attribute values end at the next `;` and may not contain controls.
+fn check_attribute(attribute: &'static str, value: String) -> Result<String, CookieError> {
+    match value.contains(';') || value.chars().any(|c| c.is_control()) {
+        true => Err(CookieError::Attribute { attribute, value }),
+        false => Ok(value)
+    }
+}
+
+impl SetCookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Result<Self, CookieError> {
+        let name = name.into();
+        let value = value.into();
+        if name.is_empty() || !name.chars().all(is_token_character) {
+            return Err(CookieError::Name(name));
+        }
+        if !is_valid_value(&value) {
+            return Err(CookieError::Value(name));
+        }
+        Ok(Self {
+            name,
+            value,
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        })
+    }
+
+    /// Turns the cookie into one that makes clients delete the stored cookie of its name.
+    /// Path and domain have to match the ones it was set with.
+    pub fn removal(mut self) -> Self {
+        self.value.clear();
+        self.max_age(Duration::ZERO).expires(SystemTime::UNIX_EPOCH)
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Result<Self, CookieError> {
+        self.path = Some(check_attribute("Path", path.into())?);
+        Ok(self)
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Result<Self, CookieError> {
+        self.domain = Some(check_attribute("Domain", domain.into())?);
+        Ok(self)
+    }
+
+    /// Lifetime in whole seconds; takes precedence over `Expires` in clients that know it.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    /// Clients reject `SameSite=None` on cookies that aren't `Secure`, so it implies `Secure`.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self.secure |= same_site == SameSite::None;
+        self
+    }
+}
+
+impl Display for SetCookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", format_http_date(expires))?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={same_site}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_cookie_headers() {
+        let cookies = Cookies::parse(["a=1; b=\"two\"; junk; =empty", "a=3;c="]);
+        assert_eq!(cookies.get_all("a").collect::<Vec<_>>(), ["1", "3"]);
+        assert_eq!(cookies.get_all("b").collect::<Vec<_>>(), ["two"]);
+        assert_eq!(cookies.get_all("c").collect::<Vec<_>>(), [""]);
+        assert_eq!(cookies.get_all("A").count(), 0);
+    }
+
+    #[test]
+    fn serializes_attributes_in_order() {
+        let cookie = SetCookie::new("id", "\"ab\"").unwrap()
+            .path("/admin").unwrap()
+            .domain("example.com").unwrap()
+            .max_age(Duration::from_secs(60))
+            .expires(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777))
+            .http_only()
+            .same_site(SameSite::Strict);
+        assert_eq!(
+            cookie.to_string(),
+            "id=\"ab\"; Path=/admin; Domain=example.com; Max-Age=60; Expires=Sun, 06 Nov 1994 08:49:37 GMT; HttpOnly; SameSite=Strict"
+        );
+    }
+
+    #[test]
+    fn same_site_none_implies_secure() {
+        let cookie = SetCookie::new("a", "1").unwrap().same_site(SameSite::None);
+        assert_eq!(cookie.to_string(), "a=1; Secure; SameSite=None");
+    }
+
+    #[test]
+    fn removal_clears_the_value() {
+        let cookie = SetCookie::new("a", "1").unwrap().path("/").unwrap().removal();
+        assert_eq!(cookie.to_string(), "a=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn rejects_what_would_split_the_header() {
+        assert_eq!(SetCookie::new("", "1"), Err(CookieError::Name(String::new())));
+        assert_eq!(SetCookie::new("a=b", "1"), Err(CookieError::Name("a=b".to_string())));
+        assert_eq!(SetCookie::new("a", "1;b=2"), Err(CookieError::Value("a".to_string())));
+        assert_eq!(SetCookie::new("a", "x y"), Err(CookieError::Value("a".to_string())));
+        assert_eq!(
+            SetCookie::new("a", "1").unwrap().path("/; Domain=evil.example"),
+            Err(CookieError::Attribute { attribute: "Path", value: "/; Domain=evil.example".to_string() })
+        );
+        assert_eq!(
+            SetCookie::new("a", "1").unwrap().domain("example.com\r\n").unwrap_err().to_string(),
+            "Invalid cookie Domain `example.com\r\n`"
+        );
+    }
+
+    #[test]
+    fn parses_same_site_modes() {
+        assert_eq!("lax".parse(), Ok(SameSite::Lax));
+        assert!("Lax".parse::<SameSite>().is_err());
+    }
+}
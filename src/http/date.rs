@@ -0,0 +1,174 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Converts days since 1970-01-01 into a proleptic Gregorian `(year, month, day)`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Converts a proleptic Gregorian date into days since 1970-01-01.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn parse_month(month: &str) -> Option<u32> {
+    MONTHS.iter().position(|name| name.eq_ignore_ascii_case(month)).map(|index| index as u32 + 1)
+}
+
+fn parse_time_of_day(time: &str) -> Option<i64> {
+    let mut parts = time.split(':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(hour * 3600 + minute * 60 + second)
+}
+
+/// Parses the three date formats HTTP allows: IMF-fixdate, the obsolete RFC 850 format
+/// and ANSI C's `asctime()` format. Returns `None` for anything else.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let parts = value.split_whitespace().collect::<Vec<_>>();
+    let (year, month, day, time) = match parts.as_slice() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => (year.parse::<i64>().ok()?, parse_month(month)?, day.parse::<u32>().ok()?, *time),
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut date_parts = date.split('-');
+            let (day, month, year) = (date_parts.next()?, date_parts.next()?, date_parts.next()?);
+            let year = year.parse::<i64>().ok()?;
+            // Two digit years more than 50 years in the future are taken to be in the past
+            let year = if year < 100 { if year < 70 { 2000 + year } else { 1900 + year } } else { year };
+            (year, parse_month(month)?, day.parse::<u32>().ok()?, *time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => (year.parse::<i64>().ok()?, parse_month(month)?, day.parse::<u32>().ok()?, *time),
+        _ => return None
+    };
+    let days = days_from_civil(year, month, day);
+    // Rejects days past the end of the month, which would otherwise roll over into the next
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    let seconds = days * 86_400 + parse_time_of_day(time)?;
+    if seconds < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(seconds as u64))
+}
+
+/// Formats a timestamp as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(error) => -(error.duration().as_secs() as i64)
+    };
+    let days = seconds.div_euclid(86_400);
+    let seconds_of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{weekday}, {day:02} {month} {year:04} {hour:02}:{minute:02}:{second:02} GMT",
+        weekday = WEEKDAYS[days.rem_euclid(7) as usize],
+        month = MONTHS[month as usize - 1],
+        hour = seconds_of_day / 3600,
+        minute = seconds_of_day % 3600 / 60,
+        second = seconds_of_day % 60,
+    )
+}
+
+/// Formats a timestamp as an RFC 3339 date-time in UTC, e.g. `1994-11-06T08:49:37Z`.
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(error) => -(error.duration().as_secs() as i64)
+    };
+    let seconds_of_day = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z",
+        hour = seconds_of_day / 3600,
+        minute = seconds_of_day % 3600 / 60,
+        second = seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// 1994-11-06T08:49:37Z, the example used throughout RFC 9110.
+    fn example() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(784_111_777)
+    }
+
+    #[test]
+    fn parses_all_three_formats() {
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(example()));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(example()));
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(example()));
+    }
+
+    #[test]
+    fn maps_two_digit_years_around_1970() {
+        assert_eq!(parse_http_date("Thursday, 01-Jan-70 00:00:00 GMT"), Some(UNIX_EPOCH));
+        assert_eq!(parse_http_date("Saturday, 01-Jan-00 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(946_684_800)));
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for value in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Tue, 29 Feb 2022 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+        ] {
+            assert_eq!(parse_http_date(value), None, "{value}");
+        }
+        assert!(parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT").is_some());
+    }
+
+    #[test]
+    fn formats_imf_fixdate() {
+        assert_eq!(format_http_date(example()), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH + Duration::from_secs(951_782_400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+    }
+
+    #[test]
+    fn formatting_round_trips() {
+        for seconds in [0, 68_169_600, 784_111_777, 951_782_399, 4_102_444_800] {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+            assert_eq!(parse_http_date(&format_http_date(time)), Some(time));
+        }
+    }
+
+    #[test]
+    fn formats_rfc3339() {
+        assert_eq!(format_rfc3339(example()), "1994-11-06T08:49:37Z");
+    }
+}
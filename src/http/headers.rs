@@ -1,25 +1,211 @@
 use std::fmt::Display;
 
-#[derive(Hash, Eq, PartialEq)]
+#[derive(Debug, Hash, Eq, PartialEq)]
 pub enum HTTPHeader {
+    Accept,
     UserAgent,
     AcceptEncoding,
     ContentType,
     ContentLength,
     ContentEncoding,
+    TransferEncoding,
+    Date,
+    Server,
+    Location,
+    XContentTypeOptions,
+    ETag,
+    LastModified,
+    IfMatch,
+    IfNoneMatch,
+    IfModifiedSince,
+    IfUnmodifiedSince,
+    Range,
+    IfRange,
+    AcceptRanges,
+    ContentRange,
+    Depth,
+    Destination,
+    Overwrite,
+    LockToken,
+    If,
+    Timeout,
+    Dav,
+    Allow,
+    CacheControl,
+    TusResumable,
+    TusVersion,
+    TusExtension,
+    TusChecksumAlgorithm,
+    UploadOffset,
+    UploadLength,
+    UploadMetadata,
+    UploadExpires,
+    UploadChecksum,
+    Cookie,
+    SetCookie,
+    AcceptLanguage,
+    AcceptCharset,
+    Vary,
 }
 
 impl Display for HTTPHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let header_string = match self {
+            HTTPHeader::Accept => "Accept".to_string(),
             HTTPHeader::UserAgent => "User-Agent".to_string(),
             HTTPHeader::AcceptEncoding => "Accept-Encoding".to_string(),
             HTTPHeader::ContentType => "Content-Type".to_string(),
             HTTPHeader::ContentLength => "Content-Length".to_string(),
-            HTTPHeader::ContentEncoding => "Content-Encoding".to_string()
+            HTTPHeader::ContentEncoding => "Content-Encoding".to_string(),
+            HTTPHeader::TransferEncoding => "Transfer-Encoding".to_string(),
+            HTTPHeader::Date => "Date".to_string(),
+            HTTPHeader::Server => "Server".to_string(),
+            HTTPHeader::Location => "Location".to_string(),
+            HTTPHeader::XContentTypeOptions => "X-Content-Type-Options".to_string(),
+            HTTPHeader::ETag => "ETag".to_string(),
+            HTTPHeader::LastModified => "Last-Modified".to_string(),
+            HTTPHeader::IfMatch => "If-Match".to_string(),
+            HTTPHeader::IfNoneMatch => "If-None-Match".to_string(),
+            HTTPHeader::IfModifiedSince => "If-Modified-Since".to_string(),
+            HTTPHeader::IfUnmodifiedSince => "If-Unmodified-Since".to_string(),
+            HTTPHeader::Range => "Range".to_string(),
+            HTTPHeader::IfRange => "If-Range".to_string(),
+            HTTPHeader::AcceptRanges => "Accept-Ranges".to_string(),
+            HTTPHeader::ContentRange => "Content-Range".to_string(),
+            HTTPHeader::Depth => "Depth".to_string(),
+            HTTPHeader::Destination => "Destination".to_string(),
+            HTTPHeader::Overwrite => "Overwrite".to_string(),
+            HTTPHeader::LockToken => "Lock-Token".to_string(),
+            HTTPHeader::If => "If".to_string(),
+            HTTPHeader::Timeout => "Timeout".to_string(),
+            HTTPHeader::Dav => "DAV".to_string(),
+            HTTPHeader::Allow => "Allow".to_string(),
+            HTTPHeader::CacheControl => "Cache-Control".to_string(),
+            HTTPHeader::TusResumable => "Tus-Resumable".to_string(),
+            HTTPHeader::TusVersion => "Tus-Version".to_string(),
+            HTTPHeader::TusExtension => "Tus-Extension".to_string(),
+            HTTPHeader::TusChecksumAlgorithm => "Tus-Checksum-Algorithm".to_string(),
+            HTTPHeader::UploadOffset => "Upload-Offset".to_string(),
+            HTTPHeader::UploadLength => "Upload-Length".to_string(),
+            HTTPHeader::UploadMetadata => "Upload-Metadata".to_string(),
+            HTTPHeader::UploadExpires => "Upload-Expires".to_string(),
+            HTTPHeader::UploadChecksum => "Upload-Checksum".to_string(),
+            HTTPHeader::Cookie => "Cookie".to_string(),
+            HTTPHeader::SetCookie => "Set-Cookie".to_string(),
+            HTTPHeader::AcceptLanguage => "Accept-Language".to_string(),
+            HTTPHeader::AcceptCharset => "Accept-Charset".to_string(),
+            HTTPHeader::Vary => "Vary".to_string()
         };
         write!(f, "{}", header_string)
     }
 }
 
-pub type HeaderMap = std::collections::HashMap<String, Vec<String>>;
+/// Whether the values of a header may be joined into a single comma separated field line.
+/// `Set-Cookie` values contain commas themselves (`Expires`), so each one needs its own line,
+/// and `Cookie` separates its pairs with `"; "` while values may contain commas.
+pub fn is_combinable(header_name: &str) -> bool {
+    !header_name.eq_ignore_ascii_case("Set-Cookie") && !header_name.eq_ignore_ascii_case("Cookie")
+}
+
+/// Header fields in insertion order. Names are matched case-insensitively but
+/// are kept in the casing they were first inserted with.
+#[derive(Debug, Default, Clone)]
+pub struct HeaderMap {
+    entries: Vec<(String, Vec<String>)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|(entry_name, _)| entry_name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Vec<String>> {
+        self.position(name).map(|index| &self.entries[index].1)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    /// Adds values after any already present for `name`.
+    pub fn append(&mut self, name: String, values: Vec<String>) {
+        match self.position(&name) {
+            None => self.entries.push((name, values)),
+            Some(index) => self.entries[index].1.extend(values)
+        }
+    }
+
+    /// Replaces all values of `name`, keeping its original position.
+    pub fn insert(&mut self, name: String, values: Vec<String>) {
+        match self.position(&name) {
+            None => self.entries.push((name, values)),
+            Some(index) => self.entries[index].1 = values
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Vec<String>> {
+        self.position(name).map(|index| self.entries.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&String, &Vec<String>)> {
+        self.entries.iter().map(|(name, values)| (name, values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn values(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn names(headers: &HeaderMap) -> Vec<&str> {
+        headers.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn append_adds_values_under_the_first_casing() {
+        let mut headers = HeaderMap::new();
+        headers.append("Accept".to_string(), values(&["text/html"]));
+        headers.append("Vary".to_string(), values(&["Cookie"]));
+        headers.append("accept".to_string(), values(&["*/*"]));
+        assert_eq!(names(&headers), ["Accept", "Vary"]);
+        assert_eq!(headers.get("ACCEPT"), Some(&values(&["text/html", "*/*"])));
+    }
+
+    #[test]
+    fn insert_replaces_values_in_place() {
+        let mut headers = HeaderMap::new();
+        headers.append("Content-Type".to_string(), values(&["text/plain"]));
+        headers.append("Date".to_string(), values(&["today"]));
+        headers.insert("content-type".to_string(), values(&["text/html"]));
+        headers.insert("Server".to_string(), values(&["test"]));
+        assert_eq!(names(&headers), ["Content-Type", "Date", "Server"]);
+        assert_eq!(headers.get("Content-Type"), Some(&values(&["text/html"])));
+    }
+
+    #[test]
+    fn remove_returns_the_values() {
+        let mut headers = HeaderMap::new();
+        headers.append("Set-Cookie".to_string(), values(&["a=1", "b=2"]));
+        headers.append("Date".to_string(), values(&["today"]));
+        assert_eq!(headers.remove("set-cookie"), Some(values(&["a=1", "b=2"])));
+        assert_eq!(headers.remove("Set-Cookie"), None);
+        assert!(!headers.contains("Set-Cookie"));
+        assert_eq!(names(&headers), ["Date"]);
+    }
+
+    #[test]
+    fn cookies_are_not_combinable() {
+        assert!(!is_combinable("set-cookie"));
+        assert!(!is_combinable("Cookie"));
+        assert!(is_combinable("Vary"));
+    }
+}
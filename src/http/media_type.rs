@@ -0,0 +1,71 @@
+/// A parsed `Content-Type`-like value: `type/subtype` followed by `; name=value` parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+    /// Lowercased `type/subtype`, e.g. `multipart/form-data`.
+    pub essence: String,
+    pub parameters: Vec<(String, String)>,
+}
+
+impl MediaType {
+    pub fn parse(value: &str) -> Option<Self> {
+        let (essence, parameters) = value.split_once(';').unwrap_or((value, ""));
+        let essence = essence.trim().to_ascii_lowercase();
+        match essence.split_once('/') {
+            Some((main, sub)) if !main.is_empty() && !sub.is_empty() => {}
+            _ => return None
+        }
+        Some(Self { essence, parameters: parse_parameters(parameters) })
+    }
+
+    /// Value of the parameter `name`, matched case-insensitively.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.iter()
+            .find(|(parameter, _)| parameter.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parses `; name=value` parameters as used by `Content-Type` and `Content-Disposition`.
+/// Values may be quoted strings with backslash escapes; names are lowercased.
+pub fn parse_parameters(input: &str) -> Vec<(String, String)> {
+    let mut parameters = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ';' || c.is_ascii_whitespace());
+        let Some(equals) = rest.find([';', '=']) else {
+            break;
+        };
+        if rest[equals..].starts_with(';') {
+            // A parameter without a value
+            rest = &rest[equals..];
+            continue;
+        }
+        let name = rest[..equals].trim().to_ascii_lowercase();
+        rest = rest[equals + 1..].trim_start();
+
+        let mut value = String::new();
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut characters = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((index, c)) = characters.next() {
+                match c {
+                    '"' => {
+                        end = index + 1;
+                        break;
+                    }
+                    '\\' => value.extend(characters.next().map(|(_, escaped)| escaped)),
+                    c => value.push(c)
+                }
+            }
+            rest = &quoted[end..];
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            value.push_str(rest[..end].trim());
+            rest = &rest[end..];
+        }
+        if !name.is_empty() {
+            parameters.push((name, value));
+        }
+    }
+    parameters
+}
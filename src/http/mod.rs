@@ -1,11 +1,26 @@
+use std::fs::File;
 use std::str::FromStr;
-use crate::http::headers::HTTPHeader;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
 use crate::http::request::HTTPMethod;
 
 pub mod request;
 pub mod response;
+pub mod status;
 pub mod headers;
 pub mod compression;
+pub mod date;
+pub mod sendfile;
+pub mod url;
+pub mod conditional;
+pub mod range;
+pub mod media_type;
+pub mod multipart;
+pub mod charset;
+pub mod cookie;
+pub mod negotiation;
 
 struct RequestLine {
     http_method: HTTPMethod,
@@ -19,38 +34,107 @@ struct RequestLine {
 #[derive(Debug)]
 pub struct BodyTransformError;
 
-#[derive(Debug, Default, Clone)]
+/// Response or request content. In-memory content is a shared `Bytes` buffer, so cloning a
+/// `Body` never copies it; file-backed content is streamed straight from the file when sent.
+#[derive(Debug, Clone)]
 pub struct Body {
-    content: Vec<u8>,
+    content: BodyContent,
+}
+
+#[derive(Debug, Clone)]
+enum BodyContent {
+    Memory(Bytes),
+    File(FileRegion),
+    /// Bodies sent one after another, e.g. the parts of a `multipart/byteranges` response.
+    Parts(Vec<Body>),
+}
+
+/// A piece of a body as it is written out.
+#[derive(Debug, Clone, Copy)]
+pub enum Segment<'a> {
+    Memory(&'a [u8]),
+    File(&'a FileRegion),
+}
+
+/// `length` bytes of `file` starting at `offset`.
+#[derive(Debug, Clone)]
+pub struct FileRegion {
+    pub file: Arc<File>,
+    pub offset: u64,
+    pub length: u64,
 }
 
+impl Default for Body {
+    fn default() -> Self {
+        Body::new(Bytes::new())
+    }
+}
 
 impl FromStr for Body {
     type Err = BodyTransformError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Body { content: s.bytes().collect() })
+        Ok(Body::new(s.to_string()))
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Body::new(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::new(bytes)
     }
 }
 
-impl AsRef<[u8]> for Body {
-    fn as_ref(&self) -> &[u8] {
-        self.content.as_slice()
+impl From<String> for Body {
+    fn from(s: String) -> Self {
+        Body::new(s)
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Self {
+        Body::new(Bytes::copy_from_slice(s.as_bytes()))
     }
 }
 
 impl Body {
-    pub fn new(bytes: Vec<u8>) -> Self {
+    pub fn new(bytes: impl Into<Bytes>) -> Self {
+        Self {
+            content: BodyContent::Memory(bytes.into())
+        }
+    }
+
+    /// `file` may be shared between several bodies, as the region is read at explicit offsets.
+    pub fn from_file_region(file: impl Into<Arc<File>>, offset: u64, length: u64) -> Self {
         Self {
-            content: bytes
+            content: BodyContent::File(FileRegion { file: file.into(), offset, length })
+        }
+    }
+
+    pub fn from_parts(parts: Vec<Body>) -> Self {
+        Self {
+            content: BodyContent::Parts(parts)
         }
     }
 
     pub fn len(&self) -> usize {
-        self.content.len()
+        match &self.content {
+            BodyContent::Memory(bytes) => bytes.len(),
+            BodyContent::File(region) => region.length as usize,
+            BodyContent::Parts(parts) => parts.iter().map(Body::len).sum()
+        }
     }
-}
 
-enum HeaderName {
-    Known(HTTPHeader),
-    Custom(String),
+    /// The body flattened into the pieces that get written, in order.
+    pub fn segments(&self) -> Vec<Segment<'_>> {
+        match &self.content {
+            BodyContent::Memory(bytes) => vec![Segment::Memory(bytes.as_ref())],
+            BodyContent::File(region) => vec![Segment::File(region)],
+            BodyContent::Parts(parts) => parts.iter().flat_map(Body::segments).collect()
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,298 @@
+use std::io::{self, ErrorKind, Read};
+
+use crate::http::media_type::{self, MediaType};
+
+/// Longest header section of a single part.
+const MAX_PART_HEADERS: usize = 16 * 1024;
+const READ_SIZE: usize = 8 * 1024;
+
+/// Headers of one part of a `multipart/form-data` body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartHeaders {
+    /// The form field the part belongs to.
+    pub name: String,
+    /// Set for file parts: the name of the file on the client, as sent.
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+}
+
+impl PartHeaders {
+    fn parse(section: &str) -> io::Result<Self> {
+        let mut disposition = None;
+        let mut content_type = None;
+        for line in section.split("\r\n").filter(|line| !line.is_empty()) {
+            let (name, value) = line.split_once(':').ok_or_else(|| malformed("Malformed part header"))?;
+            if name.trim().eq_ignore_ascii_case("Content-Disposition") {
+                disposition = Some(value.trim().to_string());
+            } else if name.trim().eq_ignore_ascii_case("Content-Type") {
+                content_type = Some(value.trim().to_string());
+            }
+        }
+
+        let disposition = disposition.ok_or_else(|| malformed("Part without Content-Disposition"))?;
+        let (kind, parameters) = disposition.split_once(';').unwrap_or((&disposition, ""));
+        if !kind.trim().eq_ignore_ascii_case("form-data") {
+            return Err(malformed("Part is not form-data"));
+        }
+        let parameters = media_type::parse_parameters(parameters);
+        let parameter = |name: &str| parameters.iter().find(|(parameter, _)| parameter == name).map(|(_, value)| value.clone());
+        Ok(Self {
+            name: parameter("name").ok_or_else(|| malformed("Part without a name"))?,
+            filename: parameter("filename"),
+            content_type,
+        })
+    }
+}
+
+fn malformed(message: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// The boundary of a `multipart/form-data` content type, `None` for other types.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let media_type = MediaType::parse(content_type)?;
+    if media_type.essence != "multipart/form-data" {
+        return None;
+    }
+    media_type.parameter("boundary")
+        .filter(|boundary| (1..=70).contains(&boundary.len()))
+        .map(str::to_string)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the first boundary.
+    Preamble,
+    /// Inside the content of a part.
+    Content,
+    /// The buffer starts with the delimiter that ended a part.
+    Delimiter,
+    /// Past the closing delimiter.
+    End,
+}
+
+/// Incremental `multipart/form-data` (RFC 7578) parser over any reader. Only a few KiB are
+/// buffered, so file parts can be copied elsewhere as they arrive: call `next_part` for the
+/// headers of each part, then read its content from the parser itself.
+pub struct Multipart<R: Read> {
+    source: R,
+    buffer: Vec<u8>,
+    /// `CRLF--boundary`, which ends the content of a part.
+    delimiter: Vec<u8>,
+    state: State,
+}
+
+impl<R: Read> Multipart<R> {
+    pub fn new(source: R, boundary: &str) -> Self {
+        // The first boundary isn't preceded by a line break, pretend it is
+        Self { source, buffer: b"\r\n".to_vec(), delimiter: format!("\r\n--{boundary}").into_bytes(), state: State::Preamble }
+    }
+
+    /// Reads more input into the buffer, `false` at the end of the input.
+    fn fill(&mut self) -> io::Result<bool> {
+        let length = self.buffer.len();
+        self.buffer.resize(length + READ_SIZE, 0);
+        let result = loop {
+            match self.source.read(&mut self.buffer[length..]) {
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                result => break result
+            }
+        };
+        self.buffer.truncate(length + result.as_ref().map_or(0, |read| *read));
+        Ok(result? > 0)
+    }
+
+    /// Makes sure at least `length` bytes are buffered.
+    fn fill_to(&mut self, length: usize) -> io::Result<()> {
+        while self.buffer.len() < length {
+            if !self.fill()? {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "Multipart body ended early"));
+            }
+        }
+        Ok(())
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|window| window == needle)
+    }
+
+    /// Skips to the next part and returns its headers, `None` after the last one.
+    /// Unread content of the current part is discarded.
+    pub fn next_part(&mut self) -> io::Result<Option<PartHeaders>> {
+        loop {
+            match self.state {
+                State::End => return Ok(None),
+                State::Content => {
+                    io::copy(self, &mut io::sink())?;
+                }
+                State::Preamble => match Multipart::<R>::find(&self.buffer, &self.delimiter) {
+                    Some(start) => {
+                        self.buffer.drain(..start);
+                        self.state = State::Delimiter;
+                    }
+                    None => {
+                        // Keep what could be the start of the delimiter
+                        let keep = self.delimiter.len() - 1;
+                        let discard = self.buffer.len().saturating_sub(keep);
+                        self.buffer.drain(..discard);
+                        self.fill_to(self.buffer.len() + 1)?;
+                    }
+                },
+                State::Delimiter => {
+                    self.fill_to(self.delimiter.len() + 2)?;
+                    self.buffer.drain(..self.delimiter.len());
+                    if self.buffer.starts_with(b"--") {
+                        self.state = State::End;
+                        return Ok(None);
+                    }
+                    // Transport padding may follow the boundary before the line break
+                    let line_end = loop {
+                        if let Some(index) = Multipart::<R>::find(&self.buffer, b"\r\n") {
+                            break index;
+                        }
+                        if self.buffer.len() > MAX_PART_HEADERS {
+                            return Err(malformed("Malformed boundary line"));
+                        }
+                        self.fill_to(self.buffer.len() + 1)?;
+                    };
+                    if self.buffer[..line_end].iter().any(|&byte| byte != b' ' && byte != b'\t') {
+                        return Err(malformed("Malformed boundary line"));
+                    }
+                    self.buffer.drain(..line_end + 2);
+
+                    let headers_end = loop {
+                        if self.buffer.starts_with(b"\r\n") {
+                            break 0;
+                        }
+                        if let Some(index) = Multipart::<R>::find(&self.buffer, b"\r\n\r\n") {
+                            break index + 2;
+                        }
+                        if self.buffer.len() > MAX_PART_HEADERS {
+                            return Err(malformed("Part headers are too long"));
+                        }
+                        self.fill_to(self.buffer.len() + 1)?;
+                    };
+                    let section = std::str::from_utf8(&self.buffer[..headers_end])
+                        .map_err(|_| malformed("Part headers are not UTF-8"))?;
+                    let headers = PartHeaders::parse(section)?;
+                    self.buffer.drain(..headers_end + 2);
+                    self.state = State::Content;
+                    return Ok(Some(headers));
+                }
+            }
+        }
+    }
+}
+
+/// Reads the content of the current part; returns 0 at its end.
+impl<R: Read> Read for Multipart<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.state != State::Content || buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let available = match Multipart::<R>::find(&self.buffer, &self.delimiter) {
+                Some(0) => {
+                    self.state = State::Delimiter;
+                    return Ok(0);
+                }
+                Some(end) => end,
+                // Anything that could be the start of the delimiter has to wait for more input
+                None => self.buffer.len().saturating_sub(self.delimiter.len() - 1)
+            };
+            if available > 0 {
+                let length = available.min(buf.len());
+                buf[..length].copy_from_slice(&self.buffer[..length]);
+                self.buffer.drain(..length);
+                return Ok(length);
+            }
+            self.fill_to(self.buffer.len() + 1)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const BODY: &[u8] = b"preamble\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        Hello\r\n--XyZ  \r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        line one\r\n-XyZ is not a delimiter\r\n--XyZ--\r\nepilogue";
+
+    /// Hands out one byte per read, so every delimiter straddles reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = self.0.len().min(buf.len()).min(1);
+            buf[..length].copy_from_slice(&self.0[..length]);
+            self.0 = &self.0[length..];
+            Ok(length)
+        }
+    }
+
+    fn parts(mut form: Multipart<impl Read>) -> io::Result<Vec<(PartHeaders, Vec<u8>)>> {
+        let mut parts = Vec::new();
+        while let Some(headers) = form.next_part()? {
+            let mut content = Vec::new();
+            form.read_to_end(&mut content)?;
+            parts.push((headers, content));
+        }
+        Ok(parts)
+    }
+
+    fn expected() -> Vec<(PartHeaders, Vec<u8>)> {
+        vec![
+            (PartHeaders { name: "title".to_string(), filename: None, content_type: None }, b"Hello".to_vec()),
+            (
+                PartHeaders { name: "file".to_string(), filename: Some("a.txt".to_string()), content_type: Some("text/plain".to_string()) },
+                b"line one\r\n-XyZ is not a delimiter".to_vec()
+            ),
+        ]
+    }
+
+    #[test]
+    fn parses_parts() {
+        assert_eq!(parts(Multipart::new(BODY, "XyZ")).unwrap(), expected());
+    }
+
+    #[test]
+    fn parses_parts_split_across_reads() {
+        assert_eq!(parts(Multipart::new(Trickle(BODY), "XyZ")).unwrap(), expected());
+    }
+
+    #[test]
+    fn skips_unread_content() {
+        let mut form = Multipart::new(BODY, "XyZ");
+        assert_eq!(form.next_part().unwrap().unwrap().name, "title");
+        assert_eq!(form.next_part().unwrap().unwrap().name, "file");
+        assert_eq!(form.next_part().unwrap(), None);
+        assert_eq!(form.next_part().unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_truncated_bodies() {
+        let error = parts(Multipart::new(&BODY[..BODY.len() - 20], "XyZ")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_parts_without_a_name() {
+        let body = b"--b\r\nContent-Disposition: form-data\r\n\r\nx\r\n--b--";
+        let error = parts(Multipart::new(&body[..], "b")).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reads_the_boundary_parameter() {
+        assert_eq!(boundary("multipart/form-data; boundary=\"a b\"").as_deref(), Some("a b"));
+        assert_eq!(boundary("multipart/mixed; boundary=x"), None);
+        assert_eq!(boundary("multipart/form-data"), None);
+        assert_eq!(boundary(&format!("multipart/form-data; boundary={}", "x".repeat(71))), None);
+    }
+}
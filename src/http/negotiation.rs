@@ -0,0 +1,232 @@
+use crate::http::headers::HTTPHeader;
+use crate::http::media_type::{self, MediaType};
+
+/// Highest quality value, `q=1`. Qualities are kept in thousandths, the precision RFC 9110 allows.
+const MAX_QUALITY: u16 = 1000;
+
+/// What a response can be negotiated on, each driven by its own request header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    /// `Accept`, offers are media types such as `text/html`.
+    MediaType,
+    /// `Accept-Encoding`, offers are content codings such as `gzip` or `identity`.
+    Encoding,
+    /// `Accept-Language`, offers are language tags such as `en-US`.
+    // None of the built-in routes has translations to choose from
+    #[allow(dead_code)]
+    Language,
+    /// `Accept-Charset`, offers are charset names such as `utf-8`.
+    Charset,
+}
+
+impl Dimension {
+    pub fn header(self) -> HTTPHeader {
+        match self {
+            Dimension::MediaType => HTTPHeader::Accept,
+            Dimension::Encoding => HTTPHeader::AcceptEncoding,
+            Dimension::Language => HTTPHeader::AcceptLanguage,
+            Dimension::Charset => HTTPHeader::AcceptCharset
+        }
+    }
+}
+
+/// One element of an `Accept*` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preference {
+    /// Media range, coding, language range or charset, possibly a wildcard.
+    pub value: String,
+    /// Media type parameters before `q`; always empty for the other dimensions.
+    pub parameters: Vec<(String, String)>,
+    /// Quality in thousandths.
+    pub quality: u16,
+}
+
+/// Splits a header at commas outside of quoted strings.
+fn split_elements(header: &str) -> Vec<&str> {
+    let mut elements = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in header.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                elements.push(&header[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    elements.push(&header[start..]);
+    elements.into_iter().map(str::trim).filter(|element| !element.is_empty()).collect()
+}
+
+/// Parses a quality value, leniently accepting more than three decimals.
+fn parse_quality(value: &str) -> Option<u16> {
+    let quality = value.parse::<f32>().ok().filter(|quality| (0.0..=1.0).contains(quality))?;
+    Some((quality * MAX_QUALITY as f32).round() as u16)
+}
+
+/// Parses the elements of an `Accept*` header. Elements with a malformed `q` are dropped,
+/// as are parameters after it (accept extensions).
+pub fn parse_preferences(header: &str) -> Vec<Preference> {
+    split_elements(header).into_iter()
+        .filter_map(|element| {
+            let (value, parameters) = element.split_once(';').unwrap_or((element, ""));
+            let mut quality = MAX_QUALITY;
+            let mut kept = Vec::new();
+            for (name, parameter) in media_type::parse_parameters(parameters) {
+                if name == "q" {
+                    quality = parse_quality(&parameter)?;
+                    break;
+                }
+                kept.push((name, parameter));
+            }
+            Some(Preference { value: value.trim().to_ascii_lowercase(), parameters: kept, quality })
+        })
+        .collect()
+}
+
+/// How specifically `preference` matches `offer`, `None` if it doesn't. Higher is more specific.
+fn specificity(dimension: Dimension, preference: &Preference, offer: &str) -> Option<usize> {
+    let offer = offer.to_ascii_lowercase();
+    match dimension {
+        Dimension::MediaType => {
+            let offer = MediaType::parse(&offer)?;
+            let (offer_type, offer_subtype) = offer.essence.split_once('/')?;
+            let (range_type, range_subtype) = preference.value.split_once('/')?;
+            match (range_type, range_subtype) {
+                ("*", "*") => Some(0),
+                (range_type, "*") if range_type == offer_type => Some(1),
+                (range_type, range_subtype) if range_type == offer_type && range_subtype == offer_subtype => {
+                    let parameters_match = preference.parameters.iter()
+                        .all(|(name, value)| offer.parameter(name).is_some_and(|offered| offered.eq_ignore_ascii_case(value)));
+                    parameters_match.then_some(2 + preference.parameters.len())
+                }
+                _ => None
+            }
+        }
+        // Basic filtering (RFC 4647 section 3.3.1): a range matches tags it is a prefix of
+        Dimension::Language => match preference.value.as_str() {
+            "*" => Some(0),
+            range if offer == range || offer.strip_prefix(range).is_some_and(|rest| rest.starts_with('-')) => Some(range.len()),
+            _ => None
+        },
+        Dimension::Encoding | Dimension::Charset => match preference.value.as_str() {
+            "*" => Some(0),
+            value if value == offer => Some(1),
+            _ => None
+        }
+    }
+}
+
+/// Quality of `offer` under `preferences`, taken from the most specific matching element.
+/// `None` stands for a missing header.
+pub fn quality(dimension: Dimension, preferences: Option<&[Preference]>, offer: &str) -> u16 {
+    let Some(preferences) = preferences else {
+        // Clients that don't say which codings they understand only get unencoded content
+        return match dimension == Dimension::Encoding && !offer.eq_ignore_ascii_case("identity") {
+            true => 0,
+            false => MAX_QUALITY
+        };
+    };
+    let best_match = preferences.iter()
+        .filter_map(|preference| specificity(dimension, preference, offer).map(|specificity| (specificity, preference.quality)))
+        .max_by_key(|(specificity, _)| *specificity);
+    match best_match {
+        Some((_, quality)) => quality,
+        // Unencoded content is acceptable unless excluded explicitly
+        None if dimension == Dimension::Encoding && offer.eq_ignore_ascii_case("identity") => MAX_QUALITY,
+        None => 0
+    }
+}
+
+/// The offer with the highest quality for the client, `None` if none is acceptable.
+/// Offers are listed in the server's order of preference, which breaks ties.
+pub fn best_offer<'o>(dimension: Dimension, header: Option<&str>, offers: &[&'o str]) -> Option<&'o str> {
+    let preferences = header.map(parse_preferences);
+    let mut best: Option<(&str, u16)> = None;
+    for &offer in offers {
+        let quality = quality(dimension, preferences.as_deref(), offer);
+        let better = match best {
+            None => quality > 0,
+            Some((_, best_quality)) => quality > best_quality
+        };
+        if better {
+            best = Some((offer, quality));
+        }
+    }
+    best.map(|(offer, _)| offer)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn parses_preferences() {
+        let preferences = parse_preferences("text/html;level=1;q=0.5;ext=x, \"odd,value\", */*;q=2, text/plain;q=0.1234");
+        assert_eq!(preferences, vec![
+            Preference { value: "text/html".to_string(), parameters: vec![("level".to_string(), "1".to_string())], quality: 500 },
+            Preference { value: "\"odd,value\"".to_string(), parameters: Vec::new(), quality: 1000 },
+            Preference { value: "text/plain".to_string(), parameters: Vec::new(), quality: 123 },
+        ]);
+    }
+
+    #[test]
+    fn prefers_the_most_specific_media_range() {
+        let header = Some("text/*;q=0.3, text/html;q=0.7, text/html;level=1, */*;q=0.5");
+        assert_eq!(best_offer(Dimension::MediaType, header, &["text/plain", "image/png"]), Some("image/png"));
+        assert_eq!(best_offer(Dimension::MediaType, header, &["text/plain", "text/html;level=1"]), Some("text/html;level=1"));
+        assert_eq!(best_offer(Dimension::MediaType, Some("application/json;q=0"), &["application/json"]), None);
+    }
+
+    #[test]
+    fn breaks_ties_in_server_order() {
+        let header = Some("application/json, text/html");
+        assert_eq!(best_offer(Dimension::MediaType, header, &["text/html", "application/json"]), Some("text/html"));
+        assert_eq!(best_offer(Dimension::MediaType, None, &["application/json", "text/html"]), Some("application/json"));
+    }
+
+    #[test]
+    fn keeps_identity_acceptable() {
+        let offers = ["gzip", "identity"];
+        assert_eq!(best_offer(Dimension::Encoding, None, &offers), Some("identity"));
+        assert_eq!(best_offer(Dimension::Encoding, Some("br"), &offers), Some("identity"));
+        assert_eq!(best_offer(Dimension::Encoding, Some("GZIP, identity;q=0.5"), &offers), Some("gzip"));
+        assert_eq!(best_offer(Dimension::Encoding, Some("*;q=0"), &offers), None);
+        assert_eq!(best_offer(Dimension::Encoding, Some("gzip;q=0, identity;q=0"), &offers), None);
+    }
+
+    #[test]
+    fn matches_language_ranges_as_prefixes() {
+        let offers = ["de", "en-US", "en-GB"];
+        assert_eq!(best_offer(Dimension::Language, Some("en"), &offers), Some("en-US"));
+        assert_eq!(best_offer(Dimension::Language, Some("EN-gb, en;q=0.8"), &offers), Some("en-GB"));
+        assert_eq!(best_offer(Dimension::Language, Some("fr, *;q=0.1"), &offers), Some("de"));
+        assert_eq!(best_offer(Dimension::Language, None, &offers), Some("de"));
+        // A prefix has to end at a subtag boundary
+        assert_eq!(best_offer(Dimension::Language, Some("e"), &offers), None);
+        assert_eq!(best_offer(Dimension::Language, Some("en-US"), &["en"]), None);
+    }
+
+    #[test]
+    fn excludes_languages_with_quality_zero() {
+        let offers = ["en-US", "de"];
+        assert_eq!(best_offer(Dimension::Language, Some("en;q=0, *"), &offers), Some("de"));
+        // The more specific range wins over the exclusion
+        assert_eq!(best_offer(Dimension::Language, Some("en;q=0, en-US;q=0.5"), &offers), Some("en-US"));
+        assert_eq!(best_offer(Dimension::Language, Some("en-US;q=0, de;q=0"), &offers), None);
+        assert_eq!(quality(Dimension::Language, Some(&parse_preferences("*;q=0")), "de"), 0);
+    }
+
+    #[test]
+    fn matches_charsets() {
+        assert_eq!(best_offer(Dimension::Charset, Some("iso-8859-1, *;q=0.1"), &["utf-8"]), Some("utf-8"));
+        assert_eq!(best_offer(Dimension::Charset, Some("iso-8859-1"), &["UTF-8"]), None);
+    }
+}
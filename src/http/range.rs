@@ -0,0 +1,212 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::http::conditional::{EntityTag, truncate_to_seconds, Validators};
+use crate::http::date::parse_http_date;
+use crate::http::headers::HTTPHeader;
+use crate::http::request::Request;
+
+/// More ranges than this in one request are ignored and the full content is sent instead.
+const MAX_RANGES: usize = 64;
+
+/// Inclusive byte range, as in `Content-Range`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub first: u64,
+    pub last: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.last - self.first + 1
+    }
+
+    /// `Content-Range` value for this range of a representation of `complete_length` bytes.
+    pub fn content_range(&self, complete_length: u64) -> String {
+        format!("bytes {first}-{last}/{complete_length}", first = self.first, last = self.last)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable `Range` header, send everything.
+    Full,
+    Ranges(Vec<ByteRange>),
+    /// None of the ranges overlaps the content: `416 Range Not Satisfiable`.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header value against content of `length` bytes. Supports `first-last`,
+/// open-ended `first-` and suffix `-length` ranges, which are coalesced as RFC 9110, section
+/// 14.2 allows. Other units and malformed headers are ignored as RFC 9110 asks.
+pub fn parse(value: &str, length: u64) -> RangeRequest {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let specs = specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()).collect::<Vec<_>>();
+    // A range set has at least one range, `bytes=` alone is malformed
+    if specs.is_empty() {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs {
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let range = match (first.parse::<u64>().ok(), last.parse::<u64>().ok()) {
+            (Some(first), _) if first >= length => None,
+            (Some(first), None) if last.is_empty() => Some(ByteRange { first, last: length - 1 }),
+            (Some(first), Some(last)) if first <= last => Some(ByteRange { first, last: last.min(length - 1) }),
+            (None, Some(suffix)) if first.is_empty() && suffix > 0 && length > 0 => {
+                Some(ByteRange { first: length.saturating_sub(suffix), last: length - 1 })
+            }
+            (None, Some(0)) if first.is_empty() => None,
+            _ => return RangeRequest::Full
+        };
+        ranges.extend(range);
+        if ranges.len() > MAX_RANGES {
+            return RangeRequest::Full;
+        }
+    }
+
+    if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Ranges(coalesce(ranges))
+    }
+}
+
+/// Merges overlapping and adjacent ranges, so no byte is sent twice however the client
+/// layered its ranges. The result is in ascending order.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.first);
+    let mut coalesced: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(previous) if range.first <= previous.last.saturating_add(1) => previous.last = previous.last.max(range.last),
+            _ => coalesced.push(range)
+        }
+    }
+    coalesced
+}
+
+/// Parses a `Content-Range` request header such as `bytes 100-199/1000` or `bytes 100-199/*`
+/// into the range and the complete length, if given.
+pub fn parse_content_range(value: &str) -> Option<(ByteRange, Option<u64>)> {
+    let (range, complete_length) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let range = ByteRange { first: first.trim().parse().ok()?, last: last.trim().parse().ok()? };
+    if range.first > range.last {
+        return None;
+    }
+    let complete_length = match complete_length.trim() {
+        "*" => None,
+        length => Some(length.parse::<u64>().ok()?)
+    };
+    if complete_length.is_some_and(|length| length <= range.last) {
+        return None;
+    }
+    Some((range, complete_length))
+}
+
+/// Whether an `If-Range` condition lets the `Range` header apply. Only strong validators
+/// count: a matching strong `ETag`, or a date equal to `Last-Modified`.
+pub fn if_range_matches(request: &Request, validators: &Validators) -> bool {
+    let Some(if_range) = request.get_known_header_line(HTTPHeader::IfRange) else {
+        return true;
+    };
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        let Some(etag) = &validators.etag else {
+            return false;
+        };
+        return if_range.strip_prefix('"')
+            .and_then(|tag| tag.strip_suffix('"'))
+            .is_some_and(|tag| EntityTag::strong(tag).strong_eq(etag));
+    }
+    match (parse_http_date(if_range), validators.last_modified) {
+        (Some(date), Some(last_modified)) => truncate_to_seconds(last_modified) == date,
+        _ => false
+    }
+}
+
+/// Boundary for a `multipart/byteranges` response, unique within this process.
+pub fn multipart_boundary() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_nanos());
+    format!("byteranges-{nanos:x}-{count:x}", count = COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn ranges(ranges: &[(u64, u64)]) -> RangeRequest {
+        RangeRequest::Ranges(ranges.iter().map(|&(first, last)| ByteRange { first, last }).collect())
+    }
+
+    #[test]
+    fn parses_all_range_forms() {
+        assert_eq!(parse("bytes=0-499", 10_000), ranges(&[(0, 499)]));
+        assert_eq!(parse("bytes=9500-", 10_000), ranges(&[(9500, 9999)]));
+        assert_eq!(parse("bytes=-500", 10_000), ranges(&[(9500, 9999)]));
+        assert_eq!(parse(" bytes=0-0, -1 ,500-600", 10_000), ranges(&[(0, 0), (500, 600), (9999, 9999)]));
+    }
+
+    #[test]
+    fn coalesces_overlapping_and_adjacent_ranges() {
+        assert_eq!(parse("bytes=0-99, 50-149", 1000), ranges(&[(0, 149)]));
+        assert_eq!(parse("bytes=100-199, 0-99", 1000), ranges(&[(0, 199)]));
+        assert_eq!(parse("bytes=0-499, 100-199, -600", 1000), ranges(&[(0, 999)]));
+        assert_eq!(parse("bytes=0-0, 2-2, 1-1", 1000), ranges(&[(0, 2)]));
+        assert_eq!(parse("bytes=0-9, 11-19", 1000), ranges(&[(0, 9), (11, 19)]));
+        assert_eq!(parse("bytes=500-, 0-", 1000), ranges(&[(0, 999)]));
+        let repeated = format!("bytes={}", vec!["0-999"; MAX_RANGES].join(","));
+        assert_eq!(parse(&repeated, 1000), ranges(&[(0, 999)]));
+    }
+
+    #[test]
+    fn clamps_ranges_to_the_content() {
+        assert_eq!(parse("bytes=5-100", 10), ranges(&[(5, 9)]));
+        assert_eq!(parse("bytes=-100", 10), ranges(&[(0, 9)]));
+    }
+
+    #[test]
+    fn drops_unsatisfiable_ranges() {
+        assert_eq!(parse("bytes=10-20, 2-3", 10), ranges(&[(2, 3)]));
+        assert_eq!(parse("bytes=10-20", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignores_malformed_headers() {
+        for value in ["", "bytes=", "bytes=,", "items=0-1", "bytes=5-2", "bytes=a-b", "bytes=1", "bytes=0-1, x"] {
+            assert_eq!(parse(value, 10), RangeRequest::Full, "{value}");
+        }
+        let too_many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse(&too_many, 10), RangeRequest::Full);
+    }
+
+    #[test]
+    fn formats_content_range() {
+        let range = ByteRange { first: 100, last: 199 };
+        assert_eq!(range.length(), 100);
+        assert_eq!(range.content_range(1000), "bytes 100-199/1000");
+    }
+
+    #[test]
+    fn parses_content_range() {
+        let range = ByteRange { first: 100, last: 199 };
+        assert_eq!(parse_content_range("bytes 100-199/1000"), Some((range, Some(1000))));
+        assert_eq!(parse_content_range("bytes 100-199/*"), Some((range, None)));
+        assert_eq!(parse_content_range("bytes 100-199/199"), None);
+        assert_eq!(parse_content_range("bytes 200-100/1000"), None);
+        assert_eq!(parse_content_range("bytes */1000"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+    }
+}
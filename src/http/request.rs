@@ -1,11 +1,28 @@
-use std::fmt::Formatter;
-use std::io::{BufReader, ErrorKind, Read};
+use std::cell::{OnceCell, RefCell};
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, ErrorKind, Read};
 use std::io;
-use std::net::TcpStream;
 use std::str::FromStr;
 
-use crate::http::{Body, RequestLine};
-use crate::http::headers::{HeaderMap, HTTPHeader};
+use crate::config::Config;
+use crate::http::RequestLine;
+use crate::http::charset::Charset;
+use crate::http::cookie::Cookies;
+use crate::http::headers::{HeaderMap, HTTPHeader, is_combinable};
+use crate::http::media_type::MediaType;
+use crate::http::negotiation::{self, Dimension};
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+use crate::http::multipart::{self, Multipart};
+use crate::http::url::Params;
+use crate::json::{self, Value};
+use crate::session::Session;
+
+/// Longest request line or header line accepted, in bytes.
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+
+/// Most header lines accepted in one request.
+const MAX_HEADER_COUNT: usize = 100;
 
 #[allow(dead_code)]
 struct RequestTarget(String);
@@ -14,112 +31,269 @@ struct RequestTarget(String);
 struct HTTPVersion;
 
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HTTPMethod {
     GET,
+    HEAD,
     POST,
+    PUT,
+    DELETE,
+    PATCH,
+    OPTIONS,
+    PROPFIND,
+    PROPPATCH,
+    MKCOL,
+    COPY,
+    MOVE,
+    LOCK,
+    UNLOCK,
 }
 
 impl std::fmt::Display for HTTPMethod {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             HTTPMethod::GET => write!(f, "GET"),
+            HTTPMethod::HEAD => write!(f, "HEAD"),
             HTTPMethod::POST => write!(f, "POST"),
+            HTTPMethod::PUT => write!(f, "PUT"),
+            HTTPMethod::DELETE => write!(f, "DELETE"),
+            HTTPMethod::PATCH => write!(f, "PATCH"),
+            HTTPMethod::OPTIONS => write!(f, "OPTIONS"),
+            HTTPMethod::PROPFIND => write!(f, "PROPFIND"),
+            HTTPMethod::PROPPATCH => write!(f, "PROPPATCH"),
+            HTTPMethod::MKCOL => write!(f, "MKCOL"),
+            HTTPMethod::COPY => write!(f, "COPY"),
+            HTTPMethod::MOVE => write!(f, "MOVE"),
+            HTTPMethod::LOCK => write!(f, "LOCK"),
+            HTTPMethod::UNLOCK => write!(f, "UNLOCK"),
         }
     }
 }
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::enum_variant_names)]
 pub enum HTTPRequestParseError {
     InvalidMethodError,
     InvalidStatusLineError,
     InvalidPathError,
 }
 
+/// Why `Request::new` refused the head of a request, carried by its `InvalidData` error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadTooLarge {
+    /// The request line is longer than `MAX_LINE_LENGTH`, usually because of the target.
+    RequestLine,
+    /// A header line is longer than `MAX_LINE_LENGTH`.
+    HeaderLine,
+    /// There are more than `MAX_HEADER_COUNT` header lines.
+    HeaderCount,
+}
+
+impl HeadTooLarge {
+    /// The limit a rejected head ran into, if that is why `error` was returned.
+    pub fn from_error(error: &io::Error) -> Option<HeadTooLarge> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<HeadTooLarge>()).copied()
+    }
+
+    pub fn status(&self) -> HTTPStatus {
+        match self {
+            HeadTooLarge::RequestLine => HTTPStatus::URITooLong,
+            HeadTooLarge::HeaderLine | HeadTooLarge::HeaderCount => HTTPStatus::RequestHeaderFieldsTooLarge
+        }
+    }
+}
+
+impl std::fmt::Display for HeadTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeadTooLarge::RequestLine => write!(f, "Request lines are limited to {MAX_LINE_LENGTH} bytes"),
+            HeadTooLarge::HeaderLine => write!(f, "Header lines are limited to {MAX_LINE_LENGTH} bytes"),
+            HeadTooLarge::HeaderCount => write!(f, "Requests are limited to {MAX_HEADER_COUNT} header lines")
+        }
+    }
+}
+
+impl std::error::Error for HeadTooLarge {}
+
+/// Why `Request::body` could not provide the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyError {
+    /// The client closed the connection before sending `Content-Length` bytes.
+    Incomplete,
+    /// A handler streamed the body already, through `Request::multipart`.
+    Consumed,
+    /// The body is larger than the limit, `Config.max_buffered_body_size`.
+    TooLarge(u64),
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyError::Incomplete => write!(f, "Incomplete request body"),
+            BodyError::Consumed => write!(f, "The request body was read already"),
+            BodyError::TooLarge(limit) => write!(f, "Request bodies are limited to {limit} bytes")
+        }
+    }
+}
+
+/// Why `Request::form` could not decode the body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormError {
+    /// The body is not `application/x-www-form-urlencoded`.
+    UnsupportedMediaType,
+    UnsupportedCharset(String),
+    /// The body is larger than the limit, `Config.max_buffered_body_size`.
+    TooLarge(u64),
+    Body(BodyError),
+}
+
+impl std::fmt::Display for FormError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::UnsupportedMediaType => write!(f, "Expected an application/x-www-form-urlencoded body"),
+            FormError::UnsupportedCharset(charset) => write!(f, "Unsupported form charset `{charset}`"),
+            FormError::TooLarge(limit) => write!(f, "Form bodies are limited to {limit} bytes"),
+            FormError::Body(error) => write!(f, "{error}")
+        }
+    }
+}
+
+/// Why `Request::json` could not decode the body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    /// The body is not declared as JSON, or not as UTF-8.
+    UnsupportedMediaType,
+    Syntax(json::ParseError),
+    /// The body is larger than the limit, `Config.max_buffered_body_size`.
+    TooLarge(u64),
+    Body(BodyError),
+}
+
 impl FromStr for HTTPMethod {
     type Err = HTTPRequestParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "GET" => Ok(Self::GET),
+            "HEAD" => Ok(Self::HEAD),
             "POST" => Ok(Self::POST),
+            "PUT" => Ok(Self::PUT),
+            "DELETE" => Ok(Self::DELETE),
+            "PATCH" => Ok(Self::PATCH),
+            "OPTIONS" => Ok(Self::OPTIONS),
+            "PROPFIND" => Ok(Self::PROPFIND),
+            "PROPPATCH" => Ok(Self::PROPPATCH),
+            "MKCOL" => Ok(Self::MKCOL),
+            "COPY" => Ok(Self::COPY),
+            "MOVE" => Ok(Self::MOVE),
+            "LOCK" => Ok(Self::LOCK),
+            "UNLOCK" => Ok(Self::UNLOCK),
             _ => Err(HTTPRequestParseError::InvalidMethodError)
         }
     }
 }
 
 
+/// The connection, positioned at the start of the body and limited to its `Content-Length`.
+struct BodyReader(Box<dyn Read>);
+
+impl Debug for BodyReader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BodyReader")
+    }
+}
+
 #[derive(Debug)]
 pub struct Request {
     pub http_version: String,
     pub resource: String,
     pub method: HTTPMethod,
     pub headers: HeaderMap,
-    pub body: Body,
+    content_length: u64,
+    /// Filled by `body` on first use.
+    body: OnceCell<Vec<u8>>,
+    /// Taken by `body` or `multipart`, whichever reads the body.
+    body_reader: RefCell<Option<BodyReader>>,
+    /// Empty unless sessions are enabled; filled in before the handler runs.
+    pub session: Session,
+    /// Request headers the response was negotiated on, for its `Vary` header.
+    negotiated: RefCell<Vec<HTTPHeader>>,
 }
 
 impl std::fmt::Display for Request {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", format!("Path: {path}, Method: {method}", path = &self.resource, method = &self.method))
+        write!(f, "Path: {path}, Method: {method}", path = &self.resource, method = &self.method)
     }
 }
 
 
 impl Request {
-    pub fn new(mut stream: BufReader<&TcpStream>) -> io::Result<Self> {
-        // let mut request_buffer = Vec::with_capacity(0x1000);
-
+    /// Reads the request line and headers from `stream`. The body is left on the stream until
+    /// a handler asks for it.
+    pub fn new(mut stream: impl BufRead + 'static) -> io::Result<Self> {
         let request_line = Request::read_request_line(&mut stream)?;
         let headers = Request::read_headers(&mut stream)?;
-        // let content_length_value = headers.get("Content-Length").unwrap();
-        // let content_length = content_length_value.get(0).unwrap().parse().unwrap();
         let content_length = match headers.get("Content-Length") {
             None => 0,
-            Some(value) => value.get(0).unwrap().parse().unwrap()
+            Some(value) => value.first()
+                .and_then(|length| length.parse().ok())
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Invalid Content-Length"))?
         };
-        let body = Request::read_body(&mut stream, content_length)?;
-
 
         Ok(Self {
             http_version: request_line.http_version,
             resource: request_line.resource,
             method: request_line.http_method,
             headers,
-            body,
+            content_length,
+            body: OnceCell::new(),
+            body_reader: RefCell::new(Some(BodyReader(Box::new(stream.take(content_length))))),
+            session: Session::default(),
+            negotiated: RefCell::new(Vec::new()),
         })
     }
 
-    fn read_headers(stream: &mut BufReader<&TcpStream>) -> io::Result<HeaderMap> {
+    fn read_headers(stream: &mut impl BufRead) -> io::Result<HeaderMap> {
         let mut headers: HeaderMap = HeaderMap::new();
 
-        loop {
-            let header_line = Request::read_header_line(stream)?;
+        for count in 0.. {
+            let header_line = Request::read_header_line(stream, HeadTooLarge::HeaderLine)?;
             if header_line.is_empty() {
                 break;
             }
+            if count == MAX_HEADER_COUNT {
+                return Err(io::Error::new(ErrorKind::InvalidData, HeadTooLarge::HeaderCount));
+            }
 
-            let mut header_name_and_value = header_line.split(": ");
-            let name = header_name_and_value.next().unwrap().to_string();
-            let values = header_name_and_value.next().unwrap().split(", ").map(|x| x.to_string()).collect::<Vec<String>>();
+            let Some((name, value)) = header_line.split_once(':') else {
+                return Err(io::Error::new(ErrorKind::InvalidData, "Malformed header line"));
+            };
+            let name = name.to_string();
+            let value = value.trim_matches([' ', '\t']);
+            let values = match is_combinable(&name) {
+                true => value.split(", ").map(|x| x.to_string()).collect::<Vec<String>>(),
+                false => vec![value.to_string()]
+            };
 
-            let header_value_slot = headers.entry(name).or_insert(Vec::with_capacity(1));
-            header_value_slot.extend(values);
+            headers.append(name, values);
         }
 
         Ok(headers)
     }
 
 
-    fn read_request_line(stream: &mut BufReader<&TcpStream>) -> io::Result<RequestLine> {
-        let request_line = Request::read_header_line(stream).unwrap();
+    fn read_request_line(stream: &mut impl BufRead) -> io::Result<RequestLine> {
+        let request_line = Request::read_header_line(stream, HeadTooLarge::RequestLine)?;
         let mut parts = request_line.split_ascii_whitespace();
-        let http_method = match parts.next().unwrap() {
-            "GET" => HTTPMethod::GET,
-            "POST" => HTTPMethod::POST,
-            _ => return Err(io::Error::new(ErrorKind::InvalidData, "Unsupported HTTP method"))
+        let (Some(http_method), Some(resource), Some(http_version)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Malformed request line"));
         };
-        let resource = parts.next().unwrap().to_string();
-        let http_version = parts.next().unwrap().to_string();
+        let http_method = match http_method.parse::<HTTPMethod>() {
+            Ok(http_method) => http_method,
+            Err(_) => return Err(io::Error::new(ErrorKind::InvalidData, "Unsupported HTTP method"))
+        };
+        let resource = resource.to_string();
+        let http_version = http_version.to_string();
 
         Ok(RequestLine {
             http_method,
@@ -127,37 +301,159 @@ impl Request {
             http_version,
         })
     }
-    fn read_header_line(stream: &mut BufReader<&TcpStream>) -> io::Result<String> {
+    /// Reads one line of the head, failing with `too_long` past `MAX_LINE_LENGTH`.
+    fn read_header_line(stream: &mut impl BufRead, too_long: HeadTooLarge) -> io::Result<String> {
         let mut buf: Vec<u8> = Vec::with_capacity(0x1000);
         while let Some(Ok(byte)) = stream.bytes().next() {
             if byte == b'\n' {
                 if buf.ends_with(b"\r") {
                     buf.pop();
                 }
+                if buf.len() > MAX_LINE_LENGTH {
+                    return Err(io::Error::new(ErrorKind::InvalidData, too_long));
+                }
                 let header_line = String::from_utf8(buf).map_err(|_| { io::Error::new(ErrorKind::InvalidData, "Not a HTTP header") })?;
                 return Ok(header_line);
             }
+            // One byte past the limit leaves room for the `\r` of a line right at it
+            if buf.len() > MAX_LINE_LENGTH {
+                return Err(io::Error::new(ErrorKind::InvalidData, too_long));
+            }
             buf.push(byte);
         }
 
         Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Client aborted early"))
     }
 
-    fn read_body(stream: &mut BufReader<&TcpStream>, content_length: usize) -> io::Result<Body> {
-        if content_length == 0 {
-            return Ok(Body::default());
+    /// Length of the body as announced by the client, known before any of it is read.
+    pub fn content_length(&self) -> u64 {
+        self.content_length
+    }
+
+    /// The body, read from the connection on first use. It is held in memory, so bodies over
+    /// `Config.max_buffered_body_size` are refused before anything is read.
+    pub fn body(&self, config: &Config) -> Result<&[u8], BodyError> {
+        if let Some(body) = self.body.get() {
+            return Ok(body);
+        }
+        if self.content_length > config.max_buffered_body_size {
+            return Err(BodyError::TooLarge(config.max_buffered_body_size));
+        }
+        let Some(mut reader) = self.body_reader.borrow_mut().take() else {
+            eprintln!("The body of `{method} {resource}` was streamed already", method = self.method, resource = self.resource);
+            return Err(BodyError::Consumed);
+        };
+        // Grows as data arrives rather than trusting the announced length up front
+        let mut body = Vec::new();
+        match reader.0.read_to_end(&mut body) {
+            Ok(length) if length as u64 == self.content_length => Ok(self.body.get_or_init(|| body)),
+            _ => Err(BodyError::Incomplete)
         }
-        // let mut str_bod = String::new();
-        let mut content: Vec<u8> = vec![0; content_length];
-        _ = stream.read_exact(&mut content)?;
-        // let body_content = match stream.read_exact(&mut body) {
-        //     Ok(content) => println!("Content? :{:?}", content),
-        //     Err(err) => println!("Error: {:?}", err)
-        // };
-        // println!("BODY: {:?}", String::from_utf8_lossy(&body));
-        Ok(Body::new(content))
     }
 
+    /// The request target without its query string.
+    pub fn path(&self) -> &str {
+        match self.resource.split_once('?') {
+            None => &self.resource,
+            Some((path, _)) => path
+        }
+    }
+
+    pub fn query_string(&self) -> Option<&str> {
+        self.resource.split_once('?').map(|(_, query)| query)
+    }
+
+    pub fn query(&self) -> Params {
+        Params::parse(self.query_string().unwrap_or_default())
+    }
+
+    /// Decodes an `application/x-www-form-urlencoded` body. Its `charset` parameter selects
+    /// the encoding of the escaped bytes, UTF-8 if there is none.
+    pub fn form(&self, config: &Config) -> Result<Params, FormError> {
+        let media_type = self.get_known_header_line(HTTPHeader::ContentType)
+            .and_then(|content_type| MediaType::parse(&content_type))
+            .filter(|media_type| media_type.essence == "application/x-www-form-urlencoded")
+            .ok_or(FormError::UnsupportedMediaType)?;
+        let charset = match media_type.parameter("charset") {
+            None => Charset::Utf8,
+            Some(label) => Charset::from_label(label).ok_or_else(|| FormError::UnsupportedCharset(label.to_string()))?
+        };
+        let body = self.body(config).map_err(|error| match error {
+            BodyError::TooLarge(limit) => FormError::TooLarge(limit),
+            error => FormError::Body(error)
+        })?;
+        Ok(Params::parse_form(body, charset))
+    }
+
+    /// Parses a JSON body, which has to be sent as `application/json` or another `+json` type.
+    pub fn json(&self, config: &Config) -> Result<Value, JsonError> {
+        let media_type = self.get_known_header_line(HTTPHeader::ContentType)
+            .and_then(|content_type| MediaType::parse(&content_type))
+            .filter(|media_type| media_type.essence == "application/json" || media_type.essence.ends_with("+json"))
+            .ok_or(JsonError::UnsupportedMediaType)?;
+        if media_type.parameter("charset").is_some_and(|charset| Charset::from_label(charset) != Some(Charset::Utf8)) {
+            return Err(JsonError::UnsupportedMediaType);
+        }
+        let body = self.body(config).map_err(|error| match error {
+            BodyError::TooLarge(limit) => JsonError::TooLarge(limit),
+            error => JsonError::Body(error)
+        })?;
+        json::parse_bytes(body).map_err(JsonError::Syntax)
+    }
+
+    /// Cookies from every `Cookie` header of the request.
+    pub fn cookies(&self) -> Cookies {
+        let lines = self.get_known_header_values(HTTPHeader::Cookie).map(Vec::as_slice).unwrap_or_default();
+        Cookies::parse(lines.iter().map(String::as_str))
+    }
+
+    /// Picks the offer the client prefers according to the `Accept*` header of `dimension`,
+    /// or answers `406 Not Acceptable` if none is acceptable. Offers are given in the order the
+    /// handler prefers them. The header is added to `Vary` of whatever response is sent.
+    pub fn negotiate<'o>(&self, dimension: Dimension, offers: &[&'o str]) -> Result<&'o str, Response> {
+        let best = negotiation::best_offer(dimension, self.get_known_header_line(dimension.header()).as_deref(), offers);
+        let mut negotiated = self.negotiated.borrow_mut();
+        if !negotiated.contains(&dimension.header()) {
+            negotiated.push(dimension.header());
+        }
+        best.ok_or_else(|| {
+            Response::new(HTTPStatus::NotAcceptable)
+                .text(format!("Available {header} values: {offers}\n", header = dimension.header(), offers = offers.join(", ")))
+        })
+    }
+
+    /// Headers `negotiate` looked at so far.
+    pub fn vary_headers(&self) -> Vec<String> {
+        self.negotiated.borrow().iter().map(HTTPHeader::to_string).collect()
+    }
+
+    /// The body as a stream, for handlers that pass it on rather than look at it. Unless
+    /// `body` read it already, it comes straight from the connection and can be read once.
+    pub fn body_stream(&self) -> Result<Box<dyn Read + '_>, BodyError> {
+        if let Some(reader) = self.body_reader.borrow_mut().take() {
+            return Ok(reader.0);
+        }
+        match self.body.get() {
+            Some(body) => Ok(Box::new(body.as_slice())),
+            None => {
+                eprintln!("The body of `{method} {resource}` was streamed already", method = self.method, resource = self.resource);
+                Err(BodyError::Consumed)
+            }
+        }
+    }
+
+    /// Parser for a `multipart/form-data` body, `None` for other content types. Unless `body`
+    /// read it already, the body is streamed from the connection as the parser is read.
+    pub fn multipart(&self) -> Option<Multipart<Box<dyn Read + '_>>> {
+        let boundary = multipart::boundary(&self.get_known_header_line(HTTPHeader::ContentType)?)?;
+        Some(Multipart::new(self.body_stream().ok()?, &boundary))
+    }
+
+    /// All values of the header as the single field line the client sent.
+    /// Needed for headers like dates, which contain `", "` themselves.
+    pub fn get_known_header_line(&self, header_name: HTTPHeader) -> Option<String> {
+        self.get_known_header_values(header_name).map(|values| values.join(", "))
+    }
 
     pub fn get_known_header_values(&self, header_name: HTTPHeader) -> Option<&Vec<String>> {
         match self.headers.get(&header_name.to_string()) {
@@ -166,3 +462,101 @@ impl Request {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn request(raw: &'static str) -> io::Result<Request> {
+        Request::new(raw.as_bytes())
+    }
+
+    fn config() -> Config {
+        Config::new("127.0.0.1", 0, None)
+    }
+
+    #[test]
+    fn parses_the_head_and_reads_the_body_on_demand() {
+        let request = request("POST /a?b=c HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert_eq!(request.method, HTTPMethod::POST);
+        assert_eq!(request.path(), "/a");
+        assert_eq!(request.query_string(), Some("b=c"));
+        assert_eq!(request.content_length(), 5);
+        assert_eq!(request.body(&config()), Ok(&b"hello"[..]));
+        assert_eq!(request.body(&config()), Ok(&b"hello"[..]));
+    }
+
+    #[test]
+    fn rejects_invalid_content_lengths() {
+        let error = request("POST / HTTP/1.1\r\nContent-Length: five\r\n\r\n").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reports_short_bodies() {
+        let request = request("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello").unwrap();
+        assert_eq!(request.body(&config()), Err(BodyError::Incomplete));
+    }
+
+    #[test]
+    fn streams_multipart_bodies() {
+        let request = request("POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=b\r\nContent-Length: 56\r\n\r\n\
+            --b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b--").unwrap();
+        let mut form = request.multipart().unwrap();
+        assert_eq!(form.next_part().unwrap().map(|part| part.name).as_deref(), Some("a"));
+        drop(form);
+        assert_eq!(request.body(&config()), Err(BodyError::Consumed));
+    }
+
+    #[test]
+    fn limits_buffered_bodies() {
+        let mut config = config();
+        config.max_buffered_body_size = 4;
+        let request = request("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert_eq!(request.body(&config), Err(BodyError::TooLarge(4)));
+        // Nothing was read, so the body can still be streamed
+        let mut body = String::new();
+        request.body_stream().unwrap().read_to_string(&mut body).unwrap();
+        assert_eq!(body, "hello");
+    }
+
+    #[test]
+    fn limits_line_length_and_header_count() {
+        let too_large = |raw: String| HeadTooLarge::from_error(&Request::new(io::Cursor::new(raw.into_bytes())).unwrap_err());
+
+        let target = "a".repeat(MAX_LINE_LENGTH);
+        assert_eq!(too_large(format!("GET /{target} HTTP/1.1\r\n\r\n")), Some(HeadTooLarge::RequestLine));
+        let value = "v".repeat(MAX_LINE_LENGTH);
+        assert_eq!(too_large(format!("GET / HTTP/1.1\r\nX-Long: {value}\r\n\r\n")), Some(HeadTooLarge::HeaderLine));
+        let fields = "X-Field: 1\r\n".repeat(MAX_HEADER_COUNT + 1);
+        assert_eq!(too_large(format!("GET / HTTP/1.1\r\n{fields}\r\n")), Some(HeadTooLarge::HeaderCount));
+        assert_eq!(HeadTooLarge::RequestLine.status(), HTTPStatus::URITooLong);
+        assert_eq!(HeadTooLarge::HeaderCount.status(), HTTPStatus::RequestHeaderFieldsTooLarge);
+
+        let longest = "v".repeat(MAX_LINE_LENGTH - "X-Long: ".len());
+        let fields = "X-Field: 1\r\n".repeat(MAX_HEADER_COUNT - 1);
+        let raw = format!("GET / HTTP/1.1\r\n{fields}X-Long: {longest}\r\n\r\n");
+        let request = Request::new(io::Cursor::new(raw.into_bytes())).unwrap();
+        assert_eq!(request.headers.get("X-Long").map(|values| values[0].len()), Some(longest.len()));
+        assert_eq!(too_large("GET / HTTP/1.1\r\nbroken\r\n\r\n".to_string()), None);
+    }
+
+    #[test]
+    fn checks_the_form_size_before_reading() {
+        let config = Config::new("127.0.0.1", 0, None);
+        let raw = format!("POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n", config.max_buffered_body_size + 1);
+        let request = Request::new(io::Cursor::new(raw.into_bytes())).unwrap();
+        assert_eq!(request.form(&config).unwrap_err(), FormError::TooLarge(config.max_buffered_body_size));
+    }
+
+    #[test]
+    fn negotiates_the_language_and_records_it_for_vary() {
+        let request = request("GET / HTTP/1.1\r\nAccept-Language: de;q=0, en\r\nAccept: text/html\r\n\r\n").unwrap();
+        assert_eq!(request.negotiate(Dimension::Language, &["de", "en-GB"]).unwrap(), "en-GB");
+        assert_eq!(request.negotiate(Dimension::Language, &["de"]).unwrap_err().status, HTTPStatus::NotAcceptable);
+        assert_eq!(request.negotiate(Dimension::MediaType, &["text/html"]).unwrap(), "text/html");
+        assert_eq!(request.vary_headers(), vec!["Accept-Language", "Accept"]);
+    }
+}
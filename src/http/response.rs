@@ -1,26 +1,16 @@
-use std::io::{BufWriter, Write};
+use std::fmt::Display;
+use std::io::{self, ErrorKind, IoSlice, Write};
+use std::net::TcpStream;
+use std::time::SystemTime;
 
-use nom::AsBytes;
-
-use crate::http::{Body, HeaderName};
-use crate::http::headers::{HeaderMap, HTTPHeader};
-
-#[derive(Debug)]
-pub enum HTTPStatus {
-    Ok,
-    Created,
-    NotFound,
-}
-
-impl HTTPStatus {
-    pub fn to_string(&self) -> &'static str {
-        match self {
-            HTTPStatus::Ok => "200 OK",
-            HTTPStatus::Created => "201 Created",
-            HTTPStatus::NotFound => "404 Not Found"
-        }
-    }
-}
+use crate::http::{Body, FileRegion, Segment};
+use crate::http::cookie::SetCookie;
+use crate::http::date::format_http_date;
+use crate::http::headers::{HeaderMap, HTTPHeader, is_combinable};
+use crate::http::request::{BodyError, FormError, JsonError};
+use crate::http::sendfile::send_region;
+use crate::http::status::HTTPStatus;
+use crate::json::Value;
 
 #[derive(Debug)]
 pub struct Response {
@@ -32,10 +22,6 @@ pub struct Response {
 
 impl Response {
     const LINE_FEED: &'static str = "\r\n";
-    // TODO
-    // Add set_body, set_headers
-    // Add method for response headers separator and body separator
-    // Add method to add body length header (or add it by default?)
     pub fn new(status: HTTPStatus) -> Self {
         Self {
             status,
@@ -45,6 +31,59 @@ impl Response {
         }
     }
 
+    pub fn ok() -> Self {
+        Response::new(HTTPStatus::Ok)
+    }
+
+    pub fn not_found() -> Self {
+        Response::new(HTTPStatus::NotFound)
+    }
+
+    /// `302 Found` pointing at `location`.
+    pub fn redirect(location: &str) -> Self {
+        Response::new(HTTPStatus::Found).header(HTTPHeader::Location, location)
+    }
+
+    pub fn status(mut self, status: HTTPStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Appends a header value, `header_name` being either a `HTTPHeader` or a custom name.
+    pub fn header(mut self, header_name: impl Display, header_value: impl Display) -> Self {
+        self.headers.append(header_name.to_string(), vec![header_value.to_string()]);
+        self
+    }
+
+    /// Adds a `Set-Cookie` header; every cookie goes out on a field line of its own.
+    pub fn cookie(self, cookie: SetCookie) -> Self {
+        self.header(HTTPHeader::SetCookie, cookie)
+    }
+
+    /// Sets the body together with its `Content-Type`.
+    pub fn content(mut self, content_type: &str, body: impl Into<Body>) -> Self {
+        self.set_known_header(HTTPHeader::ContentType, vec![content_type]);
+        self.set_body(body.into());
+        self
+    }
+
+    pub fn text(self, body: impl Into<String>) -> Self {
+        self.content("text/plain", body.into())
+    }
+
+    /// Declares UTF-8, the encoding of every `String`, so browsers don't have to guess.
+    pub fn html(self, body: impl Into<String>) -> Self {
+        self.content("text/html; charset=utf-8", body.into())
+    }
+
+    pub fn json(self, value: impl Into<Value>) -> Self {
+        self.content("application/json", value.into().to_string())
+    }
+
+    pub fn bytes(self, body: impl Into<Body>) -> Self {
+        self.content("application/octet-stream", body)
+    }
+
     pub fn set_http_version(&mut self, http_version: &str) {
         self.http_version = Some(http_version.to_string())
     }
@@ -53,63 +92,364 @@ impl Response {
         self.body = Some(body)
     }
 
+    /// Appends values to the header, keeping any values already set.
     pub fn add_known_header(&mut self, header_name: HTTPHeader, header_values: Vec<&str>) {
-        self.insert_header_values(HeaderName::Known(header_name), header_values);
+        self.append_header_values(header_name, header_values);
     }
-    #[allow(dead_code)]
-    pub fn add_custom_header(&mut self, header_name: String, header_values: Vec<&str>) {
-        self.insert_header_values(HeaderName::Custom(header_name), header_values);
+
+    /// Replaces every value of the header.
+    pub fn set_known_header(&mut self, header_name: HTTPHeader, header_values: Vec<&str>) {
+        self.insert_header_values(header_name, header_values);
     }
 
-    fn insert_header_values(&mut self, header_name: HeaderName, header_values: Vec<&str>) {
-        let header_name = match header_name {
-            HeaderName::Known(header) => header.to_string(),
-            HeaderName::Custom(header) => header
-        };
-        self.headers.insert(header_name, header_values.iter().map(|&value| { value.to_string() }).collect());
+    /// Adds a request header the response depends on to `Vary`, unless it is listed already.
+    pub fn add_vary(&mut self, header_name: &str) {
+        let varies = self.headers.get(&HTTPHeader::Vary.to_string())
+            .is_some_and(|values| values.iter().any(|value| value == "*" || value.eq_ignore_ascii_case(header_name)));
+        if !varies {
+            self.add_known_header(HTTPHeader::Vary, vec![header_name]);
+        }
+    }
+
+    fn append_header_values(&mut self, header_name: HTTPHeader, header_values: Vec<&str>) {
+        self.headers.append(header_name.to_string(), header_values.iter().map(|&value| { value.to_string() }).collect());
     }
 
-    fn write_line_feed(buffer: &mut BufWriter<Vec<u8>>) -> std::io::Result<usize> {
-        // Response::LINE_FEED.as_bytes()
-        buffer.write(Response::LINE_FEED.as_bytes())
+    fn insert_header_values(&mut self, header_name: HTTPHeader, header_values: Vec<&str>) {
+        self.headers.insert(header_name.to_string(), header_values.iter().map(|&value| { value.to_string() }).collect());
     }
 
-    pub fn set_content_length_header(&mut self) {
-        match &self.body {
-            None => {}
-            Some(body) => self.add_known_header(HTTPHeader::ContentLength, vec![body.len().to_string().as_str()])
+    /// 1xx, 204 and 304 responses never carry content.
+    pub fn allows_body(&self) -> bool {
+        !(self.status.is_informational() || self.status == HTTPStatus::NoContent || self.status == HTTPStatus::NotModified)
+    }
+
+    fn is_chunked(&self) -> bool {
+        match self.headers.get(&HTTPHeader::TransferEncoding.to_string()) {
+            None => false,
+            Some(values) => values.last().is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
         }
     }
 
-    pub fn try_into_bytes(&self) -> BufWriter<Vec<u8>> {
-        let mut buf = BufWriter::new(Vec::with_capacity(1));
+    /// Derives `Content-Length`/`Transfer-Encoding` from the body, overwriting whatever the
+    /// handler set, and fills in `Date` and `Server` unless the handler provided them.
+    pub fn finalize(&mut self, server_name: Option<&str>) {
+        self.headers.remove(&HTTPHeader::ContentLength.to_string());
+
+        if !self.allows_body() {
+            self.headers.remove(&HTTPHeader::TransferEncoding.to_string());
+            self.body = None;
+        } else if self.is_chunked() && self.http_version.as_deref() != Some("HTTP/1.0") {
+            // Chunked framing is applied by the serializer
+            self.body.get_or_insert_with(Body::default);
+        } else {
+            self.headers.remove(&HTTPHeader::TransferEncoding.to_string());
+            let content_length = self.body.as_ref().map_or(0, |body| body.len());
+            self.set_known_header(HTTPHeader::ContentLength, vec![content_length.to_string().as_str()]);
+        }
 
-        let status_line = format!(
-            "{http_version} {status}",
-            status = self.status.to_string(),
-            http_version = self.http_version.clone().unwrap_or("HTTP/1.1".to_string())
+        if !self.headers.contains(&HTTPHeader::Date.to_string()) {
+            self.set_known_header(HTTPHeader::Date, vec![format_http_date(SystemTime::now()).as_str()]);
+        }
+        if let Some(server_name) = server_name {
+            if !self.headers.contains(&HTTPHeader::Server.to_string()) {
+                self.set_known_header(HTTPHeader::Server, vec![server_name]);
+            }
+        }
+    }
+
+    /// Status line and header section, terminated by the empty line that precedes the body.
+    /// Headers are written in insertion order; non-combinable headers get one field line per value.
+    pub fn head_bytes(&self) -> Vec<u8> {
+        let mut head: Vec<u8> = Vec::with_capacity(0x100);
+        // Writing into a `Vec` cannot fail
+        let _ = write!(
+            head,
+            "{http_version} {status}{line_feed}",
+            status = self.status,
+            http_version = self.http_version.as_deref().unwrap_or("HTTP/1.1"),
+            line_feed = Response::LINE_FEED
         );
 
-        buf.write(status_line.as_bytes()).unwrap();
+        for (header_name, header_values) in self.headers.iter() {
+            if is_combinable(header_name) {
+                let _ = write!(head, "{header_name}: {values}{line_feed}", values = header_values.join(", "), line_feed = Response::LINE_FEED);
+            } else {
+                for value in header_values {
+                    let _ = write!(head, "{header_name}: {value}{line_feed}", line_feed = Response::LINE_FEED);
+                }
+            }
+        }
+        head.extend_from_slice(Response::LINE_FEED.as_bytes());
+
+        head
+    }
+
+    /// Writes the head and body with vectored I/O, so in-memory bodies go out without being
+    /// copied. File-backed bodies are copied through a buffer. Tests use it to serialize
+    /// into memory; the server sends with `send_to`.
+    #[cfg(test)]
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_framed(writer, |region, writer| crate::http::sendfile::copy_region(region, writer))
+    }
 
-        let _ = Response::write_line_feed(&mut buf);
+    /// Like `write_to`, but file-backed bodies are handed to the kernel with `sendfile(2)`.
+    pub fn send_to(&self, stream: &TcpStream) -> io::Result<()> {
+        let mut writer = stream;
+        self.write_framed(&mut writer, |region, writer| send_region(region, writer))
+    }
 
-        // TODO Move to Headers.try_into_bytes()
-        let headers = self.headers.iter().map(|(header_name, header_value)| {
-            return format!("{header_name}: {values}", values = header_value.join(", "));
-        }).collect::<Vec<_>>().join(Response::LINE_FEED);
+    fn write_framed<W: Write>(&self, writer: &mut W, write_file: impl Fn(&FileRegion, &mut W) -> io::Result<()>) -> io::Result<()> {
+        let head = self.head_bytes();
+        let body_length = self.body.as_ref().map_or(0, |body| body.len());
+        // Only a finalized reply to `HEAD` is chunked without a body, and it sends no chunks
+        let chunked = self.is_chunked() && self.body.is_some();
 
-        buf.write(headers.as_bytes()).unwrap();
-        let _ = Response::write_line_feed(&mut buf);
-        let _ = Response::write_line_feed(&mut buf);
+        let chunk_size_line = format!("{length:x}{line_feed}", length = body_length, line_feed = Response::LINE_FEED);
+        let last_chunk = format!("0{line_feed}{line_feed}", line_feed = Response::LINE_FEED);
+        let mut prefix: Vec<&[u8]> = vec![&head];
+        let mut suffix: Vec<&[u8]> = Vec::with_capacity(2);
+        if chunked && body_length > 0 {
+            prefix.push(chunk_size_line.as_bytes());
+            suffix.push(Response::LINE_FEED.as_bytes());
+        }
+        if chunked {
+            suffix.push(last_chunk.as_bytes());
+        }
 
+        let mut segments = prefix.into_iter().map(Segment::Memory).collect::<Vec<_>>();
+        if let Some(body) = &self.body {
+            segments.extend(body.segments());
+        }
+        segments.extend(suffix.into_iter().map(Segment::Memory));
+
+        // Runs of in-memory segments go out in one vectored write
+        let mut buffers: Vec<&[u8]> = Vec::with_capacity(segments.len());
+        for segment in segments {
+            match segment {
+                Segment::Memory(bytes) => buffers.push(bytes),
+                Segment::File(region) => {
+                    write_all_vectored(writer, &mut buffers)?;
+                    buffers.clear();
+                    write_file(region, writer)?;
+                }
+            }
+        }
+        write_all_vectored(writer, &mut buffers)
+    }
+}
+
+/// Like `Write::write_all` for several buffers at once. Partial writes are tracked by hand
+/// because `IoSlice::advance_slices` is newer than the toolchain we target.
+fn write_all_vectored(writer: &mut impl Write, buffers: &mut [&[u8]]) -> io::Result<()> {
+    let mut remaining = buffers;
+    while remaining.iter().any(|buffer| !buffer.is_empty()) {
+        let slices = remaining.iter().map(|buffer| IoSlice::new(buffer)).collect::<Vec<_>>();
+        let mut written = match writer.write_vectored(&slices) {
+            Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole response")),
+            Ok(written) => written,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error)
+        };
 
-        match &self.body {
-            None => {}
-            Some(body) => {
-                buf.write(body.content.as_bytes()).unwrap();
+        let mut fully_written = 0;
+        for buffer in remaining.iter_mut() {
+            if written < buffer.len() {
+                *buffer = &buffer[written..];
+                break;
             }
+            written -= buffer.len();
+            fully_written += 1;
         }
-        buf
+        remaining = &mut remaining[fully_written..];
+    }
+    Ok(())
+}
+
+impl From<HTTPStatus> for Response {
+    fn from(status: HTTPStatus) -> Self {
+        Response::new(status)
+    }
+}
+
+impl From<&str> for Response {
+    fn from(body: &str) -> Self {
+        Response::ok().text(body)
+    }
+}
+
+impl From<String> for Response {
+    fn from(body: String) -> Self {
+        Response::ok().text(body)
+    }
+}
+
+impl From<Vec<u8>> for Response {
+    fn from(body: Vec<u8>) -> Self {
+        Response::ok().bytes(body)
+    }
+}
+
+impl From<FormError> for Response {
+    fn from(error: FormError) -> Self {
+        let status = match error {
+            FormError::UnsupportedMediaType | FormError::UnsupportedCharset(_) => HTTPStatus::UnsupportedMediaType,
+            FormError::TooLarge(_) => HTTPStatus::ContentTooLarge,
+            FormError::Body(error) => return error.into()
+        };
+        Response::new(status).text(error.to_string())
+    }
+}
+
+impl From<BodyError> for Response {
+    fn from(error: BodyError) -> Self {
+        match error {
+            BodyError::Incomplete => Response::new(HTTPStatus::BadRequest).text(error.to_string()),
+            BodyError::Consumed => Response::new(HTTPStatus::InternalServerError),
+            BodyError::TooLarge(_) => Response::new(HTTPStatus::ContentTooLarge).text(error.to_string())
+        }
+    }
+}
+
+impl From<JsonError> for Response {
+    fn from(error: JsonError) -> Self {
+        match error {
+            JsonError::UnsupportedMediaType => Response::new(HTTPStatus::UnsupportedMediaType)
+                .json(Value::object([("error", Value::from("Expected an application/json body in UTF-8"))])),
+            JsonError::Syntax(error) => Response::new(HTTPStatus::BadRequest).json(Value::object([
+                ("error", Value::from(error.to_string())),
+                ("offset", Value::from(error.offset as u64)),
+                ("line", Value::from(error.line as u64)),
+                ("column", Value::from(error.column as u64)),
+            ])),
+            JsonError::TooLarge(limit) => Response::new(HTTPStatus::ContentTooLarge)
+                .json(Value::object([("error", Value::from(format!("JSON bodies are limited to {limit} bytes")))])),
+            JsonError::Body(error) => error.into()
+        }
+    }
+}
+
+/// Lets handlers return early with `?` on anything that converts into a `Response`.
+impl<T: Into<Response>, E: Into<Response>> From<Result<T, E>> for Response {
+    fn from(result: Result<T, E>) -> Self {
+        match result {
+            Ok(response) => response.into(),
+            Err(response) => response.into()
+        }
+    }
+}
+
+impl<T: Into<Response>> From<(HTTPStatus, T)> for Response {
+    fn from((status, response): (HTTPStatus, T)) -> Self {
+        response.into().status(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const DATE: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    /// Finalizes with a fixed `Date` so the output can be compared byte for byte.
+    fn serialize(mut response: Response) -> String {
+        response.set_known_header(HTTPHeader::Date, vec![DATE]);
+        response.finalize(Some("test"));
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn writes_headers_in_insertion_order() {
+        let response = Response::ok().header("X-Second", "b").header("X-First", "a").text("hi");
+        assert_eq!(
+            serialize(response),
+            format!("HTTP/1.1 200 OK\r\nX-Second: b\r\nX-First: a\r\nContent-Type: text/plain\r\nDate: {DATE}\r\nContent-Length: 2\r\nServer: test\r\n\r\nhi")
+        );
+    }
+
+    #[test]
+    fn writes_every_set_cookie_on_its_own_line() {
+        let response = Response::new(HTTPStatus::NoContent)
+            .header(HTTPHeader::SetCookie, "a=1; Expires=Sun, 06 Nov 1994 08:49:37 GMT")
+            .header(HTTPHeader::Vary, "Accept")
+            .header(HTTPHeader::SetCookie, "b=2")
+            .header(HTTPHeader::Vary, "Cookie");
+        assert_eq!(
+            serialize(response),
+            format!("HTTP/1.1 204 No Content\r\nSet-Cookie: a=1; Expires=Sun, 06 Nov 1994 08:49:37 GMT\r\nSet-Cookie: b=2\r\nVary: Accept, Cookie\r\nDate: {DATE}\r\nServer: test\r\n\r\n")
+        );
+    }
+
+    #[test]
+    fn keeps_headers_set_by_the_handler() {
+        let mut response = Response::ok().header(HTTPHeader::Server, "custom").header(HTTPHeader::Date, "yesterday");
+        response.finalize(Some("test"));
+        assert_eq!(response.headers.get("server"), Some(&vec!["custom".to_string()]));
+        assert_eq!(response.headers.get("date"), Some(&vec!["yesterday".to_string()]));
+    }
+
+    #[test]
+    fn derives_content_length_from_the_body() {
+        let response = Response::ok().header(HTTPHeader::ContentLength, "999").bytes(b"abc".to_vec());
+        assert_eq!(
+            serialize(response),
+            format!("HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nDate: {DATE}\r\nContent-Length: 3\r\nServer: test\r\n\r\nabc")
+        );
+        assert_eq!(serialize(Response::not_found()), format!("HTTP/1.1 404 Not Found\r\nDate: {DATE}\r\nContent-Length: 0\r\nServer: test\r\n\r\n"));
+    }
+
+    #[test]
+    fn frames_chunked_bodies() {
+        let response = Response::ok().header(HTTPHeader::TransferEncoding, "chunked").text("hello world");
+        assert_eq!(
+            serialize(response),
+            format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Type: text/plain\r\nDate: {DATE}\r\nServer: test\r\n\r\nb\r\nhello world\r\n0\r\n\r\n")
+        );
+        let empty = Response::ok().header(HTTPHeader::TransferEncoding, "chunked");
+        assert_eq!(
+            serialize(empty),
+            format!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nDate: {DATE}\r\nServer: test\r\n\r\n0\r\n\r\n")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_content_length_for_http_1_0() {
+        let mut response = Response::ok().header(HTTPHeader::TransferEncoding, "chunked").text("hello");
+        response.set_http_version("HTTP/1.0");
+        assert_eq!(
+            serialize(response),
+            format!("HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\nDate: {DATE}\r\nContent-Length: 5\r\nServer: test\r\n\r\nhello")
+        );
+    }
+
+    #[test]
+    fn drops_content_of_responses_without_a_body() {
+        for status in [HTTPStatus::Continue, HTTPStatus::NoContent, HTTPStatus::NotModified] {
+            let response = Response::new(status.clone())
+                .header(HTTPHeader::TransferEncoding, "chunked")
+                .header(HTTPHeader::ContentLength, "5")
+                .text("hello");
+            assert_eq!(
+                serialize(response),
+                format!("HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nDate: {DATE}\r\nServer: test\r\n\r\n")
+            );
+        }
+    }
+
+    #[test]
+    fn adds_vary_once() {
+        let mut response = Response::ok();
+        response.add_vary("Accept");
+        response.add_vary("accept");
+        response.add_vary("Accept-Encoding");
+        assert_eq!(response.headers.get("Vary"), Some(&vec!["Accept".to_string(), "Accept-Encoding".to_string()]));
+
+        let mut response = Response::ok().header(HTTPHeader::Vary, "*");
+        response.add_vary("Cookie");
+        assert_eq!(response.headers.get("Vary"), Some(&vec!["*".to_string()]));
     }
 }
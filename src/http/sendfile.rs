@@ -0,0 +1,69 @@
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+
+use crate::http::FileRegion;
+
+/// Copies the region through a userspace buffer. Used for writers that are not plain sockets,
+/// and whenever the kernel refuses `sendfile(2)` for the file.
+pub fn copy_region(region: &FileRegion, writer: &mut impl Write) -> io::Result<()> {
+    let mut file: &File = &region.file;
+    file.seek(SeekFrom::Start(region.offset))?;
+    let copied = io::copy(&mut file.take(region.length), writer)?;
+    if copied < region.length {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "File shrank while being sent"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::os::raw::c_int;
+
+    pub const EINVAL: i32 = 22;
+    pub const ENOSYS: i32 = 38;
+    /// Linux transfers at most this many bytes per `sendfile` call anyway.
+    pub const MAX_CHUNK: i64 = 0x7fff_f000;
+
+    extern "C" {
+        pub fn sendfile64(out_fd: c_int, in_fd: c_int, offset: *mut i64, count: usize) -> isize;
+    }
+}
+
+/// Sends the region with `sendfile(2)`, so the content goes from the page cache to the socket
+/// without passing through userspace.
+#[cfg(target_os = "linux")]
+pub fn send_region(region: &FileRegion, stream: &TcpStream) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let start = region.offset as i64;
+    let end = start + region.length as i64;
+    let mut offset = start;
+    while offset < end {
+        let count = (end - offset).min(linux::MAX_CHUNK) as usize;
+        // SAFETY: both descriptors are owned by live values for the duration of the call
+        // and `offset` is a valid, exclusive pointer.
+        let sent = unsafe { linux::sendfile64(stream.as_raw_fd(), region.file.as_raw_fd(), &mut offset, count) };
+        if sent > 0 {
+            continue;
+        }
+        if sent == 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "File shrank while being sent"));
+        }
+
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            _ if error.kind() == ErrorKind::Interrupted => continue,
+            Some(linux::EINVAL) | Some(linux::ENOSYS) if offset == start => {
+                return copy_region(region, &mut &*stream);
+            }
+            _ => return Err(error)
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn send_region(region: &FileRegion, stream: &TcpStream) -> io::Result<()> {
+    copy_region(region, &mut &*stream)
+}
@@ -0,0 +1,248 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Why a status was rejected, with the rejected input as given.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvalidStatus {
+    /// Not a number in `100..=599`.
+    Code(String),
+    /// Contains control characters other than tabs, which would end the status line early.
+    ReasonPhrase(String),
+}
+
+impl Display for InvalidStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidStatus::Code(code) => write!(f, "`{code}` is not a valid HTTP status code"),
+            InvalidStatus::ReasonPhrase(reason) => write!(f, "`{}` is not a valid reason phrase", reason.escape_debug())
+        }
+    }
+}
+
+/// An unregistered status. The fields are private so it can only be made by
+/// `HTTPStatus::custom`, which checks them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomStatus {
+    code: u16,
+    reason: String,
+}
+
+macro_rules! http_statuses {
+    ($($variant:ident => ($code:literal, $reason:literal),)+) => {
+        /// Every status code registered with IANA, plus `Custom` for anything else in `100..=599`.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum HTTPStatus {
+            $($variant,)+
+            Custom(CustomStatus),
+        }
+
+        impl HTTPStatus {
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(HTTPStatus::$variant => $code,)+
+                    HTTPStatus::Custom(custom) => custom.code,
+                }
+            }
+
+            pub fn reason_phrase(&self) -> &str {
+                match self {
+                    $(HTTPStatus::$variant => $reason,)+
+                    HTTPStatus::Custom(custom) => custom.reason.as_str(),
+                }
+            }
+
+            /// Looks up a registered status; unregistered codes yield `None`.
+            pub fn from_registered_code(code: u16) -> Option<Self> {
+                match code {
+                    $($code => Some(HTTPStatus::$variant),)+
+                    _ => None
+                }
+            }
+        }
+    };
+}
+
+http_statuses! {
+    Continue => (100, "Continue"),
+    SwitchingProtocols => (101, "Switching Protocols"),
+    Processing => (102, "Processing"),
+    EarlyHints => (103, "Early Hints"),
+    Ok => (200, "OK"),
+    Created => (201, "Created"),
+    Accepted => (202, "Accepted"),
+    NonAuthoritativeInformation => (203, "Non-Authoritative Information"),
+    NoContent => (204, "No Content"),
+    ResetContent => (205, "Reset Content"),
+    PartialContent => (206, "Partial Content"),
+    MultiStatus => (207, "Multi-Status"),
+    AlreadyReported => (208, "Already Reported"),
+    IMUsed => (226, "IM Used"),
+    MultipleChoices => (300, "Multiple Choices"),
+    MovedPermanently => (301, "Moved Permanently"),
+    Found => (302, "Found"),
+    SeeOther => (303, "See Other"),
+    NotModified => (304, "Not Modified"),
+    UseProxy => (305, "Use Proxy"),
+    TemporaryRedirect => (307, "Temporary Redirect"),
+    PermanentRedirect => (308, "Permanent Redirect"),
+    BadRequest => (400, "Bad Request"),
+    Unauthorized => (401, "Unauthorized"),
+    PaymentRequired => (402, "Payment Required"),
+    Forbidden => (403, "Forbidden"),
+    NotFound => (404, "Not Found"),
+    MethodNotAllowed => (405, "Method Not Allowed"),
+    NotAcceptable => (406, "Not Acceptable"),
+    ProxyAuthenticationRequired => (407, "Proxy Authentication Required"),
+    RequestTimeout => (408, "Request Timeout"),
+    Conflict => (409, "Conflict"),
+    Gone => (410, "Gone"),
+    LengthRequired => (411, "Length Required"),
+    PreconditionFailed => (412, "Precondition Failed"),
+    ContentTooLarge => (413, "Content Too Large"),
+    URITooLong => (414, "URI Too Long"),
+    UnsupportedMediaType => (415, "Unsupported Media Type"),
+    RangeNotSatisfiable => (416, "Range Not Satisfiable"),
+    ExpectationFailed => (417, "Expectation Failed"),
+    MisdirectedRequest => (421, "Misdirected Request"),
+    UnprocessableContent => (422, "Unprocessable Content"),
+    Locked => (423, "Locked"),
+    FailedDependency => (424, "Failed Dependency"),
+    TooEarly => (425, "Too Early"),
+    UpgradeRequired => (426, "Upgrade Required"),
+    PreconditionRequired => (428, "Precondition Required"),
+    TooManyRequests => (429, "Too Many Requests"),
+    RequestHeaderFieldsTooLarge => (431, "Request Header Fields Too Large"),
+    UnavailableForLegalReasons => (451, "Unavailable For Legal Reasons"),
+    InternalServerError => (500, "Internal Server Error"),
+    NotImplemented => (501, "Not Implemented"),
+    BadGateway => (502, "Bad Gateway"),
+    ServiceUnavailable => (503, "Service Unavailable"),
+    GatewayTimeout => (504, "Gateway Timeout"),
+    HTTPVersionNotSupported => (505, "HTTP Version Not Supported"),
+    VariantAlsoNegotiates => (506, "Variant Also Negotiates"),
+    InsufficientStorage => (507, "Insufficient Storage"),
+    LoopDetected => (508, "Loop Detected"),
+    NotExtended => (510, "Not Extended"),
+    NetworkAuthenticationRequired => (511, "Network Authentication Required"),
+}
+
+impl HTTPStatus {
+    /// A status outside the registry. Registered codes map to their variant, keeping the
+    /// canonical reason phrase, so equal codes always compare equal.
+    pub fn custom(code: u16, reason: impl Into<String>) -> Result<Self, InvalidStatus> {
+        if !(100..=599).contains(&code) {
+            return Err(InvalidStatus::Code(code.to_string()));
+        }
+        let reason = reason.into();
+        if reason.chars().any(|c| c.is_control() && c != '\t') {
+            return Err(InvalidStatus::ReasonPhrase(reason));
+        }
+        Ok(HTTPStatus::from_registered_code(code).unwrap_or(HTTPStatus::Custom(CustomStatus { code, reason })))
+    }
+
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.code())
+    }
+}
+
+impl TryFrom<u16> for HTTPStatus {
+    type Error = InvalidStatus;
+
+    /// Registered codes map to their variant, any other code in `100..=599` becomes
+    /// `Custom` with an empty reason phrase.
+    fn try_from(code: u16) -> Result<Self, Self::Error> {
+        HTTPStatus::custom(code, "")
+    }
+}
+
+impl FromStr for HTTPStatus {
+    type Err = InvalidStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim().parse::<u16>().map_err(|_| InvalidStatus::Code(s.to_string()))?;
+        HTTPStatus::try_from(code)
+    }
+}
+
+impl Display for HTTPStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{code} {reason}", code = self.code(), reason = self.reason_phrase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn custom_maps_registered_codes_to_their_variant() {
+        assert_eq!(HTTPStatus::custom(404, "Nope"), Ok(HTTPStatus::NotFound));
+        let custom = HTTPStatus::custom(460, "Checksum Mismatch").unwrap();
+        assert_eq!((custom.code(), custom.reason_phrase()), (460, "Checksum Mismatch"));
+        assert_eq!(custom, HTTPStatus::custom(460, "Checksum Mismatch").unwrap());
+    }
+
+    #[test]
+    fn custom_rejects_codes_out_of_range() {
+        assert_eq!(HTTPStatus::custom(99, "Low"), Err(InvalidStatus::Code("99".to_string())));
+        assert_eq!(HTTPStatus::custom(600, "High"), Err(InvalidStatus::Code("600".to_string())));
+    }
+
+    #[test]
+    fn custom_rejects_line_breaks_in_reason_phrases() {
+        let injected = "OK\r\nSet-Cookie: admin=1";
+        assert_eq!(HTTPStatus::custom(299, injected), Err(InvalidStatus::ReasonPhrase(injected.to_string())));
+        assert_eq!(
+            HTTPStatus::custom(299, "a\nb").unwrap_err().to_string(),
+            "`a\\nb` is not a valid reason phrase"
+        );
+        assert_eq!(HTTPStatus::custom(299, "Tab\tand caf\u{e9}").unwrap().reason_phrase(), "Tab\tand caf\u{e9}");
+    }
+
+    #[test]
+    fn parses_codes() {
+        assert_eq!(" 201 ".parse(), Ok(HTTPStatus::Created));
+        assert_eq!("299".parse::<HTTPStatus>().map(|status| status.to_string()), Ok("299 ".to_string()));
+        assert_eq!("abc".parse::<HTTPStatus>(), Err(InvalidStatus::Code("abc".to_string())));
+        assert_eq!("abc".parse::<HTTPStatus>().unwrap_err().to_string(), "`abc` is not a valid HTTP status code");
+    }
+
+    #[test]
+    fn displays_code_and_reason() {
+        assert_eq!(HTTPStatus::ContentTooLarge.to_string(), "413 Content Too Large");
+    }
+
+    #[test]
+    fn classifies_codes() {
+        let classes = |status: HTTPStatus| [
+            status.is_informational(),
+            status.is_success(),
+            status.is_redirection(),
+            status.is_client_error(),
+            status.is_server_error(),
+        ];
+        assert_eq!(classes(HTTPStatus::EarlyHints), [true, false, false, false, false]);
+        assert_eq!(classes(HTTPStatus::NoContent), [false, true, false, false, false]);
+        assert_eq!(classes(HTTPStatus::NotModified), [false, false, true, false, false]);
+        assert_eq!(classes(HTTPStatus::custom(499, "Client Closed Request").unwrap()), [false, false, false, true, false]);
+        assert_eq!(classes(HTTPStatus::NetworkAuthenticationRequired), [false, false, false, false, true]);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::http::charset::Charset;
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None
+    }
+}
+
+/// Decodes `%XX` escapes. Returns `None` for malformed escapes.
+pub fn percent_decode_bytes(input: &str) -> Option<Vec<u8>> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let high = hex_value(*bytes.get(index + 1)?)?;
+            let low = hex_value(*bytes.get(index + 2)?)?;
+            decoded.push(high << 4 | low);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Decodes `%XX` escapes. Returns `None` for malformed escapes or if the result is not UTF-8.
+pub fn percent_decode(input: &str) -> Option<String> {
+    String::from_utf8(percent_decode_bytes(input)?).ok()
+}
+
+/// Percent-encodes everything but unreserved characters, for use as one segment of a path.
+pub fn percent_encode_segment(segment: &str) -> String {
+    segment.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+        byte => format!("%{byte:02X}")
+    }).collect()
+}
+
+/// Decodes an `application/x-www-form-urlencoded` component, where `+` stands for a space.
+/// Malformed escapes are kept as they are, like browsers do.
+fn form_decode(input: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut index = 0;
+    while index < input.len() {
+        let escaped = match input[index] {
+            b'%' => input.get(index + 1).and_then(|&high| hex_value(high))
+                .zip(input.get(index + 2).and_then(|&low| hex_value(low))),
+            _ => None
+        };
+        match (input[index], escaped) {
+            (_, Some((high, low))) => {
+                decoded.push(high << 4 | low);
+                index += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte)
+        }
+        index += 1;
+    }
+    decoded
+}
+
+/// Query string or form parameters in the order they appear. Names may repeat.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Params {
+    pairs: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn parse(query: &str) -> Self {
+        Params::parse_form(query.as_bytes(), Charset::Utf8)
+    }
+
+    /// Parses an `application/x-www-form-urlencoded` body whose escapes encode `charset`.
+    pub fn parse_form(body: &[u8], charset: Charset) -> Self {
+        let pairs = body.split(|&byte| byte == b'&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = match pair.iter().position(|&byte| byte == b'=') {
+                    None => (pair, &pair[pair.len()..]),
+                    Some(equals) => (&pair[..equals], &pair[equals + 1..])
+                };
+                (charset.decode(&form_decode(name)), charset.decode(&form_decode(value)))
+            })
+            .collect();
+        Self { pairs }
+    }
+
+    /// First value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs.iter().find(|(pair_name, _)| pair_name == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item=&'a str> {
+        self.pairs.iter().filter(move |(pair_name, _)| pair_name == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.pairs.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
@@ -0,0 +1,550 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter, Write};
+
+/// Deepest nesting of arrays and objects the parser accepts, so hostile input can't
+/// exhaust the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A JSON value (RFC 8259). Object members keep their order. Numbers are `f64`, so integers
+/// beyond 2^53 lose precision.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// An object with the given members, e.g. `Value::object([("name", "a".into())])`.
+    pub fn object<K: Into<String>>(members: impl IntoIterator<Item=(K, Value)>) -> Self {
+        Value::Object(members.into_iter().map(|(key, value)| (key.into(), value)).collect())
+    }
+
+    /// Member `key` of an object, `None` for other values.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, string: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?
+        }
+    }
+    f.write_char('"')
+}
+
+/// Serializes compactly. Non-finite numbers, which JSON can't express, become `null`.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(boolean) => write!(f, "{boolean}"),
+            Value::Number(number) if !number.is_finite() => f.write_str("null"),
+            // Whole numbers print without a fraction; `Display` for `f64` never uses exponents
+            Value::Number(number) => write!(f, "{number}"),
+            Value::String(string) => write_string(f, string),
+            Value::Array(elements) => {
+                f.write_char('[')?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(members) => {
+                f.write_char('{')?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(boolean: bool) -> Self {
+        Value::Bool(boolean)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Self {
+        Value::Number(number)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(number: u64) -> Self {
+        Value::Number(number as f64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(number: i64) -> Self {
+        Value::Number(number as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Self {
+        Value::String(string.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Self {
+        Value::String(string)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(elements: Vec<T>) -> Self {
+        Value::Array(elements.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+// Conversions back into Rust types hand the value back when it has another type.
+
+impl TryFrom<Value> for bool {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(boolean) => Ok(boolean),
+            value => Err(value)
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(number) => Ok(number),
+            value => Err(value)
+        }
+    }
+}
+
+/// Only integral numbers in range convert.
+impl TryFrom<Value> for u64 {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(number) if number.fract() == 0.0 && (0.0..=u64::MAX as f64).contains(&number) => Ok(number as u64),
+            value => Err(value)
+        }
+    }
+}
+
+/// Only integral numbers in range convert.
+impl TryFrom<Value> for i64 {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(number) if number.fract() == 0.0 && (i64::MIN as f64..=i64::MAX as f64).contains(&number) => Ok(number as i64),
+            value => Err(value)
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(string) => Ok(string),
+            value => Err(value)
+        }
+    }
+}
+
+impl<T: TryFrom<Value, Error=Value>> TryFrom<Value> for Vec<T> {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(elements) => {
+                // Kept so the whole array can be handed back on a mismatch
+                let original = elements.clone();
+                elements.into_iter().map(T::try_from).collect::<Result<_, _>>().map_err(|_| Value::Array(original))
+            }
+            value => Err(value)
+        }
+    }
+}
+
+/// Where and why parsing failed. `line` and `column` count from 1, columns in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub message: &'static str,
+}
+
+impl ParseError {
+    fn at(input: &str, offset: usize, message: &'static str) -> Self {
+        let before = &input[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        Self { offset, line, column: before[line_start..].chars().count() + 1, message }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+/// Parses a complete JSON text, strictly: no comments, trailing commas, leading zeros,
+/// lone surrogates or duplicate object keys.
+pub fn parse(input: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser { input, bytes: input.as_bytes(), position: 0 };
+    parser.skip_whitespace();
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.position < input.len() {
+        return Err(parser.error("Unexpected content after the JSON value"));
+    }
+    Ok(value)
+}
+
+/// Like `parse`, for input that may not be UTF-8; invalid bytes are reported by position.
+pub fn parse_bytes(input: &[u8]) -> Result<Value, ParseError> {
+    match std::str::from_utf8(input) {
+        Ok(text) => parse(text),
+        Err(error) => {
+            let valid = std::str::from_utf8(&input[..error.valid_up_to()]).unwrap_or_default();
+            Err(ParseError::at(valid, valid.len(), "Invalid UTF-8"))
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError::at(self.input, self.position, message)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8, message: &'static str) -> Result<(), ParseError> {
+        match self.peek() == Some(byte) {
+            true => {
+                self.position += 1;
+                Ok(())
+            }
+            false => Err(self.error(message))
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Value) -> Result<Value, ParseError> {
+        match self.input[self.position..].starts_with(literal) {
+            true => {
+                self.position += literal.len();
+                Ok(value)
+            }
+            false => Err(self.error("Invalid literal"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, ParseError> {
+        match self.peek() {
+            None => Err(self.error("Unexpected end of input")),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b'[') if depth < MAX_DEPTH => self.array(depth + 1),
+            Some(b'{') if depth < MAX_DEPTH => self.object(depth + 1),
+            Some(b'[' | b'{') => Err(self.error("Nesting too deep")),
+            Some(_) => Err(self.error("Expected a JSON value"))
+        }
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.position;
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.position += 1;
+        }
+        self.position - start
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.position;
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some(b'0') => {
+                self.position += 1;
+                if matches!(self.peek(), Some(b'0'..=b'9')) {
+                    return Err(self.error("Leading zeros are not allowed"));
+                }
+            }
+            Some(b'1'..=b'9') => {
+                self.digits();
+            }
+            _ => return Err(self.error("Expected a digit"))
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            if self.digits() == 0 {
+                return Err(self.error("Expected a digit after the decimal point"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.position += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.position += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("Expected a digit in the exponent"));
+            }
+        }
+        let number = self.input[start..self.position].parse::<f64>().map_err(|_| self.error("Invalid number"))?;
+        match number.is_finite() {
+            true => Ok(Value::Number(number)),
+            false => Err(ParseError::at(self.input, start, "Number out of range"))
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, ParseError> {
+        let hex = self.input.get(self.position..self.position + 4)
+            .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("Expected four hex digits"))?;
+        self.position += 4;
+        Ok(u32::from_str_radix(hex, 16).unwrap_or_default())
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"', "Expected a string")?;
+        let mut string = String::new();
+        loop {
+            let run_start = self.position;
+            while matches!(self.peek(), Some(byte) if byte != b'"' && byte != b'\\' && byte >= 0x20) {
+                self.position += 1;
+            }
+            string.push_str(&self.input[run_start..self.position]);
+            match self.peek() {
+                None => return Err(self.error("Unterminated string")),
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    let escape = self.peek().ok_or_else(|| self.error("Unterminated string"))?;
+                    self.position += 1;
+                    match escape {
+                        b'"' => string.push('"'),
+                        b'\\' => string.push('\\'),
+                        b'/' => string.push('/'),
+                        b'b' => string.push('\u{8}'),
+                        b'f' => string.push('\u{c}'),
+                        b'n' => string.push('\n'),
+                        b'r' => string.push('\r'),
+                        b't' => string.push('\t'),
+                        b'u' => {
+                            let escape_start = self.position - 2;
+                            let unit = self.hex_escape()?;
+                            let code_point = match unit {
+                                0xD800..=0xDBFF => {
+                                    if !self.input[self.position..].starts_with("\\u") {
+                                        return Err(ParseError::at(self.input, escape_start, "Unpaired surrogate"));
+                                    }
+                                    self.position += 2;
+                                    let low = self.hex_escape()?;
+                                    if !(0xDC00..=0xDFFF).contains(&low) {
+                                        return Err(ParseError::at(self.input, escape_start, "Unpaired surrogate"));
+                                    }
+                                    0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00)
+                                }
+                                0xDC00..=0xDFFF => return Err(ParseError::at(self.input, escape_start, "Unpaired surrogate")),
+                                unit => unit
+                            };
+                            string.extend(char::from_u32(code_point));
+                        }
+                        _ => {
+                            self.position -= 1;
+                            return Err(self.error("Invalid escape sequence"));
+                        }
+                    }
+                }
+                Some(_) => return Err(self.error("Control characters must be escaped in strings"))
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.expect(b'[', "Expected an array")?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Value::Array(elements));
+        }
+        loop {
+            self.skip_whitespace();
+            elements.push(self.value(depth)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(elements));
+                }
+                _ => return Err(self.error("Expected `,` or `]`"))
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.expect(b'{', "Expected an object")?;
+        let mut members = Vec::new();
+        let mut keys = HashSet::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key_start = self.position;
+            let key = self.string()?;
+            if !keys.insert(key.clone()) {
+                return Err(ParseError::at(self.input, key_start, "Duplicate object key"));
+            }
+            self.skip_whitespace();
+            self.expect(b':', "Expected `:`")?;
+            self.skip_whitespace();
+            members.push((key, self.value(depth)?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("Expected `,` or `}`"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn error(input: &str) -> (usize, usize, usize, &'static str) {
+        let error = parse(input).unwrap_err();
+        (error.offset, error.line, error.column, error.message)
+    }
+
+    #[test]
+    fn parses_values() {
+        let value = parse(r#" {"a": [1, -2.5e2, true, null], "b": {"c": "d\u00e9\ud83d\ude00\n"}} "#).unwrap();
+        assert_eq!(value, Value::object([
+            ("a", Value::from(vec![Value::from(1.0), Value::from(-250.0), Value::from(true), Value::Null])),
+            ("b", Value::object([("c", Value::from("d\u{e9}\u{1f600}\n"))])),
+        ]));
+        assert_eq!(value.get("b").and_then(|b| b.get("c")), Some(&Value::from("d\u{e9}\u{1f600}\n")));
+    }
+
+    #[test]
+    fn serializes_compactly() {
+        let value = Value::object([
+            ("text", Value::from("\"quoted\"\\\u{1}\t")),
+            ("numbers", Value::from(vec![1.0, 0.5, f64::NAN])),
+            ("none", Value::from(None::<bool>)),
+        ]);
+        assert_eq!(value.to_string(), r#"{"text":"\"quoted\"\\\u0001\t","numbers":[1,0.5,null],"none":null}"#);
+        assert_eq!(parse(&value.to_string()).unwrap().get("text"), value.get("text"));
+    }
+
+    #[test]
+    fn reports_error_positions() {
+        assert_eq!(error("[1,\n 2,]"), (7, 2, 4, "Expected a JSON value"));
+        assert_eq!(error("{\"é\": x}"), (7, 1, 7, "Expected a JSON value"));
+        assert_eq!(error("1 2"), (2, 1, 3, "Unexpected content after the JSON value"));
+        assert_eq!(error(""), (0, 1, 1, "Unexpected end of input"));
+    }
+
+    #[test]
+    fn rejects_what_strict_json_forbids() {
+        assert_eq!(error("01").3, "Leading zeros are not allowed");
+        assert_eq!(error("1.").3, "Expected a digit after the decimal point");
+        assert_eq!(error("1e999").3, "Number out of range");
+        assert_eq!(error(r#""\ud800""#).3, "Unpaired surrogate");
+        assert_eq!(error(r#""\x""#).3, "Invalid escape sequence");
+        assert_eq!(error("\"a\u{1}\"").3, "Control characters must be escaped in strings");
+        assert_eq!(error(r#"{"a": 1, "a": 2}"#), (9, 1, 10, "Duplicate object key"));
+        assert_eq!(error(&"[".repeat(MAX_DEPTH + 1)).3, "Nesting too deep");
+        assert_eq!(parse_bytes(b"[\"\xff\"]").unwrap_err().offset, 2);
+    }
+
+    #[test]
+    fn converts_into_rust_types() {
+        assert_eq!(u64::try_from(Value::from(3.0)), Ok(3));
+        assert_eq!(u64::try_from(Value::from(-3.0)), Err(Value::from(-3.0)));
+        assert_eq!(i64::try_from(Value::from(0.5)), Err(Value::from(0.5)));
+        assert_eq!(Vec::<String>::try_from(Value::from(vec!["a", "b"])), Ok(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(Vec::<bool>::try_from(Value::from(vec!["a"])), Err(Value::from(vec!["a"])));
+    }
+}
@@ -1,10 +1,12 @@
 use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use itertools::Itertools;
 
 use crate::config::Config;
 use crate::route::Router;
-use crate::routes::get_routes;
+use crate::routes::{get_fallback_route, get_routes};
 use crate::server::Server;
 
 mod server;
@@ -12,17 +14,94 @@ mod http;
 mod route;
 mod config;
 mod routes;
+mod metrics;
+mod files;
+mod hash;
+mod base64;
+mod json;
+mod webdav;
+mod session;
+mod random;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let found = args.iter().find_position(|s| { s.contains("--directory") });
-    let files_directory_path_string = match found {
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    let found = args.iter().find_position(|s| { *s == name });
+    match found {
         None => None,
         Some((index, _)) => args.get(index + 1).cloned()
-    };
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let files_directory_path_string = arg_value(&args, "--directory");
 
-    let config = Config::new("127.0.0.1", 4221, files_directory_path_string);
-    let router = Router::new(Some(get_routes()));
+    let mut config = Config::new("127.0.0.1", 4221, files_directory_path_string);
+    if let Some(server_name) = arg_value(&args, "--server-name") {
+        config.server_name = Some(server_name).filter(|name| !name.is_empty());
+    }
+    config.directory_listing = args.iter().any(|arg| arg == "--directory-listing");
+    config.site_root = arg_value(&args, "--site-root");
+    config.not_found_page = arg_value(&args, "--not-found-page");
+    config.spa_fallback = args.iter().any(|arg| arg == "--spa");
+    if let Some(etag_mode) = arg_value(&args, "--etags") {
+        config.etag_mode = etag_mode.parse().unwrap_or_else(|error| panic!("{error}"));
+    }
+    if let Some(symlink_policy) = arg_value(&args, "--symlinks") {
+        config.symlink_policy = symlink_policy.parse().unwrap_or_else(|error| panic!("{error}"));
+    }
+    if let Some(mime_types) = arg_value(&args, "--mime-types") {
+        config.mime_types = files::mime::parse_overrides(&mime_types).unwrap_or_else(|error| panic!("{error}"));
+    }
+    config.sniff_mime_types = !args.iter().any(|arg| arg == "--no-mime-sniffing");
+    config.webdav = args.iter().any(|arg| arg == "--webdav");
+    if let Some(upload_expiry) = arg_value(&args, "--upload-expiry") {
+        let seconds = upload_expiry.parse().unwrap_or_else(|error| panic!("Invalid --upload-expiry `{upload_expiry}`: {error}"));
+        config.upload_expiry = Duration::from_secs(seconds);
+    }
+    if let Some(uploads_path) = arg_value(&args, "--uploads-directory") {
+        config.uploads_path = PathBuf::from(uploads_path);
+    }
+    if let Some(size) = arg_value(&args, "--max-upload-size") {
+        config.max_upload_size = size.parse().unwrap_or_else(|error| panic!("Invalid --max-upload-size `{size}`: {error}"));
+    }
+    if let Some(size) = arg_value(&args, "--max-form-part-size") {
+        config.max_form_part_size = size.parse().unwrap_or_else(|error| panic!("Invalid --max-form-part-size `{size}`: {error}"));
+    }
+    if let Some(size) = arg_value(&args, "--max-form-size") {
+        config.max_form_size = size.parse().unwrap_or_else(|error| panic!("Invalid --max-form-size `{size}`: {error}"));
+    }
+    if let Some(size) = arg_value(&args, "--max-buffered-body-size") {
+        config.max_buffered_body_size = size.parse().unwrap_or_else(|error| panic!("Invalid --max-buffered-body-size `{size}`: {error}"));
+    }
+    if let Some(session_store) = arg_value(&args, "--sessions") {
+        config.session_store = Some(session_store.parse().unwrap_or_else(|error| panic!("{error}")));
+    }
+    // Comma separated, newest first; older keys only verify cookies issued before a key change
+    if let Some(keys) = arg_value(&args, "--session-keys") {
+        config.session_keys = keys.split(',').filter(|key| !key.is_empty()).map(|key| key.as_bytes().to_vec()).collect();
+    }
+    if config.session_store.is_some() && config.session_keys.is_empty() {
+        eprintln!("No --session-keys given, sessions won't survive a restart");
+        config.session_keys.push(random::random_bytes(32).unwrap_or_else(|error| panic!("Failed to create a session key: {error}")));
+    }
+    if let Some(max_age) = arg_value(&args, "--session-max-age") {
+        let seconds = max_age.parse().unwrap_or_else(|error| panic!("Invalid --session-max-age `{max_age}`: {error}"));
+        config.session_max_age = Duration::from_secs(seconds);
+    }
+    config.session_cookie_domain = arg_value(&args, "--session-domain");
+    if let Some(same_site) = arg_value(&args, "--session-same-site") {
+        config.session_same_site = same_site.parse().unwrap_or_else(|error| panic!("{error}"));
+    }
+    config.session_secure_cookie = args.iter().any(|arg| arg == "--session-secure");
+    if config.session_store.is_some() {
+        session::check_cookie(&config).unwrap_or_else(|error| panic!("{error}"));
+    }
+    if let Some(level) = arg_value(&args, "--compression-level") {
+        config.compression_level = level.parse().unwrap_or_else(|error| panic!("{error}"));
+    }
+    config.metrics = args.iter().any(|arg| arg == "--metrics");
+    let mut router = Router::new(Some(get_routes()));
+    router.set_fallback(get_fallback_route());
     let server = Server::new(config, router);
 
     server.serve()
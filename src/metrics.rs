@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::http::status::HTTPStatus;
+
+/// Status classes responses are counted in, `1xx` to `5xx`.
+const STATUS_CLASSES: [fn(&HTTPStatus) -> bool; 5] = [
+    HTTPStatus::is_informational,
+    HTTPStatus::is_success,
+    HTTPStatus::is_redirection,
+    HTTPStatus::is_client_error,
+    HTTPStatus::is_server_error,
+];
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    handler_panics: AtomicUsize,
+    /// Responses sent, by index into `STATUS_CLASSES`.
+    responses: [AtomicUsize; STATUS_CLASSES.len()],
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a panicked handler and returns the total number of panics seen so far.
+    pub fn record_handler_panic(&self) -> usize {
+        self.handler_panics.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn handler_panics(&self) -> usize {
+        self.handler_panics.load(Ordering::Relaxed)
+    }
+
+    pub fn record_response(&self, status: &HTTPStatus) {
+        if let Some(class) = STATUS_CLASSES.iter().position(|is_class| is_class(status)) {
+            self.responses[class].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = format!(
+            "# HELP handler_panics_total Handlers that panicked instead of returning a response.\n\
+             # TYPE handler_panics_total counter\n\
+             handler_panics_total {}\n\
+             # HELP http_responses_total Responses sent, by status class.\n\
+             # TYPE http_responses_total counter\n",
+            self.handler_panics()
+        );
+        for (index, count) in self.responses.iter().enumerate() {
+            output.push_str(&format!("http_responses_total{{class=\"{}xx\"}} {}\n", index + 1, count.load(Ordering::Relaxed)));
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn counts_handler_panics() {
+        let metrics = Metrics::new();
+        assert_eq!(metrics.record_handler_panic(), 1);
+        assert_eq!(metrics.record_handler_panic(), 2);
+        assert_eq!(metrics.handler_panics(), 2);
+        assert!(metrics.render().contains("\nhandler_panics_total 2\n"));
+    }
+
+    #[test]
+    fn counts_responses_by_class() {
+        let metrics = Metrics::new();
+        metrics.record_response(&HTTPStatus::Ok);
+        metrics.record_response(&HTTPStatus::NoContent);
+        metrics.record_response(&HTTPStatus::NotFound);
+        let rendered = metrics.render();
+        assert!(rendered.contains("\nhttp_responses_total{class=\"2xx\"} 2\n"));
+        assert!(rendered.contains("\nhttp_responses_total{class=\"4xx\"} 1\n"));
+        assert!(rendered.ends_with("\nhttp_responses_total{class=\"5xx\"} 0\n"));
+    }
+}
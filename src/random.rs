@@ -0,0 +1,10 @@
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Reads `length` bytes from the kernel's random source, for ids and keys that must not be
+/// guessable.
+pub fn random_bytes(length: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; length];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
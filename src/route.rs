@@ -1,47 +1,78 @@
+use std::fmt::{Debug, Formatter};
+
 use crate::config::Config;
 use crate::http::request::{HTTPMethod, Request};
 use crate::http::response::Response;
 
-type RequestHandler = fn(request: &Request, config: &Config) -> Response;
-#[derive(Debug)]
+pub type RequestHandler = Box<dyn Fn(&Request, &Config) -> Response + Send + Sync>;
+
+/// Wraps a handler returning anything convertible into a `Response`.
+fn into_request_handler<F, R>(handler: F) -> RequestHandler
+    where F: Fn(&Request, &Config) -> R + Send + Sync + 'static,
+          R: Into<Response> {
+    Box::new(move |request, config| handler(request, config).into())
+}
+
 pub struct Route {
     pub method: HTTPMethod,
     pub path: String,
     pub handler: RequestHandler,
 }
 
+impl Debug for Route {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Route")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Route {
-    pub fn new(method: HTTPMethod, path: &str, handler: RequestHandler) -> Self {
+    pub fn new<F, R>(method: HTTPMethod, path: &str, handler: F) -> Self
+        where F: Fn(&Request, &Config) -> R + Send + Sync + 'static,
+              R: Into<Response> {
         Self {
             method,
             path: path.to_string(),
-            handler,
+            handler: into_request_handler(handler),
         }
     }
 }
 
-#[derive(Eq, PartialEq, Hash, Debug)]
-pub struct RouterKey(pub String, pub HTTPMethod);
-#[derive(Debug)]
 pub struct Router {
     pub routes: Vec<Route>,
+    /// Handles requests no route matches, answered with 404 if unset.
+    pub fallback: Option<RequestHandler>,
+}
+
+impl Debug for Router {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Router")
+            .field("routes", &self.routes)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
 }
 impl Router {
     pub fn new(routes: Option<Vec<Route>>) -> Self {
         match routes {
             None => Self {
-                routes: Vec::with_capacity(1)
+                routes: Vec::with_capacity(1),
+                fallback: None,
             },
             Some(routes) => {
                 Self {
-                    routes
+                    routes,
+                    fallback: None,
                 }
             }
         }
     }
 
-    #[allow(dead_code)]
-    pub fn add_route(&mut self, path: &str, method: HTTPMethod, handler: RequestHandler) {
-        self.routes.push(Route { path: path.to_string(), method, handler });
+    pub fn set_fallback<F, R>(&mut self, handler: F)
+        where F: Fn(&Request, &Config) -> R + Send + Sync + 'static,
+              R: Into<Response> {
+        self.fallback = Some(into_request_handler(handler));
     }
-}
\ No newline at end of file
+}
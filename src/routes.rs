@@ -1,112 +1,167 @@
-use crate::http::Body;
-use crate::http::compression;
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::config::Config;
+use crate::files;
+use crate::files::{form, manage, resumable, site};
+use crate::http::compression::{self, Format};
 use crate::http::headers::HTTPHeader;
-use crate::http::request::HTTPMethod;
-use crate::http::response::{HTTPStatus, Response};
+use crate::http::negotiation::Dimension;
+use crate::http::request::{FormError, HTTPMethod, Request};
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+use crate::json::Value;
 use crate::route::Route;
+use crate::webdav;
 
 pub fn get_routes() -> Vec<Route> {
-    let echo = Route::new(HTTPMethod::GET, "/echo", |request, _| {
-        let echo_data = request.resource.split_once("/echo/").unwrap().1;
+    let echo = Route::new(HTTPMethod::GET, "/echo", |request, config| {
+        let Some((_, echo_data)) = request.resource.split_once("/echo/") else {
+            return Err(Response::not_found());
+        };
 
-        let mut response = Response::new(HTTPStatus::Ok);
-        response.add_known_header(HTTPHeader::ContentType, vec!["text/plain"]);
+        let format = match request.negotiate(Dimension::Encoding, &["gzip", "deflate", "identity"])? {
+            "gzip" => Format::Gzip,
+            "deflate" => Format::Zlib,
+            _ => return Ok(Response::ok().text(echo_data))
+        };
+        let data = compression::compress(echo_data.as_bytes(), format, config.compression_level);
+        let coding = match format {
+            Format::Gzip => "gzip",
+            _ => "deflate"
+        };
+        Ok::<_, Response>(Response::ok().header(HTTPHeader::ContentEncoding, coding).content("text/plain", data))
+    });
 
-        match request.get_known_header_values(HTTPHeader::AcceptEncoding) {
-            None => {
-                response.set_body(echo_data.parse().unwrap());
-                response.set_content_length_header();
-            }
-            Some(values) => {
-                match values.len() {
-                    0 => {}
-                    _ => {
-                        for encoding in values.iter() {
-                            if encoding == "gzip" {
-                                // TODO Move compression to common place for handling requests
-                                // As well as setting content length?
-                                match compression::gzip(echo_data) {
-                                    Err(_) => {}
-                                    Ok(data) => {
-                                        response.add_known_header(HTTPHeader::ContentEncoding, vec![encoding]);
-                                        response.set_body(Body::new(data));
-                                    }
-                                }
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
+    // Answers with the fields of a form or JSON body as JSON, to see what a client really sends
+    let echo_body = Route::new(HTTPMethod::POST, "/echo", |request, config| {
+        if request.path() != "/echo" {
+            return Err(Response::not_found());
+        }
+        let value = match request.form(config) {
+            // Repeated names, as multiple selections send them, become arrays
+            Ok(form) => Value::Object(form.iter().map(|(name, _)| name).unique().map(|name| {
+                let values = form.get_all(name).collect::<Vec<_>>();
+                let value = match values.as_slice() {
+                    [value] => Value::from(*value),
+                    _ => Value::from(values)
+                };
+                (name.to_string(), value)
+            }).collect()),
+            Err(FormError::UnsupportedMediaType) => request.json(config)?,
+            Err(error) => return Err(error.into())
         };
-        response.set_content_length_header();
-        response
+        Ok(Response::ok().json(value))
     });
 
-    let root_route = Route::new(HTTPMethod::GET, "/", |_request, _config| {
-        Response::new(HTTPStatus::Ok)
+    let root_route = Route::new(HTTPMethod::GET, "/", |request, config| {
+        match config.site_root {
+            None => Response::ok(),
+            Some(_) => site::serve(request, config)
+        }
     });
-    let index_route = Route::new(HTTPMethod::GET, "/index.html", |_request, _config| {
-        Response::new(HTTPStatus::NotFound)
+    let index_route = Route::new(HTTPMethod::GET, "/index.html", |request, config| {
+        site::serve(request, config)
     });
 
     let user_agent_route = Route::new(HTTPMethod::GET, "/user-agent", |request, _| {
-        let mut response = Response::new(HTTPStatus::Ok);
         let user_agent_values = request.get_known_header_values(HTTPHeader::UserAgent);
-        match user_agent_values {
-            None => {}
-            Some(values) => {
-                match values.first() {
-                    None => response.set_body(Body::default()),
-                    Some(value) => response.set_body(value.parse().unwrap())
-                }
-            }
+        let user_agent = match user_agent_values {
+            None => "",
+            Some(values) => values.first().map_or("", |value| value.as_str())
         };
-
-        response.set_content_length_header();
-        response.add_known_header(HTTPHeader::ContentType, vec!["text/plain"]);
-        response
+        Response::ok().text(user_agent)
     });
 
-    let read_files_route = Route::new(HTTPMethod::GET, "/files", |request, config| {
-        let not_found = Response::new(HTTPStatus::NotFound);
-        match &config.files_path {
-            None => not_found,
-            Some(dir_path) => {
-                match request.resource.split_once("/files/") {
-                    None => not_found,
-                    Some((_, file_name)) => {
-                        match std::fs::read_to_string(format!("{dir_path}/{file_name}")) {
-                            Ok(content) => {
-                                let mut response = Response::new(HTTPStatus::Ok);
-                                let body: Body = content.parse().unwrap();
-                                response.add_known_header(HTTPHeader::ContentType, vec!["application/octet-stream"]);
-                                response.set_body(body);
-                                response.set_content_length_header();
-                                response
-                            }
-                            Err(_) => not_found
-                        }
-                    }
+    let read_files_route = Route::new(HTTPMethod::GET, files::ROUTE_PREFIX, |request, config| {
+        match files::resolve_request_path(request, config) {
+            Err(response) => response,
+            Ok(path) => files::serve(request, &path, config)
+        }
+    });
+    let write_files_route = Route::new(HTTPMethod::POST, files::ROUTE_PREFIX, |request, config| {
+        match files::resolve_request_path(request, config) {
+            Err(response) => response,
+            Ok(path) => {
+                if let Some(multipart) = request.multipart() {
+                    return form::store_parts(request, &path, multipart, config);
+                }
+                if let Err(response) = webdav::check_locks(request, &path, false) {
+                    return response;
                 }
+                match files::store(request, &path, config) {
+                    Ok(_) => Response::new(HTTPStatus::Created),
+                    Err(response) => response
+                }
+            }
+        }
+    });
+    let put_files_route = Route::new(HTTPMethod::PUT, files::ROUTE_PREFIX, |request, config| {
+        match files::resolve_request_path(request, config) {
+            Err(response) => response,
+            Ok(path) => match webdav::check_locks(request, &path, false) {
+                Err(response) => response,
+                Ok(_) => manage::put(request, &path, config)
             }
         }
     });
-    let write_files_route = Route::new(HTTPMethod::POST, "/files", |request, config| {
-        let not_found = Response::new(HTTPStatus::NotFound);
-        match &config.files_path {
-            None => not_found,
-            Some(dir_path) => {
-                match request.resource.split_once("/files/") {
-                    None => not_found,
-                    Some((_, file_name)) => {
-                        let body = &request.body;
-                        std::fs::write(format!("{dir_path}/{file_name}"), body).unwrap();
-                        Response::new(HTTPStatus::Created)
-                    }
+    let delete_files_route = Route::new(HTTPMethod::DELETE, files::ROUTE_PREFIX, |request, config| {
+        match files::resolve_request_path(request, config) {
+            Err(response) => response,
+            Ok(path) => {
+                if let Err(response) = webdav::check_locks(request, &path, true) {
+                    return response;
                 }
+                let response = manage::delete(request, &path, config);
+                if response.status.is_success() {
+                    webdav::forget(&path);
+                }
+                response
             }
         }
     });
-    vec![echo, user_agent_route, read_files_route, write_files_route, index_route, root_route]
-}
\ No newline at end of file
+    let patch_files_route = Route::new(HTTPMethod::PATCH, files::ROUTE_PREFIX, |request, config| {
+        match files::resolve_request_path(request, config) {
+            Err(response) => response,
+            Ok(path) => match webdav::check_locks(request, &path, false) {
+                Err(response) => response,
+                Ok(_) => manage::patch(request, &path, config)
+            }
+        }
+    });
+
+    let webdav_routes = [
+        (HTTPMethod::OPTIONS, webdav::options as fn(&Request, &Path, &Config) -> Response),
+        (HTTPMethod::PROPFIND, webdav::propfind),
+        (HTTPMethod::PROPPATCH, webdav::proppatch),
+        (HTTPMethod::MKCOL, webdav::mkcol),
+        (HTTPMethod::COPY, webdav::copy),
+        (HTTPMethod::MOVE, webdav::move_resource),
+        (HTTPMethod::LOCK, webdav::lock),
+        (HTTPMethod::UNLOCK, webdav::unlock),
+    ].into_iter().map(|(method, handler)| {
+        Route::new(method, files::ROUTE_PREFIX, move |request, config| webdav::handle(request, config, handler))
+    });
+
+    let upload_routes = [
+        (HTTPMethod::OPTIONS, resumable::options as fn(&Request, &Config) -> Response),
+        (HTTPMethod::POST, resumable::create),
+        (HTTPMethod::HEAD, resumable::head),
+        (HTTPMethod::PATCH, resumable::patch),
+        (HTTPMethod::DELETE, resumable::terminate),
+    ].into_iter().map(|(method, handler)| Route::new(method, resumable::ROUTE_PREFIX, handler));
+
+    let mut routes = vec![
+        echo, echo_body, user_agent_route, read_files_route, write_files_route, put_files_route, delete_files_route, patch_files_route,
+        index_route, root_route,
+    ];
+    routes.extend(webdav_routes);
+    routes.extend(upload_routes);
+    routes
+}
+
+/// Serves the static site, if one is configured, for paths no other route handles.
+pub fn get_fallback_route() -> fn(&Request, &Config) -> Response {
+    site::serve
+}
@@ -1,70 +1,174 @@
-use std::io::{BufReader, BufWriter, Write};
+use std::any::Any;
+use std::io::{BufReader, ErrorKind};
 use std::net::TcpListener;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 use std::thread;
 
 use crate::config::Config;
-use crate::http::request::Request;
-use crate::http::response::{HTTPStatus, Response};
-use crate::route::Router;
-
-type RequestHandler = fn(request: &Request, config: &Config) -> Response;
+use crate::http::request::{HeadTooLarge, HTTPMethod, Request};
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+use crate::metrics::Metrics;
+use crate::route::{RequestHandler, Route, Router};
+use crate::session;
 
 pub struct Server {
     config: Arc<Config>,
     pub router: Arc<Router>,
+    pub metrics: Arc<Metrics>,
 }
 
 impl Server {
+    const METRICS_PATH: &'static str = "/metrics";
+
     pub fn new(config: Config, router: Router) -> Server {
         Self {
             config: Arc::new(config),
             router: Arc::new(router),
+            metrics: Arc::new(Metrics::new()),
         }
     }
 
-    fn handle_request(request: &Request, router: &Arc<Router>, config: &Arc<Config>) -> Response {
-        let possible_handler: Option<&RequestHandler> = router.routes.iter().find_map(|route| {
+    fn find_route<'a>(router: &'a Router, request: &Request, method: HTTPMethod) -> Option<&'a Route> {
+        router.routes.iter().find(|route| {
 
             // Rewrite with regular expression
             if request.resource == '/'.to_string() {
-                if request.resource == route.path && request.method == route.method {
-                    return Some(&route.handler);
-                }
+                request.resource == route.path && method == route.method
             } else {
-                if request.resource.starts_with(route.path.as_str()) && route.path != '/'.to_string() && request.method == route.method {
-                    return Some(&route.handler);
-                }
+                request.resource.starts_with(route.path.as_str()) && route.path != '/'.to_string() && method == route.method
             }
+        })
+    }
 
-            None
-        });
+    fn handle_request(request: &Request, router: &Arc<Router>, config: &Arc<Config>, metrics: &Arc<Metrics>) -> Response {
+        // `HEAD` falls back to the `GET` route, whose body is dropped after framing
+        let possible_route = match request.method {
+            HTTPMethod::HEAD => Server::find_route(router, request, HTTPMethod::HEAD)
+                .or_else(|| Server::find_route(router, request, HTTPMethod::GET)),
+            method => Server::find_route(router, request, method)
+        };
 
-        let handler: RequestHandler = match possible_handler {
-            None => RequestHandler::from(|_, _| { Response::new(HTTPStatus::NotFound) }),
-            Some(handler) => *handler
+        let serves_metrics = config.metrics && request.path() == Server::METRICS_PATH
+            && matches!(request.method, HTTPMethod::GET | HTTPMethod::HEAD);
+        let mut response = match possible_route.map(|route| &route.handler).or(router.fallback.as_ref()) {
+            _ if serves_metrics => Response::ok().content("text/plain; version=0.0.4", metrics.render()),
+            None => Response::not_found(),
+            Some(handler) => Server::call_handler(handler, request, config, metrics)
         };
-        let mut response = handler(&request, &config);
         response.set_http_version(&request.http_version);
+        response.finalize(config.server_name.as_deref());
+        metrics.record_response(&response.status);
+        if request.method == HTTPMethod::HEAD {
+            response.body = None;
+        }
         response
     }
 
+    /// Runs the handler with the session loaded and saved around it, turning a panic anywhere
+    /// in there into `500 Internal Server Error` so the connection still gets an answer and
+    /// the worker thread survives.
+    fn call_handler(handler: &RequestHandler, request: &Request, config: &Config, metrics: &Metrics) -> Response {
+        let run = || {
+            session::load(request, config);
+            let response = handler(request, config);
+            let mut response = session::save(request, response, config);
+            for header_name in request.vary_headers() {
+                response.add_vary(&header_name);
+            }
+            response
+        };
+        match panic::catch_unwind(AssertUnwindSafe(run)) {
+            Ok(response) => response,
+            Err(payload) => {
+                let total_panics = metrics.record_handler_panic();
+                eprintln!(
+                    "Handler panicked on `{method} {resource} {http_version}`: {message} (handler panics so far: {total_panics})",
+                    method = request.method,
+                    resource = request.resource,
+                    http_version = request.http_version,
+                    message = Server::panic_message(payload.as_ref()),
+                );
+                Response::new(HTTPStatus::InternalServerError)
+            }
+        }
+    }
+
+    fn panic_message(payload: &(dyn Any + Send)) -> &str {
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            message
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.as_str()
+        } else {
+            "unknown panic payload"
+        }
+    }
+
 
     pub fn serve(&self) {
         let address = format!("{hostname}:{port}", hostname = self.config.address, port = self.config.port);
         let listener: TcpListener = TcpListener::bind(address).unwrap();
         for stream in listener.incoming().flatten() {
-
-            // TODO try to move inside request_handler
-            let reader = BufReader::new(&stream);
-            let request = Request::new(reader).unwrap();
             let config = self.config.clone();
             let router = self.router.clone();
+            let metrics = self.metrics.clone();
             thread::spawn(move || {
-                let response = Server::handle_request(&request, &router, &config);
-                let mut writer = BufWriter::new(&stream);
-                writer.write(response.try_into_bytes().buffer())
+                let reader = BufReader::new(stream.try_clone()?);
+                let response = match Request::new(reader) {
+                    Ok(request) => Server::handle_request(&request, &router, &config, &metrics),
+                    // Nobody left to answer
+                    Err(error) if error.kind() != ErrorKind::InvalidData => return Ok(()),
+                    Err(error) => {
+                        let status = HeadTooLarge::from_error(&error).map_or(HTTPStatus::BadRequest, |too_large| too_large.status());
+                        let mut response = Response::new(status).text(error.to_string());
+                        response.finalize(config.server_name.as_deref());
+                        metrics.record_response(&response.status);
+                        response
+                    }
+                };
+                response.send_to(&stream)
             });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn handle(raw: &'static str, config: Config, metrics: &Arc<Metrics>) -> Response {
+        let router = Router::new(Some(vec![
+            Route::new(HTTPMethod::GET, "/panic", |_, _| -> Response { panic!("handler failed") }),
+        ]));
+        let request = Request::new(raw.as_bytes()).unwrap();
+        Server::handle_request(&request, &Arc::new(router), &Arc::new(config), metrics)
+    }
+
+    #[test]
+    fn answers_handler_panics_with_500_and_counts_them() {
+        let metrics = Arc::new(Metrics::new());
+        let config = || Config::new("127.0.0.1", 0, None);
+        let response = handle("GET /panic HTTP/1.1\r\n\r\n", config(), &metrics);
+        assert_eq!(response.status, HTTPStatus::InternalServerError);
+        assert_eq!(metrics.handler_panics(), 1);
+        assert_eq!(handle("GET /other HTTP/1.1\r\n\r\n", config(), &metrics).status, HTTPStatus::NotFound);
+    }
+
+    #[test]
+    fn serves_metrics_only_when_enabled() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_handler_panic();
+        let response = handle("GET /metrics HTTP/1.1\r\n\r\n", Config::new("127.0.0.1", 0, None), &metrics);
+        assert_eq!(response.status, HTTPStatus::NotFound);
+
+        let mut config = Config::new("127.0.0.1", 0, None);
+        config.metrics = true;
+        let response = handle("GET /metrics HTTP/1.1\r\n\r\n", config, &metrics);
+        let mut bytes = Vec::new();
+        response.write_to(&mut bytes).unwrap();
+        assert!(String::from_utf8(bytes).unwrap().contains("\nhandler_panics_total 1\n"));
+    }
+}
@@ -0,0 +1,496 @@
+use std::cell::RefCell;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::base64;
+use crate::config::{Config, SessionStore};
+use crate::hash::{constant_time_eq, hmac_sha256};
+use crate::http::cookie::{CookieError, SetCookie};
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::json::{self, Value};
+use crate::random::random_bytes;
+
+mod store;
+
+/// Browsers ignore cookies larger than this, name and attributes included.
+const MAX_COOKIE_SIZE: usize = 4096;
+const ID_LENGTH: usize = 32;
+
+fn new_id() -> io::Result<String> {
+    random_bytes(ID_LENGTH).map(|bytes| base64::encode_url(&bytes))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum RequestCookie {
+    #[default]
+    Absent,
+    Valid,
+    /// Forged, signed with a retired key, expired or pointing at a session that is gone.
+    Invalid,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    data: Vec<(String, Value)>,
+    /// Id in a server-side store, `None` for new sessions and in the cookie store.
+    id: Option<String>,
+    /// The id the request came with, whose stored data goes away if the id changes.
+    loaded_id: Option<String>,
+    cookie: RequestCookie,
+    /// The session has to be saved and its cookie issued again.
+    modified: bool,
+}
+
+/// Session data of the client making a request, loaded before the handler runs and saved
+/// after it returns. Handlers reach it as `request.session`. Values are JSON values; `get`
+/// converts them into any type with a `TryFrom<Value>` implementation.
+#[derive(Debug, Default)]
+pub struct Session {
+    state: RefCell<State>,
+}
+
+// The API for handlers; none of the built-in routes keeps session data
+#[allow(dead_code)]
+impl Session {
+    /// Value of `key`, `None` if it is unset or has another type.
+    pub fn get<T: TryFrom<Value>>(&self, key: &str) -> Option<T> {
+        let state = self.state.borrow();
+        let (_, value) = state.data.iter().find(|(name, _)| name == key)?;
+        T::try_from(value.clone()).ok()
+    }
+
+    pub fn set(&self, key: &str, value: impl Into<Value>) {
+        let mut state = self.state.borrow_mut();
+        let value = value.into();
+        match state.data.iter_mut().find(|(name, _)| name == key) {
+            Some((_, existing)) => *existing = value,
+            None => state.data.push((key.to_string(), value))
+        }
+        state.modified = true;
+    }
+
+    pub fn remove(&self, key: &str) -> Option<Value> {
+        let mut state = self.state.borrow_mut();
+        let index = state.data.iter().position(|(name, _)| name == key)?;
+        state.modified = true;
+        Some(state.data.remove(index).1)
+    }
+
+    /// Gives the session a new id, keeping its data. Call it when privileges change, e.g. on
+    /// login, so an id an attacker got hold of before is worthless afterwards. Cookie store
+    /// sessions have no id; their cookie is only issued again.
+    pub fn rotate(&self) {
+        let mut state = self.state.borrow_mut();
+        state.id = None;
+        state.modified = true;
+    }
+
+    /// Deletes all data of the session, and its cookie from the client.
+    pub fn destroy(&self) {
+        let mut state = self.state.borrow_mut();
+        state.data.clear();
+        state.id = None;
+        state.modified = true;
+    }
+}
+
+fn mac(config: &Config, key: &[u8], payload: &str) -> String {
+    // The cookie name is covered too, so a value can't be replayed under another name
+    let message = format!("{name}={payload}", name = config.session_cookie_name);
+    base64::encode_url(&hmac_sha256(key, message.as_bytes()))
+}
+
+/// `payload.mac`, signed with the first of `Config.session_keys`.
+fn sign(config: &Config, payload: &str) -> Option<String> {
+    let key = config.session_keys.first()?;
+    Some(format!("{payload}.{mac}", mac = mac(config, key, payload)))
+}
+
+/// The payload of a signed cookie value and the index of the key that signed it.
+fn verify<'a>(config: &Config, value: &'a str) -> Option<(&'a str, usize)> {
+    let (payload, signature) = value.rsplit_once('.')?;
+    config.session_keys.iter()
+        .position(|key| constant_time_eq(mac(config, key, payload).as_bytes(), signature.as_bytes()))
+        .map(|index| (payload, index))
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+/// Cookie store payload: the session data and its expiry as base64url encoded JSON.
+/// It is signed, not encrypted, so clients can read but not change it.
+fn encode_payload(data: &[(String, Value)], expires: SystemTime) -> String {
+    let payload = Value::object([
+        ("expires", unix_seconds(expires).into()),
+        ("data", Value::Object(data.to_vec())),
+    ]);
+    base64::encode_url(payload.to_string().as_bytes())
+}
+
+fn decode_payload(payload: &str) -> Option<Vec<(String, Value)>> {
+    let payload = json::parse_bytes(&base64::decode_url(payload)?).ok()?;
+    let expires = payload.get("expires")?.as_f64()?;
+    if expires <= unix_seconds(SystemTime::now()) as f64 {
+        return None;
+    }
+    match payload.get("data")? {
+        Value::Object(data) => Some(data.clone()),
+        _ => None
+    }
+}
+
+/// Fills `request.session` from the session cookie, if sessions are enabled.
+/// Clients may send several cookies of the same name; the first valid one wins.
+pub fn load(request: &Request, config: &Config) {
+    let Some(store) = &config.session_store else {
+        return;
+    };
+    let mut state = request.session.state.borrow_mut();
+    for value in request.cookies().get_all(&config.session_cookie_name) {
+        state.cookie = RequestCookie::Invalid;
+        let Some((payload, key_index)) = verify(config, value) else {
+            continue;
+        };
+        let data = match store {
+            SessionStore::Cookie => decode_payload(payload),
+            store => store::load(store, payload)
+        };
+        if let Some(data) = data {
+            let id = (*store != SessionStore::Cookie).then(|| payload.to_string());
+            *state = State {
+                data,
+                id: id.clone(),
+                loaded_id: id,
+                cookie: RequestCookie::Valid,
+                // Signed with an older key, sign it again with the current one
+                modified: key_index > 0,
+            };
+            break;
+        }
+    }
+}
+
+fn session_cookie(config: &Config, value: &str) -> Result<SetCookie, CookieError> {
+    let mut cookie = SetCookie::new(config.session_cookie_name.as_str(), value)?
+        .path("/")?
+        .http_only()
+        .same_site(config.session_same_site);
+    if let Some(domain) = &config.session_cookie_domain {
+        cookie = cookie.domain(domain.as_str())?;
+    }
+    Ok(match config.session_secure_cookie {
+        true => cookie.secure(),
+        false => cookie
+    })
+}
+
+/// Fails if the session cookie settings of `config` can't make a valid `Set-Cookie` header.
+pub fn check_cookie(config: &Config) -> Result<(), CookieError> {
+    session_cookie(config, "").map(drop)
+}
+
+/// Stores a modified `request.session` and adds its `Set-Cookie` header to `response`.
+/// Sessions left without data are deleted instead. Failures are logged, the response is
+/// sent either way.
+pub fn save(request: &Request, response: Response, config: &Config) -> Response {
+    let Some(store) = &config.session_store else {
+        return response;
+    };
+    let mut state = request.session.state.borrow_mut();
+    let with_cookie = |response: Response, cookie: Result<SetCookie, CookieError>| match cookie {
+        Ok(cookie) => response.cookie(cookie),
+        Err(error) => {
+            eprintln!("Failed to create the session cookie: {error}");
+            response
+        }
+    };
+    let removal = || session_cookie(config, "").map(SetCookie::removal);
+    if !state.modified {
+        return match state.cookie {
+            RequestCookie::Invalid => with_cookie(response, removal()),
+            _ => response
+        };
+    }
+
+    if state.data.is_empty() {
+        if let Some(loaded_id) = &state.loaded_id {
+            store::remove(store, loaded_id);
+        }
+        return match state.cookie {
+            RequestCookie::Absent => response,
+            _ => with_cookie(response, removal())
+        };
+    }
+
+    let expires = SystemTime::now() + config.session_max_age;
+    let payload = match store {
+        SessionStore::Cookie => encode_payload(&state.data, expires),
+        store => {
+            let id = match &state.id {
+                Some(id) => id.clone(),
+                None => match new_id() {
+                    Ok(id) => id,
+                    Err(error) => {
+                        eprintln!("Failed to create a session id: {error}");
+                        return response;
+                    }
+                }
+            };
+            if let Err(error) = store::save(store, &id, expires, &state.data) {
+                eprintln!("Failed to save session: {error}");
+                return response;
+            }
+            // Rotated or destroyed and started again, the data under the old id is obsolete
+            if let Some(loaded_id) = state.loaded_id.as_ref().filter(|&loaded_id| *loaded_id != id) {
+                store::remove(store, loaded_id);
+            }
+            state.id = Some(id.clone());
+            id
+        }
+    };
+    let Some(value) = sign(config, &payload) else {
+        eprintln!("Sessions are enabled without a signing key, not saving the session");
+        return response;
+    };
+    let cookie = session_cookie(config, &value).map(|cookie| cookie.max_age(config.session_max_age));
+    let length = cookie.as_ref().map_or(0, |cookie| cookie.to_string().len());
+    if length > MAX_COOKIE_SIZE {
+        eprintln!("Session cookie of {length} bytes is too large for clients, not saving the session");
+        return response;
+    }
+    with_cookie(response, cookie)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn config(store: SessionStore) -> Config {
+        let mut config = Config::new("127.0.0.1", 0, None);
+        config.session_store = Some(store);
+        config.session_keys = vec![b"current key".to_vec()];
+        config
+    }
+
+    fn request(cookie: Option<&str>) -> Request {
+        let cookie = cookie.map_or(String::new(), |cookie| format!("Cookie: {cookie}\r\n"));
+        let raw = format!("GET / HTTP/1.1\r\nHost: localhost\r\n{cookie}\r\n");
+        Request::new(io::Cursor::new(raw.into_bytes())).unwrap()
+    }
+
+    fn loaded(cookie: Option<&str>, config: &Config) -> Request {
+        let request = request(cookie);
+        load(&request, config);
+        request
+    }
+
+    /// A new session holding a user.
+    fn loaded_with(config: &Config) -> Request {
+        let request = loaded(None, config);
+        request.session.set("user", "ana");
+        request
+    }
+
+    /// The `Set-Cookie` header `save` adds, if any.
+    fn saved(request: &Request, config: &Config) -> Option<String> {
+        let response = save(request, Response::ok(), config);
+        response.headers.get("Set-Cookie").map(|values| values.join("\n"))
+    }
+
+    /// The `name=value` pair of a `Set-Cookie` header, as clients send it back.
+    fn cookie_pair(set_cookie: &str) -> &str {
+        set_cookie.split(';').next().unwrap()
+    }
+
+    #[test]
+    fn gets_sets_and_removes_values() {
+        let session = Session::default();
+        assert_eq!(session.get::<String>("user"), None);
+
+        session.set("user", "ana");
+        session.set("visits", 1u64);
+        assert_eq!(session.get::<String>("user"), Some("ana".to_string()));
+        assert_eq!(session.get::<u64>("visits"), Some(1));
+        // Another type than the stored one
+        assert_eq!(session.get::<u64>("user"), None);
+
+        session.set("visits", 2u64);
+        assert_eq!(session.get::<u64>("visits"), Some(2));
+        assert_eq!(session.state.borrow().data.len(), 2);
+
+        assert_eq!(session.remove("user"), Some(Value::from("ana")));
+        assert_eq!(session.remove("user"), None);
+        assert_eq!(session.get::<String>("user"), None);
+    }
+
+    #[test]
+    fn reading_leaves_the_session_unmodified() {
+        let config = config(SessionStore::Cookie);
+        let request = loaded(None, &config);
+        assert_eq!(request.session.get::<String>("user"), None);
+        assert_eq!(request.session.remove("user"), None);
+        assert_eq!(saved(&request, &config), None);
+    }
+
+    #[test]
+    fn does_nothing_without_a_store() {
+        let mut config = config(SessionStore::Cookie);
+        config.session_store = None;
+        let request = loaded(None, &config);
+        request.session.set("user", "ana");
+        assert_eq!(saved(&request, &config), None);
+    }
+
+    #[test]
+    fn keeps_cookie_sessions_in_a_signed_cookie() {
+        let config = config(SessionStore::Cookie);
+        let request = loaded(None, &config);
+        request.session.set("user", "ana");
+        let set_cookie = saved(&request, &config).unwrap();
+        assert!(set_cookie.starts_with("session="));
+        assert!(set_cookie.contains("; Path=/"));
+        assert!(set_cookie.contains("; HttpOnly"));
+        assert!(set_cookie.contains("; SameSite=Lax"));
+        assert!(set_cookie.contains("; Max-Age=86400"));
+
+        let request = loaded(Some(cookie_pair(&set_cookie)), &config);
+        assert_eq!(request.session.get::<String>("user"), Some("ana".to_string()));
+        // Unchanged sessions are not sent again
+        assert_eq!(saved(&request, &config), None);
+    }
+
+    #[test]
+    fn rejects_forged_and_foreign_cookies() {
+        let config = config(SessionStore::Cookie);
+        let request = loaded(None, &config);
+        request.session.set("user", "ana");
+        let set_cookie = saved(&request, &config).unwrap();
+        let (payload, mac) = cookie_pair(&set_cookie).rsplit_once('.').unwrap();
+
+        // Data of another user under the original signature
+        let forged = encode_payload(&[("user".to_string(), "admin".into())], SystemTime::now() + config.session_max_age);
+        let mut other_key = self::config(SessionStore::Cookie);
+        other_key.session_keys = vec![b"other key".to_vec()];
+        for cookie in [
+            format!("session={forged}.{mac}"),
+            format!("{payload}.{mac}x"),
+            payload.to_string(),
+            cookie_pair(&saved(&loaded_with(&other_key), &other_key).unwrap()).to_string(),
+        ] {
+            let request = loaded(Some(&cookie), &config);
+            assert_eq!(request.session.get::<String>("user"), None, "{cookie}");
+            // The client is told to drop the invalid cookie
+            let removal = saved(&request, &config).unwrap();
+            assert!(removal.starts_with("session=;"), "{cookie}: {removal}");
+            assert!(removal.contains("; Max-Age=0"), "{cookie}: {removal}");
+        }
+    }
+
+    #[test]
+    fn rejects_expired_cookies() {
+        let config = config(SessionStore::Cookie);
+        let payload = encode_payload(&[("user".to_string(), "ana".into())], SystemTime::now() - Duration::from_secs(1));
+        let cookie = format!("session={}", sign(&config, &payload).unwrap());
+        let request = loaded(Some(&cookie), &config);
+        assert_eq!(request.session.get::<String>("user"), None);
+    }
+
+    #[test]
+    fn signs_again_with_the_current_key() {
+        let mut config = config(SessionStore::Cookie);
+        let set_cookie = saved(&loaded_with(&config), &config).unwrap();
+
+        config.session_keys.insert(0, b"new key".to_vec());
+        let request = loaded(Some(cookie_pair(&set_cookie)), &config);
+        assert_eq!(request.session.get::<String>("user"), Some("ana".to_string()));
+        let signed_again = saved(&request, &config).unwrap();
+        assert_ne!(cookie_pair(&signed_again), cookie_pair(&set_cookie));
+
+        config.session_keys.truncate(1);
+        let request = loaded(Some(cookie_pair(&signed_again)), &config);
+        assert_eq!(request.session.get::<String>("user"), Some("ana".to_string()));
+    }
+
+    #[test]
+    fn uses_the_first_valid_cookie() {
+        let config = config(SessionStore::Cookie);
+        let set_cookie = saved(&loaded_with(&config), &config).unwrap();
+        let cookies = format!("session=invalid; {}", cookie_pair(&set_cookie));
+        let request = loaded(Some(&cookies), &config);
+        assert_eq!(request.session.get::<String>("user"), Some("ana".to_string()));
+    }
+
+    #[test]
+    fn does_not_save_sessions_too_large_for_a_cookie() {
+        let config = config(SessionStore::Cookie);
+        let request = loaded(None, &config);
+        request.session.set("notes", "x".repeat(MAX_COOKIE_SIZE));
+        assert_eq!(saved(&request, &config), None);
+    }
+
+    #[test]
+    fn keeps_server_side_sessions_under_an_id() {
+        let config = config(SessionStore::Memory);
+        let set_cookie = saved(&loaded_with(&config), &config).unwrap();
+        let (id, _) = cookie_pair(&set_cookie).trim_start_matches("session=").rsplit_once('.').unwrap();
+        assert_eq!(store::load(&SessionStore::Memory, id), Some(vec![("user".to_string(), "ana".into())]));
+
+        let request = loaded(Some(cookie_pair(&set_cookie)), &config);
+        request.session.set("visits", 1u64);
+        // Same id, the cookie is issued again for the new expiry
+        assert_eq!(cookie_pair(&saved(&request, &config).unwrap()), cookie_pair(&set_cookie));
+        assert_eq!(store::load(&SessionStore::Memory, id).map(|data| data.len()), Some(2));
+    }
+
+    #[test]
+    fn rotating_moves_the_data_to_a_new_id() {
+        let config = config(SessionStore::Memory);
+        let set_cookie = saved(&loaded_with(&config), &config).unwrap();
+        let old_id = cookie_pair(&set_cookie).trim_start_matches("session=").rsplit_once('.').unwrap().0.to_string();
+
+        let request = loaded(Some(cookie_pair(&set_cookie)), &config);
+        request.session.rotate();
+        let rotated = saved(&request, &config).unwrap();
+        assert_ne!(cookie_pair(&rotated), cookie_pair(&set_cookie));
+        assert_eq!(store::load(&SessionStore::Memory, &old_id), None);
+
+        // The old cookie is worthless now, the new one carries the data
+        let request = loaded(Some(cookie_pair(&set_cookie)), &config);
+        assert_eq!(request.session.get::<String>("user"), None);
+        let request = loaded(Some(cookie_pair(&rotated)), &config);
+        assert_eq!(request.session.get::<String>("user"), Some("ana".to_string()));
+    }
+
+    #[test]
+    fn destroying_deletes_the_data_and_the_cookie() {
+        let config = config(SessionStore::Memory);
+        let set_cookie = saved(&loaded_with(&config), &config).unwrap();
+        let id = cookie_pair(&set_cookie).trim_start_matches("session=").rsplit_once('.').unwrap().0.to_string();
+
+        let request = loaded(Some(cookie_pair(&set_cookie)), &config);
+        request.session.destroy();
+        assert_eq!(request.session.get::<String>("user"), None);
+        let removal = saved(&request, &config).unwrap();
+        assert!(removal.starts_with("session=;"), "{removal}");
+        assert_eq!(store::load(&SessionStore::Memory, &id), None);
+
+        // Destroying a session that was never saved sends nothing
+        let request = loaded(None, &config);
+        request.session.destroy();
+        assert_eq!(saved(&request, &config), None);
+    }
+
+    #[test]
+    fn checks_the_cookie_settings() {
+        let mut config = config(SessionStore::Cookie);
+        assert!(check_cookie(&config).is_ok());
+        config.session_cookie_name = "bad name".to_string();
+        assert!(check_cookie(&config).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::SessionStore;
+use crate::files::upload::{self, WriteMode};
+use crate::json::{self, Value};
+use crate::session::unix_seconds;
+
+type Data = Vec<(String, Value)>;
+
+static SESSIONS: Mutex<Option<HashMap<String, (SystemTime, Data)>>> = Mutex::new(None);
+
+fn with_sessions<T>(action: impl FnOnce(&mut HashMap<String, (SystemTime, Data)>) -> T) -> T {
+    let mut sessions = SESSIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let sessions = sessions.get_or_insert_with(HashMap::new);
+    let now = SystemTime::now();
+    sessions.retain(|_, (expires, _)| *expires > now);
+    action(sessions)
+}
+
+/// Ids are verified before they get here; checking the alphabet as well keeps
+/// anything path-like out of file names regardless.
+fn session_path(directory: &Path, id: &str) -> Option<PathBuf> {
+    match !id.is_empty() && id.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_') {
+        true => Some(directory.join(format!("{id}.json"))),
+        false => None
+    }
+}
+
+/// Expiry and data of a session file, `None` if it is unreadable.
+fn read_file(path: &Path) -> Option<(u64, Data)> {
+    let stored = json::parse(&fs::read_to_string(path).ok()?).ok()?;
+    let expires = u64::try_from(stored.get("expires")?.clone()).ok()?;
+    match stored.get("data")? {
+        Value::Object(data) => Some((expires, data.clone())),
+        _ => None
+    }
+}
+
+/// Deletes expired and unreadable session files.
+fn sweep(directory: &Path) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+    let now = unix_seconds(SystemTime::now());
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+            continue;
+        }
+        let expired = match read_file(&path) {
+            Some((expires, _)) => expires <= now,
+            None => true
+        };
+        if expired {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Data of an unexpired session, `None` for unknown ids and the cookie store.
+pub fn load(store: &SessionStore, id: &str) -> Option<Data> {
+    match store {
+        SessionStore::Cookie => None,
+        SessionStore::Memory => with_sessions(|sessions| sessions.get(id).map(|(_, data)| data.clone())),
+        SessionStore::File(directory) => {
+            let path = session_path(directory, id)?;
+            let (expires, data) = read_file(&path)?;
+            if expires <= unix_seconds(SystemTime::now()) {
+                let _ = fs::remove_file(path);
+                return None;
+            }
+            Some(data)
+        }
+    }
+}
+
+pub fn save(store: &SessionStore, id: &str, expires: SystemTime, data: &[(String, Value)]) -> std::io::Result<()> {
+    match store {
+        SessionStore::Cookie => Ok(()),
+        SessionStore::Memory => {
+            with_sessions(|sessions| sessions.insert(id.to_string(), (expires, data.to_vec())));
+            Ok(())
+        }
+        SessionStore::File(directory) => {
+            let path = session_path(directory, id).ok_or(ErrorKind::InvalidInput)?;
+            let stored = Value::object([
+                ("expires", unix_seconds(expires).into()),
+                ("data", Value::Object(data.to_vec())),
+            ]);
+            let created = upload::write_atomically(&path, WriteMode::Replace, true, |file| file.write_all(stored.to_string().as_bytes()))?;
+            // Sessions that were never ended explicitly pile up otherwise
+            if created {
+                sweep(directory);
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn remove(store: &SessionStore, id: &str) {
+    match store {
+        SessionStore::Cookie => {}
+        SessionStore::Memory => {
+            with_sessions(|sessions| sessions.remove(id));
+        }
+        SessionStore::File(directory) => {
+            if let Some(path) = session_path(directory, id) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
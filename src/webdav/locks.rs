@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::hash::{Sha256, to_hex};
+
+/// Longest lock the server grants, also used for `Timeout: Infinite`.
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct Lock {
+    pub token: String,
+    pub root: PathBuf,
+    /// URL of the locked resource, reported as the lock root.
+    pub href: String,
+    pub exclusive: bool,
+    pub depth_infinity: bool,
+    /// The client supplied `owner` element, serialized.
+    pub owner: Option<String>,
+    pub timeout: Duration,
+    expires: Instant,
+}
+
+impl Lock {
+    /// Whether the lock applies to `path`, either directly or through a locked ancestor.
+    fn covers(&self, path: &Path) -> bool {
+        self.root == path || (self.depth_infinity && path.starts_with(&self.root))
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.expires.saturating_duration_since(Instant::now())
+    }
+}
+
+/// Locks live in memory only, so a restart releases all of them.
+static LOCKS: Mutex<Vec<Lock>> = Mutex::new(Vec::new());
+
+fn with_locks<T>(action: impl FnOnce(&mut Vec<Lock>) -> T) -> T {
+    let mut locks = LOCKS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let now = Instant::now();
+    locks.retain(|lock| lock.expires > now);
+    action(&mut locks)
+}
+
+fn new_token() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_nanos());
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{nanos}-{pid}-{count}", pid = process::id(), count = COUNTER.fetch_add(1, Ordering::Relaxed)).as_bytes());
+    let hex = to_hex(&hasher.finish());
+    format!("opaquelocktoken:{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// Parses a `Timeout` header such as `Second-600, Infinite`, capped at `MAX_TIMEOUT`.
+pub fn parse_timeout(value: Option<&str>) -> Duration {
+    let Some(value) = value else {
+        return DEFAULT_TIMEOUT;
+    };
+    for candidate in value.split(',').map(str::trim) {
+        if candidate.eq_ignore_ascii_case("Infinite") {
+            return MAX_TIMEOUT;
+        }
+        if let Some(seconds) = candidate.strip_prefix("Second-").and_then(|seconds| seconds.parse::<u64>().ok()) {
+            return Duration::from_secs(seconds).min(MAX_TIMEOUT);
+        }
+    }
+    DEFAULT_TIMEOUT
+}
+
+/// Lock tokens mentioned anywhere in an `If` header. Tagged and untagged lists are not told
+/// apart: submitting a token is taken as proof of owning the lock.
+pub fn submitted_tokens(if_header: Option<&str>) -> Vec<String> {
+    let Some(mut rest) = if_header else {
+        return Vec::new();
+    };
+    let mut tokens = Vec::new();
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let token = &rest[start + 1..start + end];
+        if token.starts_with("opaquelocktoken:") {
+            tokens.push(token.to_string());
+        }
+        rest = &rest[start + end + 1..];
+    }
+    tokens
+}
+
+pub struct LockRequest {
+    pub root: PathBuf,
+    pub href: String,
+    pub exclusive: bool,
+    pub depth_infinity: bool,
+    pub owner: Option<String>,
+    pub timeout: Duration,
+}
+
+/// Grants a new lock unless it conflicts with an existing one. Exclusive locks conflict with
+/// everything; shared locks only with exclusive ones.
+pub fn acquire(request: LockRequest) -> Option<Lock> {
+    with_locks(|locks| {
+        let conflicts = locks.iter().any(|lock| {
+            let overlaps = lock.covers(&request.root) || (request.depth_infinity && lock.root.starts_with(&request.root));
+            overlaps && (lock.exclusive || request.exclusive)
+        });
+        if conflicts {
+            return None;
+        }
+        let lock = Lock {
+            token: new_token(),
+            root: request.root,
+            href: request.href,
+            exclusive: request.exclusive,
+            depth_infinity: request.depth_infinity,
+            owner: request.owner,
+            timeout: request.timeout,
+            expires: Instant::now() + request.timeout,
+        };
+        locks.push(lock.clone());
+        Some(lock)
+    })
+}
+
+/// Restarts the timeout of the submitted lock covering `path`.
+pub fn refresh(path: &Path, tokens: &[String], timeout: Duration) -> Option<Lock> {
+    with_locks(|locks| {
+        let lock = locks.iter_mut().find(|lock| lock.covers(path) && tokens.contains(&lock.token))?;
+        lock.timeout = timeout;
+        lock.expires = Instant::now() + timeout;
+        Some(lock.clone())
+    })
+}
+
+pub fn release(path: &Path, token: &str) -> bool {
+    with_locks(|locks| {
+        let before = locks.len();
+        locks.retain(|lock| !(lock.token == token && lock.covers(path)));
+        locks.len() != before
+    })
+}
+
+/// Locks that apply to `path`; with `recursive`, also those on anything below it.
+pub fn locks_on(path: &Path, recursive: bool) -> Vec<Lock> {
+    with_locks(|locks| {
+        locks.iter()
+            .filter(|lock| lock.covers(path) || (recursive && lock.root.starts_with(path)))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Drops the locks of a deleted or moved-away resource and everything below it.
+pub fn forget(path: &Path) {
+    with_locks(|locks| locks.retain(|lock| !lock.root.starts_with(path)));
+}
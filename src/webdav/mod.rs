@@ -0,0 +1,831 @@
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::files;
+use crate::files::path::{self, PathRejection};
+use crate::http::conditional::{self, Precondition};
+use crate::http::date::{format_http_date, format_rfc3339};
+use crate::http::headers::HTTPHeader;
+use crate::http::request::Request;
+use crate::http::response::Response;
+use crate::http::status::HTTPStatus;
+use crate::http::url::percent_encode_segment;
+use crate::webdav::locks::{Lock, LockRequest};
+use crate::webdav::xml::{DAV_NAMESPACE, Element, Node};
+
+pub mod xml;
+pub mod locks;
+pub mod properties;
+
+/// Methods the files routes answer while WebDAV is enabled.
+const ALLOWED_METHODS: &str = "OPTIONS, GET, POST, PUT, DELETE, PATCH, PROPFIND, PROPPATCH, MKCOL, COPY, MOVE, LOCK, UNLOCK";
+
+/// Properties the server computes itself. Clients can read but not set them.
+const LIVE_PROPERTIES: [&str; 9] = [
+    "creationdate", "displayname", "getcontentlength", "getcontenttype", "getetag", "getlastmodified",
+    "resourcetype", "supportedlock", "lockdiscovery",
+];
+
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// Resolves the request path and runs a WebDAV `handler` on it, or answers `405` while
+/// WebDAV is disabled.
+pub fn handle(request: &Request, config: &Config, handler: fn(&Request, &Path, &Config) -> Response) -> Response {
+    if !config.webdav {
+        return Response::new(HTTPStatus::MethodNotAllowed);
+    }
+    match files::resolve_request_path(request, config) {
+        Err(response) => response,
+        Ok(path) => handler(request, path.as_path(), config)
+    }
+}
+
+/// `423 Locked` unless the request submits the token of every lock on `path`, and with
+/// `recursive` of every lock below it.
+pub fn check_locks(request: &Request, path: &Path, recursive: bool) -> Result<(), Response> {
+    let tokens = locks::submitted_tokens(request.get_known_header_line(HTTPHeader::If).as_deref());
+    let missing = locks::locks_on(path, recursive).into_iter().find(|lock| !tokens.contains(&lock.token));
+    match missing {
+        None => Ok(()),
+        Some(lock) => Err(error_response(
+            HTTPStatus::Locked,
+            &format!("<D:lock-token-submitted><D:href>{}</D:href></D:lock-token-submitted>", xml::escape(&lock.href)),
+        ))
+    }
+}
+
+/// Drops the locks and dead properties of a deleted resource and everything below it.
+pub fn forget(path: &Path) {
+    locks::forget(path);
+    properties::forget(path);
+}
+
+fn xml_document(body: &str) -> String {
+    format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n{body}")
+}
+
+/// A response carrying a `DAV:error` body with the violated precondition.
+fn error_response(status: HTTPStatus, condition: &str) -> Response {
+    Response::new(status).content(XML_CONTENT_TYPE, xml_document(&format!("<D:error xmlns:D=\"DAV:\">{condition}</D:error>")))
+}
+
+fn multistatus(responses: &str) -> Response {
+    Response::new(HTTPStatus::MultiStatus)
+        .content(XML_CONTENT_TYPE, xml_document(&format!("<D:multistatus xmlns:D=\"DAV:\">{responses}</D:multistatus>")))
+}
+
+fn propstat(properties: &str, status: &HTTPStatus) -> String {
+    format!("<D:propstat><D:prop>{properties}</D:prop><D:status>HTTP/1.1 {status}</D:status></D:propstat>")
+}
+
+/// Parses the XML request body, `Ok(None)` if there is none. Bodies are read into memory
+/// whole, so they are limited to `Config.max_buffered_body_size`.
+fn parse_body(request: &Request, config: &Config) -> Result<Option<Element>, Response> {
+    let body = request.body(config)?;
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    let text = std::str::from_utf8(body)
+        .map_err(|_| Response::new(HTTPStatus::BadRequest).text("Request body is not UTF-8"))?;
+    xml::parse(text)
+        .map(Some)
+        .map_err(|error| Response::new(HTTPStatus::BadRequest).text(format!("Malformed XML body: {error}")))
+}
+
+/// The `Depth` header: `Some(true)` for infinity, `Some(false)` for 0, `None` for anything else.
+/// `default` applies when the header is missing.
+fn depth_infinity(request: &Request, default: bool) -> Option<bool> {
+    match request.get_known_header_line(HTTPHeader::Depth).as_deref().map(str::trim) {
+        None => Some(default),
+        Some("0") => Some(false),
+        Some(value) if value.eq_ignore_ascii_case("infinity") => Some(true),
+        Some(_) => None
+    }
+}
+
+/// Serializes `element` as the content of a `DAV:prop`, where no default namespace is declared.
+fn property_xml(element: &Element) -> String {
+    let mut out = String::new();
+    element.write_xml(&mut out, None);
+    out
+}
+
+fn empty_property_xml(namespace: &str, name: &str) -> String {
+    match namespace == DAV_NAMESPACE {
+        true => format!("<D:{name}/>"),
+        false => property_xml(&Element::new(namespace, name))
+    }
+}
+
+fn active_lock_xml(lock: &Lock) -> String {
+    let scope = if lock.exclusive { "exclusive" } else { "shared" };
+    let depth = if lock.depth_infinity { "infinity" } else { "0" };
+    let owner = lock.owner.as_ref().map_or(String::new(), |owner| format!("<D:owner>{owner}</D:owner>"));
+    format!(
+        "<D:activelock><D:locktype><D:write/></D:locktype><D:lockscope><D:{scope}/></D:lockscope>\
+         <D:depth>{depth}</D:depth>{owner}<D:timeout>Second-{timeout}</D:timeout>\
+         <D:locktoken><D:href>{token}</D:href></D:locktoken><D:lockroot><D:href>{root}</D:href></D:lockroot></D:activelock>",
+        timeout = lock.remaining().as_secs_f64().ceil() as u64,
+        token = lock.token,
+        root = xml::escape(&lock.href)
+    )
+}
+
+/// A file or collection in a `PROPFIND` answer.
+struct Resource {
+    path: PathBuf,
+    href: String,
+    metadata: Metadata,
+}
+
+impl Resource {
+    /// Value of a live property, `None` if the resource does not have it.
+    fn live_property(&self, name: &str, config: &Config) -> Option<String> {
+        let is_file = self.metadata.is_file();
+        match name {
+            "creationdate" => self.metadata.created().ok().map(format_rfc3339),
+            "displayname" => self.path.file_name().map(|name| xml::escape(&name.to_string_lossy())),
+            "getcontentlength" if is_file => Some(self.metadata.len().to_string()),
+            "getcontenttype" if is_file => {
+                let file = File::open(&self.path).ok()?;
+                Some(xml::escape(&files::content_type(&self.path, &file, config)))
+            }
+            "getetag" if is_file => {
+                let file = File::open(&self.path).ok()?;
+                let etag = files::validators(&file, &self.metadata, config).etag?;
+                Some(xml::escape(&etag.to_string()))
+            }
+            "getlastmodified" => self.metadata.modified().ok().map(format_http_date),
+            "resourcetype" => Some(if self.metadata.is_dir() { "<D:collection/>".to_string() } else { String::new() }),
+            "supportedlock" => Some(
+                ["exclusive", "shared"].iter()
+                    .map(|scope| format!("<D:lockentry><D:lockscope><D:{scope}/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry>"))
+                    .collect()
+            ),
+            "lockdiscovery" => Some(locks::locks_on(&self.path, false).iter().map(active_lock_xml).collect()),
+            _ => None
+        }
+    }
+
+    fn live_property_xml(&self, name: &str, config: &Config) -> Option<String> {
+        self.live_property(name, config).map(|value| match value.is_empty() {
+            true => format!("<D:{name}/>"),
+            false => format!("<D:{name}>{value}</D:{name}>")
+        })
+    }
+}
+
+enum PropertySelection {
+    All,
+    Names,
+    Some(Vec<(String, String)>),
+}
+
+fn propfind_response(resource: &Resource, selection: &PropertySelection, config: &Config) -> String {
+    let mut found = String::new();
+    let mut missing = String::new();
+    match selection {
+        PropertySelection::All => {
+            for name in LIVE_PROPERTIES {
+                found.push_str(&resource.live_property_xml(name, config).unwrap_or_default());
+            }
+            for property in properties::get_all(&resource.path) {
+                found.push_str(&property_xml(&property));
+            }
+        }
+        PropertySelection::Names => {
+            for name in LIVE_PROPERTIES {
+                if resource.live_property(name, config).is_some() {
+                    found.push_str(&format!("<D:{name}/>"));
+                }
+            }
+            for property in properties::get_all(&resource.path) {
+                found.push_str(&empty_property_xml(&property.namespace, &property.name));
+            }
+        }
+        PropertySelection::Some(names) => {
+            for (namespace, name) in names {
+                let value = match namespace == DAV_NAMESPACE {
+                    true => resource.live_property_xml(name, config),
+                    false => None
+                };
+                match value.or_else(|| properties::get(&resource.path, namespace, name).map(|property| property_xml(&property))) {
+                    Some(value) => found.push_str(&value),
+                    None => missing.push_str(&empty_property_xml(namespace, name))
+                }
+            }
+        }
+    }
+
+    let mut response = format!("<D:response><D:href>{}</D:href>", xml::escape(&resource.href));
+    if !found.is_empty() || missing.is_empty() {
+        response.push_str(&propstat(&found, &HTTPStatus::Ok));
+    }
+    if !missing.is_empty() {
+        response.push_str(&propstat(&missing, &HTTPStatus::NotFound));
+    }
+    response.push_str("</D:response>");
+    response
+}
+
+/// `OPTIONS`: advertises WebDAV class 1 and 2.
+pub fn options(_request: &Request, _path: &Path, _config: &Config) -> Response {
+    Response::ok()
+        .header(HTTPHeader::Dav, "1, 2")
+        .header(HTTPHeader::Allow, ALLOWED_METHODS)
+        // Makes Windows clients talk WebDAV rather than FrontPage extensions
+        .header("MS-Author-Via", "DAV")
+}
+
+/// `PROPFIND` with `Depth: 0` or `1`. `Depth: infinity` is refused, as it would walk the
+/// whole share.
+pub fn propfind(request: &Request, path: &Path, config: &Config) -> Response {
+    let depth = match request.get_known_header_line(HTTPHeader::Depth).as_deref().map(str::trim) {
+        Some("0") => 0,
+        Some("1") => 1,
+        Some(value) if !value.eq_ignore_ascii_case("infinity") => return Response::new(HTTPStatus::BadRequest),
+        _ => return error_response(HTTPStatus::Forbidden, "<D:propfind-finite-depth/>")
+    };
+    let selection = match parse_body(request, config) {
+        Err(response) => return response,
+        Ok(None) => PropertySelection::All,
+        Ok(Some(body)) if body.is(DAV_NAMESPACE, "propfind") => {
+            if let Some(prop) = body.child(DAV_NAMESPACE, "prop") {
+                PropertySelection::Some(prop.elements().map(|property| (property.namespace.clone(), property.name.clone())).collect())
+            } else if body.child(DAV_NAMESPACE, "propname").is_some() {
+                PropertySelection::Names
+            } else if body.child(DAV_NAMESPACE, "allprop").is_some() {
+                PropertySelection::All
+            } else {
+                return Response::new(HTTPStatus::BadRequest).text("Expected allprop, propname or prop");
+            }
+        }
+        Ok(Some(_)) => return Response::new(HTTPStatus::BadRequest).text("Expected a propfind element")
+    };
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(error) => return files::io_error_response(&error, path)
+    };
+
+    let mut href = request.path().to_string();
+    if metadata.is_dir() && !href.ends_with('/') {
+        href.push('/');
+    }
+    let mut resources = vec![Resource { path: path.to_path_buf(), href, metadata }];
+    if depth == 1 && resources[0].metadata.is_dir() {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(error) => return files::io_error_response(&error, path)
+        };
+        let mut children = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                let path = entry.path();
+                // Symlinks the policy rejects are left out of the listing
+                let metadata = entry_metadata(&path, config).ok()?;
+                let suffix = if metadata.is_dir() { "/" } else { "" };
+                let href = format!("{}{}{suffix}", resources[0].href, percent_encode_segment(&name));
+                Some(Resource { path, href, metadata })
+            })
+            .collect::<Vec<_>>();
+        children.sort_by(|a, b| a.href.cmp(&b.href));
+        resources.extend(children);
+    }
+
+    multistatus(&resources.iter().map(|resource| propfind_response(resource, &selection, config)).collect::<String>())
+}
+
+/// `PROPPATCH`: sets and removes dead properties, all or nothing. Properties in the `DAV:`
+/// namespace are protected.
+pub fn proppatch(request: &Request, path: &Path, config: &Config) -> Response {
+    if let Err(response) = check_locks(request, path, false) {
+        return response;
+    }
+    let body = match parse_body(request, config) {
+        Err(response) => return response,
+        Ok(Some(body)) if body.is(DAV_NAMESPACE, "propertyupdate") => body,
+        Ok(_) => return Response::new(HTTPStatus::BadRequest).text("Expected a propertyupdate element")
+    };
+    if !path.exists() {
+        return Response::not_found();
+    }
+
+    // Updates in document order, `true` marking a removal
+    let mut updates: Vec<(Element, bool)> = Vec::new();
+    for instruction in body.elements() {
+        let remove = match (instruction.namespace.as_str(), instruction.name.as_str()) {
+            (DAV_NAMESPACE, "set") => false,
+            (DAV_NAMESPACE, "remove") => true,
+            _ => continue
+        };
+        for prop in instruction.elements().filter(|element| element.is(DAV_NAMESPACE, "prop")) {
+            updates.extend(prop.elements().map(|property| (property.clone(), remove)));
+        }
+    }
+
+    let protected = updates.iter().any(|(property, _)| property.namespace == DAV_NAMESPACE);
+    let mut forbidden = String::new();
+    let mut failed = String::new();
+    let mut succeeded = String::new();
+    for (property, remove) in updates {
+        let name = empty_property_xml(&property.namespace, &property.name);
+        if property.namespace == DAV_NAMESPACE {
+            forbidden.push_str(&name);
+        } else if protected {
+            failed.push_str(&name);
+        } else {
+            match remove {
+                true => properties::remove(path, &property.namespace, &property.name),
+                false => properties::set(path, property)
+            }
+            succeeded.push_str(&name);
+        }
+    }
+
+    let mut response = format!("<D:response><D:href>{}</D:href>", xml::escape(request.path()));
+    for (names, status) in [(succeeded, HTTPStatus::Ok), (forbidden, HTTPStatus::Forbidden), (failed, HTTPStatus::FailedDependency)] {
+        if !names.is_empty() {
+            response.push_str(&propstat(&names, &status));
+        }
+    }
+    response.push_str("</D:response>");
+    multistatus(&response)
+}
+
+/// `MKCOL`: creates a directory. Its parent has to exist already.
+pub fn mkcol(request: &Request, path: &Path, _config: &Config) -> Response {
+    if request.content_length() > 0 {
+        return Response::new(HTTPStatus::UnsupportedMediaType);
+    }
+    if let Err(response) = check_locks(request, path, false) {
+        return response;
+    }
+    if fs::symlink_metadata(path).is_ok() {
+        return Response::new(HTTPStatus::MethodNotAllowed).header(HTTPHeader::Allow, ALLOWED_METHODS);
+    }
+    match fs::create_dir(path) {
+        Ok(_) => Response::new(HTTPStatus::Created).header(HTTPHeader::Location, request.path()),
+        Err(error) => files::write_error_response(&error, path)
+    }
+}
+
+/// Extracts the path from a `Destination` header, which may be an absolute URL.
+/// Destinations on another host are refused with `502`.
+fn destination_path(request: &Request) -> Result<String, Response> {
+    let Some(destination) = request.get_known_header_line(HTTPHeader::Destination) else {
+        return Err(Response::new(HTTPStatus::BadRequest).text("Missing Destination header"));
+    };
+    let destination = destination.trim();
+    let path = match destination.split_once("://") {
+        None => destination,
+        Some((_, rest)) => {
+            let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            let host = request.headers.get("Host").and_then(|values| values.first());
+            if host.is_some_and(|host| !host.eq_ignore_ascii_case(authority)) {
+                return Err(Response::new(HTTPStatus::BadGateway));
+            }
+            path
+        }
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    match path.starts_with('/') {
+        true => Ok(path.to_string()),
+        false => Err(Response::new(HTTPStatus::BadRequest).text("Malformed Destination header"))
+    }
+}
+
+fn remove_any(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path)?.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path)
+    }
+}
+
+/// Metadata of an entry met while walking the files directory, subject to the symlink policy.
+/// Rejected symlinks are reported as `PermissionDenied`.
+fn entry_metadata(path: &Path, config: &Config) -> io::Result<Metadata> {
+    let root = Path::new(config.files_path.as_deref().unwrap_or_default());
+    path::entry_metadata(root, path, config.symlink_policy).map_err(|rejection| match rejection {
+        PathRejection::Io(error) => error,
+        rejection => {
+            eprintln!("Rejected `{path}` while walking the files directory: {rejection}", path = path.display());
+            io::Error::new(ErrorKind::PermissionDenied, rejection.to_string())
+        }
+    })
+}
+
+/// Copies a file, or a directory with everything below it when `recursive`, to `destination`.
+/// Symlinks the policy allows are copied as what they point to. Without a `destination` the
+/// tree is only walked, so a transfer can be refused before anything is changed.
+fn copy_tree(source: &Path, destination: Option<&Path>, recursive: bool, config: &Config) -> io::Result<()> {
+    copy_entry(source, destination, recursive, config, &mut Vec::new())
+}
+
+/// `copy_tree` below the directories in `ancestors`, which a symlink must not lead back to.
+fn copy_entry(source: &Path, destination: Option<&Path>, recursive: bool, config: &Config, ancestors: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !entry_metadata(source, config)?.is_dir() {
+        return match destination {
+            Some(destination) => fs::copy(source, destination).map(|_| ()),
+            None => Ok(())
+        };
+    }
+    if let Some(destination) = destination {
+        fs::create_dir(destination)?;
+    }
+    if recursive {
+        let canonical = fs::canonicalize(source)?;
+        if ancestors.contains(&canonical) {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "symlink loop"));
+        }
+        ancestors.push(canonical);
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let destination = destination.map(|destination| destination.join(entry.file_name()));
+            copy_entry(&entry.path(), destination.as_deref(), true, config, ancestors)?;
+        }
+        ancestors.pop();
+    }
+    Ok(())
+}
+
+/// Shared part of `COPY` and `MOVE`: checks the source tree and the destination, and clears
+/// the destination if overwriting is allowed. Returns the destination path and whether it
+/// existed before.
+fn prepare_transfer(request: &Request, source: &Path, config: &Config, moving: bool, recursive: bool) -> Result<(PathBuf, bool), Response> {
+    let destination_url = destination_path(request)?;
+    let destination = files::resolve_files_url_path(request, &destination_url, config)
+        .map_err(|response| match response.status {
+            HTTPStatus::NotFound => Response::new(HTTPStatus::Forbidden),
+            _ => response
+        })?;
+    let validators = files::current_validators(source, config);
+    if !validators.exists {
+        return Err(Response::not_found());
+    }
+    if conditional::evaluate(request, &validators, false) == Precondition::Failed {
+        return Err(Response::new(HTTPStatus::PreconditionFailed));
+    }
+    if destination == source || destination.starts_with(source) {
+        return Err(Response::new(HTTPStatus::Forbidden).text("Destination is the source or inside it"));
+    }
+    if moving {
+        check_locks(request, source, true)?;
+    }
+    check_locks(request, &destination, true)?;
+    copy_tree(source, None, recursive, config).map_err(|error| files::io_error_response(&error, source))?;
+
+    let existed = fs::symlink_metadata(&destination).is_ok();
+    if existed {
+        let overwrite = request.get_known_header_line(HTTPHeader::Overwrite);
+        if overwrite.is_some_and(|value| value.trim().eq_ignore_ascii_case("F")) {
+            return Err(Response::new(HTTPStatus::PreconditionFailed));
+        }
+        remove_any(&destination).map_err(|error| files::write_error_response(&error, &destination))?;
+        properties::forget(&destination);
+    }
+    if !destination.parent().is_some_and(Path::is_dir) {
+        return Err(Response::new(HTTPStatus::Conflict));
+    }
+    Ok((destination, existed))
+}
+
+fn transfer_response(existed: bool) -> Response {
+    match existed {
+        true => Response::new(HTTPStatus::NoContent),
+        false => Response::new(HTTPStatus::Created)
+    }
+}
+
+/// `COPY` to `Destination`, with `Depth: 0` copying a directory without its contents.
+pub fn copy(request: &Request, path: &Path, config: &Config) -> Response {
+    let Some(recursive) = depth_infinity(request, true) else {
+        return Response::new(HTTPStatus::BadRequest).text("COPY supports Depth 0 and infinity");
+    };
+    let (destination, existed) = match prepare_transfer(request, path, config, false, recursive) {
+        Ok(prepared) => prepared,
+        Err(response) => return response
+    };
+    if let Err(error) = copy_tree(path, Some(&destination), recursive, config) {
+        return files::write_error_response(&error, &destination);
+    }
+    properties::copy(path, &destination);
+    transfer_response(existed)
+}
+
+/// `MOVE` to `Destination`. Locks on the source are dropped, dead properties move along.
+pub fn move_resource(request: &Request, path: &Path, config: &Config) -> Response {
+    if depth_infinity(request, true) != Some(true) {
+        return Response::new(HTTPStatus::BadRequest).text("MOVE supports Depth infinity only");
+    }
+    let (destination, existed) = match prepare_transfer(request, path, config, true, true) {
+        Ok(prepared) => prepared,
+        Err(response) => return response
+    };
+    let result = fs::rename(path, &destination).or_else(|error| {
+        // Renaming across filesystems fails with EXDEV
+        match error.raw_os_error() {
+            Some(18) => copy_tree(path, Some(&destination), true, config).and_then(|_| remove_any(path)),
+            _ => Err(error)
+        }
+    });
+    if let Err(error) = result {
+        return files::write_error_response(&error, &destination);
+    }
+    properties::copy(path, &destination);
+    forget(path);
+    transfer_response(existed)
+}
+
+/// Serializes the children of the client's `owner` element for `lockdiscovery`.
+fn owner_xml(owner: &Element) -> String {
+    let mut out = String::new();
+    for node in &owner.children {
+        match node {
+            Node::Text(text) => out.push_str(&xml::escape(text)),
+            Node::Element(element) => element.write_xml(&mut out, None)
+        }
+    }
+    out
+}
+
+fn lock_response(status: HTTPStatus, lock: &Lock) -> Response {
+    let body = format!("<D:prop xmlns:D=\"DAV:\"><D:lockdiscovery>{}</D:lockdiscovery></D:prop>", active_lock_xml(lock));
+    Response::new(status)
+        .header(HTTPHeader::LockToken, format!("<{}>", lock.token))
+        .content(XML_CONTENT_TYPE, xml_document(&body))
+}
+
+/// `LOCK`: takes a write lock, or refreshes one when sent without a body. Locking an unmapped
+/// URL creates an empty file there.
+pub fn lock(request: &Request, path: &Path, config: &Config) -> Response {
+    let timeout = locks::parse_timeout(request.get_known_header_line(HTTPHeader::Timeout).as_deref());
+    let body = match parse_body(request, config) {
+        Err(response) => return response,
+        Ok(body) => body
+    };
+    let Some(lockinfo) = body else {
+        let tokens = locks::submitted_tokens(request.get_known_header_line(HTTPHeader::If).as_deref());
+        return match locks::refresh(path, &tokens, timeout) {
+            Some(lock) => lock_response(HTTPStatus::Ok, &lock),
+            None => error_response(HTTPStatus::PreconditionFailed, "<D:lock-token-matches-request-uri/>")
+        };
+    };
+    if !lockinfo.is(DAV_NAMESPACE, "lockinfo") {
+        return Response::new(HTTPStatus::BadRequest).text("Expected a lockinfo element");
+    }
+    let exclusive = match lockinfo.child(DAV_NAMESPACE, "lockscope") {
+        Some(scope) if scope.child(DAV_NAMESPACE, "exclusive").is_some() => true,
+        Some(scope) if scope.child(DAV_NAMESPACE, "shared").is_some() => false,
+        _ => return Response::new(HTTPStatus::BadRequest).text("Expected an exclusive or shared lockscope")
+    };
+    let write_lock = lockinfo.child(DAV_NAMESPACE, "locktype").and_then(|locktype| locktype.child(DAV_NAMESPACE, "write"));
+    if write_lock.is_none() {
+        return Response::new(HTTPStatus::BadRequest).text("Only write locks are supported");
+    }
+    let Some(depth_infinity) = depth_infinity(request, true) else {
+        return Response::new(HTTPStatus::BadRequest).text("LOCK supports Depth 0 and infinity");
+    };
+
+    let lock_request = LockRequest {
+        root: path.to_path_buf(),
+        href: request.path().to_string(),
+        exclusive,
+        depth_infinity,
+        owner: lockinfo.child(DAV_NAMESPACE, "owner").map(owner_xml),
+        timeout,
+    };
+    let Some(lock) = locks::acquire(lock_request) else {
+        return error_response(HTTPStatus::Locked, "<D:no-conflicting-lock/>");
+    };
+    if fs::symlink_metadata(path).is_ok() {
+        return lock_response(HTTPStatus::Ok, &lock);
+    }
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(_) => lock_response(HTTPStatus::Created, &lock),
+        Err(error) => {
+            locks::release(path, &lock.token);
+            match error.kind() {
+                ErrorKind::NotFound => Response::new(HTTPStatus::Conflict),
+                _ => files::write_error_response(&error, path)
+            }
+        }
+    }
+}
+
+/// `UNLOCK` with the `Lock-Token` of a lock on the resource.
+pub fn unlock(request: &Request, path: &Path, _config: &Config) -> Response {
+    let Some(token) = request.get_known_header_line(HTTPHeader::LockToken) else {
+        return Response::new(HTTPStatus::BadRequest).text("Missing Lock-Token header");
+    };
+    let token = token.trim().trim_start_matches('<').trim_end_matches('>');
+    match locks::release(path, token) {
+        true => Response::new(HTTPStatus::NoContent),
+        false => error_response(HTTPStatus::Conflict, "<D:lock-token-matches-request-uri/>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::files::scratch::ScratchDir;
+
+    use super::*;
+
+    struct Share {
+        root: ScratchDir,
+        config: Config,
+    }
+
+    impl Share {
+        fn new() -> Share {
+            let root = ScratchDir::new();
+            let mut config = Config::new("127.0.0.1", 4221, root.to_str().map(str::to_string));
+            config.webdav = true;
+            Share { root, config }
+        }
+
+        /// Runs `handler` on a request for `target` and returns the response with its body.
+        fn request(&self, method: &str, target: &str, headers: &[(&str, &str)], body: &str,
+                   handler: fn(&Request, &Path, &Config) -> Response) -> (Response, String) {
+            let headers: String = headers.iter().map(|(name, value)| format!("{name}: {value}\r\n")).collect();
+            let raw = format!("{method} {target} HTTP/1.1\r\nHost: localhost\r\n{headers}Content-Length: {length}\r\n\r\n{body}", length = body.len());
+            let request = Request::new(io::Cursor::new(raw.into_bytes())).unwrap();
+            let response = handle(&request, &self.config, handler);
+            let mut bytes = Vec::new();
+            response.write_to(&mut bytes).unwrap();
+            let text = String::from_utf8(bytes).unwrap();
+            let body = text.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+            (response, body)
+        }
+    }
+
+    fn header(response: &Response, name: &str) -> Option<String> {
+        response.headers.get(name).map(|values| values.join(", "))
+    }
+
+    #[test]
+    fn answers_405_while_disabled() {
+        let mut share = Share::new();
+        share.config.webdav = false;
+        let (response, _) = share.request("PROPFIND", "/files/", &[("Depth", "0")], "", propfind);
+        assert_eq!(response.status, HTTPStatus::MethodNotAllowed);
+    }
+
+    #[test]
+    fn propfind_lists_a_directory() {
+        let share = Share::new();
+        share.root.file("docs/a b.txt", b"hello");
+        fs::create_dir(share.root.join("docs/sub")).unwrap();
+
+        let (response, body) = share.request("PROPFIND", "/files/docs", &[("Depth", "1")], "", propfind);
+        assert_eq!(response.status, HTTPStatus::MultiStatus);
+        let hrefs = body.match_indices("<D:href>").map(|(index, _)| &body[index + 8..][..body[index + 8..].find('<').unwrap()]).collect::<Vec<_>>();
+        assert_eq!(hrefs, ["/files/docs/", "/files/docs/a%20b.txt", "/files/docs/sub/"]);
+        assert!(body.contains("<D:getcontentlength>5</D:getcontentlength>"));
+
+        let (_, body) = share.request("PROPFIND", "/files/docs/a%20b.txt", &[("Depth", "0")],
+                                      r#"<propfind xmlns="DAV:"><prop><getcontentlength/><missing xmlns="urn:x"/></prop></propfind>"#, propfind);
+        assert!(body.contains("<D:getcontentlength>5</D:getcontentlength>"));
+        assert!(body.contains("HTTP/1.1 404 Not Found"));
+    }
+
+    #[test]
+    fn propfind_refuses_infinite_depth_and_bad_bodies() {
+        let share = Share::new();
+        assert_eq!(share.request("PROPFIND", "/files/", &[], "", propfind).0.status, HTTPStatus::Forbidden);
+        assert_eq!(share.request("PROPFIND", "/files/", &[("Depth", "2")], "", propfind).0.status, HTTPStatus::BadRequest);
+        assert_eq!(share.request("PROPFIND", "/files/", &[("Depth", "0")], "<propfind", propfind).0.status, HTTPStatus::BadRequest);
+        assert_eq!(share.request("PROPFIND", "/files/", &[("Depth", "0")], "<other/>", propfind).0.status, HTTPStatus::BadRequest);
+        assert_eq!(share.request("PROPFIND", "/files/missing", &[("Depth", "0")], "", propfind).0.status, HTTPStatus::NotFound);
+    }
+
+    #[test]
+    fn limits_the_xml_body_size() {
+        let mut share = Share::new();
+        share.config.max_buffered_body_size = 16;
+        let body = r#"<propfind xmlns="DAV:"><allprop/></propfind>"#;
+        assert_eq!(share.request("PROPFIND", "/files/", &[("Depth", "0")], body, propfind).0.status, HTTPStatus::ContentTooLarge);
+    }
+
+    #[test]
+    fn proppatch_sets_and_removes_dead_properties() {
+        let share = Share::new();
+        share.root.file("note.txt", b"");
+        let set = r#"<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:z">
+            <D:set><D:prop><Z:color>blue</Z:color></D:prop></D:set></D:propertyupdate>"#;
+        let (response, body) = share.request("PROPPATCH", "/files/note.txt", &[], set, proppatch);
+        assert_eq!(response.status, HTTPStatus::MultiStatus);
+        assert!(body.contains("HTTP/1.1 200 OK"));
+
+        let find = r#"<propfind xmlns="DAV:"><prop><color xmlns="urn:z"/></prop></propfind>"#;
+        let (_, body) = share.request("PROPFIND", "/files/note.txt", &[("Depth", "0")], find, propfind);
+        assert!(body.contains(r#"<color xmlns="urn:z">blue</color>"#), "{body}");
+
+        // Protected properties fail the whole update
+        let protected = r#"<D:propertyupdate xmlns:D="DAV:" xmlns:Z="urn:z">
+            <D:remove><D:prop><Z:color/></D:prop></D:remove>
+            <D:set><D:prop><D:getetag>x</D:getetag></D:prop></D:set></D:propertyupdate>"#;
+        let (_, body) = share.request("PROPPATCH", "/files/note.txt", &[], protected, proppatch);
+        assert!(body.contains("HTTP/1.1 403 Forbidden") && body.contains("HTTP/1.1 424 Failed Dependency"));
+        let (_, body) = share.request("PROPFIND", "/files/note.txt", &[("Depth", "0")], find, propfind);
+        assert!(body.contains("blue"));
+
+        let remove = r#"<D:propertyupdate xmlns:D="DAV:"><D:remove><D:prop><color xmlns="urn:z"/></D:prop></D:remove></D:propertyupdate>"#;
+        share.request("PROPPATCH", "/files/note.txt", &[], remove, proppatch);
+        let (_, body) = share.request("PROPFIND", "/files/note.txt", &[("Depth", "0")], find, propfind);
+        assert!(!body.contains("blue") && body.contains("HTTP/1.1 404 Not Found"));
+    }
+
+    #[test]
+    fn lock_guards_writes_until_unlocked() {
+        let share = Share::new();
+        let lockinfo = r#"<D:lockinfo xmlns:D="DAV:"><D:lockscope><D:exclusive/></D:lockscope>
+            <D:locktype><D:write/></D:locktype><D:owner>someone</D:owner></D:lockinfo>"#;
+        let (response, body) = share.request("LOCK", "/files/locked.txt", &[], lockinfo, lock);
+        assert_eq!(response.status, HTTPStatus::Created);
+        assert!(share.root.join("locked.txt").is_file());
+        assert!(body.contains("<D:owner>someone</D:owner>"));
+        let token = header(&response, "Lock-Token").unwrap();
+
+        let (response, _) = share.request("LOCK", "/files/locked.txt", &[], lockinfo, lock);
+        assert_eq!(response.status, HTTPStatus::Locked);
+        let path = share.root.join("locked.txt");
+        let (response, _) = share.request("PROPPATCH", "/files/locked.txt", &[], "<propertyupdate xmlns=\"DAV:\"/>", proppatch);
+        assert_eq!(response.status, HTTPStatus::Locked);
+        let refresh_if = format!("({token})");
+        let (response, _) = share.request("LOCK", "/files/locked.txt", &[("If", &refresh_if), ("Timeout", "Second-60")], "", lock);
+        assert_eq!(response.status, HTTPStatus::Ok);
+        assert_eq!(locks::locks_on(&fs::canonicalize(&path).unwrap(), false).len(), 1);
+
+        let (response, _) = share.request("UNLOCK", "/files/locked.txt", &[("Lock-Token", "<opaquelocktoken:wrong>")], "", unlock);
+        assert_eq!(response.status, HTTPStatus::Conflict);
+        let (response, _) = share.request("UNLOCK", "/files/locked.txt", &[("Lock-Token", &token)], "", unlock);
+        assert_eq!(response.status, HTTPStatus::NoContent);
+        let (response, _) = share.request("PROPPATCH", "/files/locked.txt", &[], "<propertyupdate xmlns=\"DAV:\"/>", proppatch);
+        assert_eq!(response.status, HTTPStatus::MultiStatus);
+    }
+
+    #[test]
+    fn lock_rejects_malformed_lockinfo() {
+        let share = Share::new();
+        let shared_read = r#"<D:lockinfo xmlns:D="DAV:"><D:lockscope><D:shared/></D:lockscope><D:locktype><D:read/></D:locktype></D:lockinfo>"#;
+        assert_eq!(share.request("LOCK", "/files/x", &[], shared_read, lock).0.status, HTTPStatus::BadRequest);
+        assert_eq!(share.request("LOCK", "/files/x", &[], "<D:propfind xmlns:D=\"DAV:\"/>", lock).0.status, HTTPStatus::BadRequest);
+        assert_eq!(share.request("LOCK", "/files/x", &[], "", lock).0.status, HTTPStatus::PreconditionFailed);
+    }
+
+    #[test]
+    fn copy_duplicates_trees_and_honours_overwrite() {
+        let share = Share::new();
+        share.root.file("src/a.txt", b"a");
+        share.root.file("src/nested/b.txt", b"b");
+        share.root.file("existing/old.txt", b"old");
+
+        let (response, _) = share.request("COPY", "/files/src", &[("Destination", "http://localhost/files/copy")], "", copy);
+        assert_eq!(response.status, HTTPStatus::Created);
+        assert_eq!(fs::read_to_string(share.root.join("copy/nested/b.txt")).unwrap(), "b");
+        assert!(share.root.join("src/a.txt").is_file());
+
+        let (response, _) = share.request("COPY", "/files/src", &[("Destination", "/files/existing"), ("Overwrite", "F")], "", copy);
+        assert_eq!(response.status, HTTPStatus::PreconditionFailed);
+        let (response, _) = share.request("COPY", "/files/src", &[("Destination", "/files/existing"), ("Depth", "0")], "", copy);
+        assert_eq!(response.status, HTTPStatus::NoContent);
+        assert_eq!(fs::read_dir(share.root.join("existing")).unwrap().count(), 0);
+
+        let refused = [
+            ("/files/src/inside", HTTPStatus::Forbidden),
+            ("/files/missing/parent", HTTPStatus::Conflict),
+            ("/files/../escape", HTTPStatus::Forbidden),
+            ("http://elsewhere/files/x", HTTPStatus::BadGateway),
+        ];
+        for (destination, status) in refused {
+            assert_eq!(share.request("COPY", "/files/src", &[("Destination", destination)], "", copy).0.status, status, "{destination}");
+        }
+        assert_eq!(share.request("COPY", "/files/src", &[], "", copy).0.status, HTTPStatus::BadRequest);
+    }
+
+    #[test]
+    fn move_renames_and_keeps_dead_properties() {
+        let share = Share::new();
+        share.root.file("from.txt", b"content");
+        let set = r#"<D:propertyupdate xmlns:D="DAV:"><D:set><D:prop><tag xmlns="urn:t">kept</tag></D:prop></D:set></D:propertyupdate>"#;
+        share.request("PROPPATCH", "/files/from.txt", &[], set, proppatch);
+
+        assert_eq!(share.request("MOVE", "/files/from.txt", &[("Destination", "/files/to.txt"), ("Depth", "0")], "", move_resource).0.status,
+                   HTTPStatus::BadRequest);
+        let (response, _) = share.request("MOVE", "/files/from.txt", &[("Destination", "/files/to.txt")], "", move_resource);
+        assert_eq!(response.status, HTTPStatus::Created);
+        assert!(!share.root.join("from.txt").exists());
+        assert_eq!(fs::read_to_string(share.root.join("to.txt")).unwrap(), "content");
+
+        let find = r#"<propfind xmlns="DAV:"><prop><tag xmlns="urn:t"/></prop></propfind>"#;
+        let (_, body) = share.request("PROPFIND", "/files/to.txt", &[("Depth", "0")], find, propfind);
+        assert!(body.contains("kept"));
+        assert_eq!(share.request("MOVE", "/files/from.txt", &[("Destination", "/files/again.txt")], "", move_resource).0.status,
+                   HTTPStatus::NotFound);
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::webdav::xml::Element;
+
+/// Dead properties set with `PROPPATCH`, keyed by resource. They are kept in memory only.
+static DEAD_PROPERTIES: Mutex<Option<HashMap<PathBuf, Vec<Element>>>> = Mutex::new(None);
+
+fn with_properties<T>(action: impl FnOnce(&mut HashMap<PathBuf, Vec<Element>>) -> T) -> T {
+    let mut properties = DEAD_PROPERTIES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    action(properties.get_or_insert_with(HashMap::new))
+}
+
+pub fn get_all(path: &Path) -> Vec<Element> {
+    with_properties(|properties| properties.get(path).cloned().unwrap_or_default())
+}
+
+pub fn get(path: &Path, namespace: &str, name: &str) -> Option<Element> {
+    with_properties(|properties| {
+        properties.get(path)?.iter().find(|property| property.is(namespace, name)).cloned()
+    })
+}
+
+pub fn set(path: &Path, property: Element) {
+    with_properties(|properties| {
+        let stored = properties.entry(path.to_path_buf()).or_default();
+        stored.retain(|existing| !existing.is(&property.namespace, &property.name));
+        stored.push(property);
+    });
+}
+
+pub fn remove(path: &Path, namespace: &str, name: &str) {
+    with_properties(|properties| {
+        if let Some(stored) = properties.get_mut(path) {
+            stored.retain(|existing| !existing.is(namespace, name));
+        }
+    });
+}
+
+/// Drops the properties of `path` and everything below it.
+pub fn forget(path: &Path) {
+    with_properties(|properties| properties.retain(|resource, _| !resource.starts_with(path)));
+}
+
+/// Copies the properties of `source` and everything below it over to `destination`.
+pub fn copy(source: &Path, destination: &Path) {
+    with_properties(|properties| {
+        let copied = properties.iter()
+            .filter_map(|(resource, stored)| {
+                let relative = resource.strip_prefix(source).ok()?;
+                let copy = match relative.as_os_str().is_empty() {
+                    true => destination.to_path_buf(),
+                    false => destination.join(relative)
+                };
+                Some((copy, stored.clone()))
+            })
+            .collect::<Vec<_>>();
+        properties.extend(copied);
+    });
+}
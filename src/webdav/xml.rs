@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Namespace of the WebDAV vocabulary.
+pub const DAV_NAMESPACE: &str = "DAV:";
+
+/// Deepest nesting of elements the parser accepts, so hostile input can't exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug)]
+pub struct XmlError {
+    pub position: usize,
+    pub message: &'static str,
+}
+
+impl Display for XmlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+/// An element with its namespace resolved. Attributes other than namespace declarations
+/// are not needed by WebDAV bodies and are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    pub namespace: String,
+    pub name: String,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    pub fn new(namespace: &str, name: &str) -> Self {
+        Self { namespace: namespace.to_string(), name: name.to_string(), children: Vec::new() }
+    }
+
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace == namespace && self.name == name
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item=&Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None
+        })
+    }
+
+    pub fn child(&self, namespace: &str, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.is(namespace, name))
+    }
+
+    /// Serializes the element, declaring its namespace as the default one wherever it changes.
+    pub fn write_xml(&self, out: &mut String, parent_namespace: Option<&str>) {
+        out.push('<');
+        out.push_str(&self.name);
+        if parent_namespace != Some(self.namespace.as_str()) {
+            out.push_str(&format!(" xmlns=\"{}\"", escape(&self.namespace)));
+        }
+        if self.children.is_empty() {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        for node in &self.children {
+            match node {
+                Node::Text(text) => out.push_str(&escape(text)),
+                Node::Element(element) => element.write_xml(out, Some(&self.namespace))
+            }
+        }
+        out.push_str(&format!("</{}>", self.name));
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// A small non-validating parser for the XML bodies WebDAV clients send: elements,
+/// namespaces, text, entities and CDATA. DTDs are rejected.
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+    scopes: Vec<HashMap<String, String>>,
+}
+
+pub fn parse(input: &str) -> Result<Element, XmlError> {
+    let mut parser = Parser { input, position: 0, scopes: Vec::new() };
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    parser.skip_misc()?;
+    if parser.position < input.len() {
+        return Err(parser.error("Unexpected content after the root element"));
+    }
+    Ok(root)
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> XmlError {
+        XmlError { position: self.position, message }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, terminator: &str) -> Result<(), XmlError> {
+        match self.rest().find(terminator) {
+            None => Err(self.error("Unterminated markup")),
+            Some(index) => {
+                self.position += index + terminator.len();
+                Ok(())
+            }
+        }
+    }
+
+    /// Skips whitespace, comments and processing instructions between top level markup.
+    fn skip_misc(&mut self) -> Result<(), XmlError> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if self.rest().starts_with("<!") {
+                return Err(self.error("Document type declarations are not supported"));
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, XmlError> {
+        let rest = self.rest();
+        let length = rest.find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '=')).unwrap_or(rest.len());
+        if length == 0 {
+            return Err(self.error("Expected a name"));
+        }
+        self.position += length;
+        Ok(&rest[..length])
+    }
+
+    fn resolve(&self, prefix: &str) -> Option<String> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(prefix).cloned())
+    }
+
+    fn element(&mut self) -> Result<Element, XmlError> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("Expected an element"));
+        }
+        // Every open element has pushed its namespace scope
+        if self.scopes.len() >= MAX_DEPTH {
+            return Err(self.error("Nesting too deep"));
+        }
+        self.position += 1;
+        let qualified_name = self.name()?;
+
+        let mut scope = HashMap::new();
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") || self.rest().starts_with('>') {
+                break;
+            }
+            let attribute = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("Expected `=` after attribute name"));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => quote,
+                _ => return Err(self.error("Expected a quoted attribute value"))
+            };
+            self.position += 1;
+            let end = self.rest().find(quote).ok_or_else(|| self.error("Unterminated attribute value"))?;
+            let value = decode_entities(&self.rest()[..end]).ok_or_else(|| self.error("Invalid entity reference"))?;
+            self.position += end + 1;
+
+            if attribute == "xmlns" {
+                scope.insert(String::new(), value);
+            } else if let Some(prefix) = attribute.strip_prefix("xmlns:") {
+                scope.insert(prefix.to_string(), value);
+            }
+        }
+        self.scopes.push(scope);
+
+        let (prefix, name) = qualified_name.split_once(':').unwrap_or(("", qualified_name));
+        let namespace = match self.resolve(prefix) {
+            Some(namespace) => namespace,
+            None if prefix.is_empty() => String::new(),
+            None => return Err(self.error("Undeclared namespace prefix"))
+        };
+        let mut element = Element::new(&namespace, name);
+
+        if self.rest().starts_with("/>") {
+            self.position += 2;
+            self.scopes.pop();
+            return Ok(element);
+        }
+        self.position += 1;
+
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error("Unterminated element"));
+            } else if let Some(closing) = rest.strip_prefix("</") {
+                let end = closing.find('>').ok_or_else(|| self.error("Unterminated closing tag"))?;
+                if closing[..end].trim() != qualified_name {
+                    return Err(self.error("Mismatched closing tag"));
+                }
+                self.position += 2 + end + 1;
+                break;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = cdata.find("]]>").ok_or_else(|| self.error("Unterminated CDATA section"))?;
+                element.children.push(Node::Text(cdata[..end].to_string()));
+                self.position += "<![CDATA[".len() + end + "]]>".len();
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with('<') {
+                element.children.push(Node::Element(self.element()?));
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = decode_entities(&rest[..end]).ok_or_else(|| self.error("Invalid entity reference"))?;
+                if !text.trim().is_empty() {
+                    element.children.push(Node::Text(text));
+                }
+                self.position += end;
+            }
+        }
+
+        self.scopes.pop();
+        Ok(element)
+    }
+}
+
+fn decode_entities(text: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let end = rest[start..].find(';')? + start;
+        let entity = &rest[start + 1..end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => entity.strip_prefix('#')?.parse().ok()?
+                };
+                char::from_u32(code)?
+            }
+        };
+        decoded.push(c);
+        rest = &rest[end + 1..];
+    }
+    decoded.push_str(rest);
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn error(input: &str) -> &'static str {
+        parse(input).unwrap_err().message
+    }
+
+    #[test]
+    fn resolves_namespaces() {
+        let root = parse(r#"<?xml version="1.0"?>
+            <D:propfind xmlns:D="DAV:" xmlns="urn:default">
+                <D:prop><custom/><x:other xmlns:x="urn:x"/><D:getetag/></D:prop>
+            </D:propfind>"#).unwrap();
+        assert!(root.is(DAV_NAMESPACE, "propfind"));
+        let names = root.child(DAV_NAMESPACE, "prop").unwrap().elements()
+            .map(|element| (element.namespace.as_str(), element.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, [("urn:default", "custom"), ("urn:x", "other"), (DAV_NAMESPACE, "getetag")]);
+    }
+
+    #[test]
+    fn scopes_namespace_declarations_to_their_element() {
+        let root = parse(r#"<a xmlns="urn:a"><b xmlns="urn:b"/><c/></a>"#).unwrap();
+        let namespaces = root.elements().map(|element| element.namespace.as_str()).collect::<Vec<_>>();
+        assert_eq!(namespaces, ["urn:b", "urn:a"]);
+        assert_eq!(error(r#"<a><x:b xmlns:x="urn:x"/><x:c/></a>"#), "Undeclared namespace prefix");
+        assert_eq!(parse("<plain/>").unwrap(), Element::new("", "plain"));
+    }
+
+    #[test]
+    fn decodes_text_entities_and_cdata() {
+        let root = parse("<a>x &lt;&#65;&#x42;&amp;<!-- skipped --><![CDATA[<raw>]]></a>").unwrap();
+        assert_eq!(root.children, [Node::Text("x <AB&".to_string()), Node::Text("<raw>".to_string())]);
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested = |depth: usize| format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(error(&nested(MAX_DEPTH + 1)), "Nesting too deep");
+        assert_eq!(error(&"<a>".repeat(100_000)), "Nesting too deep");
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert_eq!(error(""), "Expected an element");
+        assert_eq!(error("text"), "Expected an element");
+        assert_eq!(error("<a>"), "Unterminated element");
+        assert_eq!(error("<a></b>"), "Mismatched closing tag");
+        assert_eq!(error("<a/><b/>"), "Unexpected content after the root element");
+        assert_eq!(error("<a b/>"), "Expected `=` after attribute name");
+        assert_eq!(error("<a b=c/>"), "Expected a quoted attribute value");
+        assert_eq!(error("<a b=\"c/>"), "Unterminated attribute value");
+        assert_eq!(error("<a>&bogus;</a>"), "Invalid entity reference");
+        assert_eq!(error("<a><![CDATA[x</a>"), "Unterminated CDATA section");
+        assert_eq!(error("<!DOCTYPE a [<!ENTITY x \"y\">]><a/>"), "Document type declarations are not supported");
+        assert_eq!(error("<!-- open <a/>"), "Unterminated markup");
+    }
+
+    #[test]
+    fn writes_namespaces_where_they_change() {
+        let mut root = Element::new("urn:a", "a");
+        root.children.push(Node::Element(Element::new("urn:a", "b")));
+        root.children.push(Node::Element(Element::new("urn:c", "c")));
+        root.children.push(Node::Text("<&>".to_string()));
+        let mut out = String::new();
+        root.write_xml(&mut out, None);
+        assert_eq!(out, r#"<a xmlns="urn:a"><b/><c xmlns="urn:c"/>&lt;&amp;&gt;</a>"#);
+        assert_eq!(parse(&out).unwrap(), root);
+    }
+}